@@ -5,7 +5,7 @@ use std::{env, fs::File, collections::HashMap, sync::Arc};
 use mysql_async::{prelude::*,Conn};
 use serde_json::Value;
 use dashmap::*;
-use crate::{error::*, element::Element, database_table::DatabaseTable, db_operation_cache::{DbOperationCacheValue, DbOperationCache}, query_triples::{QueryTriples, DatabaseQueryResult}, database_wrapper::DatabaseWrapper, app_state_mysql_stdout::AppStateStdoutMySQL};
+use crate::{error::*, checkpoint::Checkpoint, element::Element, database_table::DatabaseTable, db_operation_cache::{DbOperationCacheValue, DbOperationCache}, query_triples::{QueryTriples, DatabaseQueryResult}, database_wrapper::DatabaseWrapper, app_state_mysql_stdout::AppStateStdoutMySQL};
 use crate::app_state_mysql_live::AppStateLiveMySQL;
 
 pub const MYSQL_CREATE_TEXTS_TABLE: &str = r#"CREATE TABLE IF NOT EXISTS `texts` (
@@ -20,12 +20,21 @@ pub const MYSQL_CREATE_TABLE_LIST_TABLE: &str = r#"CREATE TABLE IF NOT EXISTS `t
     `json` MEDIUMTEXT NOT NULL,
     PRIMARY KEY (`id`)
 ) ENGINE=InnoDB"# ;
+pub const MYSQL_CREATE_CHECKPOINTS_TABLE: &str = r#"CREATE TABLE IF NOT EXISTS `checkpoints` (
+    `input` VARCHAR(255) NOT NULL,
+    `line` BIGINT UNSIGNED NOT NULL,
+    `offset` BIGINT UNSIGNED NOT NULL,
+    `timestamp` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (`input`)
+) ENGINE=InnoDB"# ;
 
 #[async_trait]
 pub trait AppDB {
     async fn init_from_db(&self, app: &AppState) -> Result<(),WDQSErr> ;
     async fn db_conn(&self) -> Result<Conn, mysql_async::Error> ;
     async fn add_to_table_list(&self, table: &DatabaseTable) -> Result<(),WDQSErr> ;
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(),WDQSErr> ;
+    async fn get_checkpoint(&self, input: &str) -> Result<Option<Checkpoint>,WDQSErr> ;
     async fn prepare_text(&self, text_chunk: &[String]) -> Result<(),WDQSErr> ;
     async fn force_flush(&self, command: &str, value_chunk: &[Vec<DbOperationCacheValue>]) -> Result<Vec<(String, Vec<String>)>,WDQSErr> ;
     async fn run_query(&self, app: &AppState, query: &QueryTriples) -> Result<HashMap<String,DatabaseQueryResult>,WDQSErr> ;
@@ -40,9 +49,10 @@ impl std::fmt::Debug for dyn AppDB {
 pub struct AppState {
     pub db_interface: Arc<Box<dyn AppDB + Sync + Send>>,
     pub tables: Arc<DashMap<String,DatabaseTable>>,
-    // pub parallel_parsing: usize,
+    pub parallel_parsing: usize, // Parser workers for imports
     pub insert_batch_size: usize,
     pub insert_chunk_size: usize,
+    pub checkpoint_interval: u64, // Lines between import checkpoints; 0 for none
     prefixes: HashMap<String,String>,
 }
 
@@ -85,9 +95,10 @@ impl AppState {
         let ret = Self {
             db_interface: Arc::new(db_interface),
             tables: Arc::new(DashMap::new()),
-            // parallel_parsing: config["parallel_parsing"].as_u64().unwrap_or(100) as usize,
+            parallel_parsing: config["parallel_parsing"].as_u64().map(|workers|workers as usize).unwrap_or_else(||std::thread::available_parallelism().map(|n|n.get()).unwrap_or(4)),
             insert_batch_size: config["insert_batch_size"].as_u64().unwrap_or(100) as usize,
             insert_chunk_size: config["insert_chunk_size"].as_u64().unwrap_or(100) as usize,
+            checkpoint_interval: config["checkpoint_interval"].as_u64().unwrap_or(1000000),
             prefixes,
         };
         ret
@@ -109,8 +120,8 @@ impl AppState {
         self.db_interface.init_from_db(&self).await
     }
 
-    pub async fn table(&self, s: &Element, p: &Element, o: &Element) -> Result<DatabaseTable,WDQSErr> {
-        let table = DatabaseTable::new(s,p,o);
+    pub async fn table(&self, s: &Element, p: &Element, o: &Element, graph: Option<&str>) -> Result<DatabaseTable,WDQSErr> {
+        let table = DatabaseTable::new(s,p,o,graph);
         if self.tables.contains_key(&table.name) {
             return Ok(table);
         }
@@ -126,6 +137,15 @@ impl AppState {
         self.db_interface.prepare_text(text_chunk).await
     }
 
+    pub async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(),WDQSErr> {
+        self.db_interface.set_checkpoint(checkpoint).await
+    }
+
+    /// The last checkpoint of an import input, if any
+    pub async fn get_checkpoint(&self, input: &str) -> Result<Option<Checkpoint>,WDQSErr> {
+        self.db_interface.get_checkpoint(input).await
+    }
+
     pub async fn force_flush(&self, command: &str, value_chunk: &[Vec<DbOperationCacheValue>]) -> Result<Vec<(String, Vec<String>)>,WDQSErr> {
         self.db_interface.force_flush(command, value_chunk).await
     }
@@ -156,8 +176,87 @@ impl AppState {
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
+    use std::sync::{Mutex, atomic::{AtomicUsize, Ordering}};
+    use crate::ntriples;
+
+    /// A database in memory, for imports; insert number `failing_flush` fails
+    #[derive(Debug, Clone, Default)]
+    pub struct MemoryDB {
+        pub flushes: Arc<AtomicUsize>, // Inserts so far
+        pub failing_flush: Arc<AtomicUsize>, // Counting from 1; 0 for none
+        pub rows: Arc<Mutex<Vec<String>>>, // "table|values"
+        pub checkpoints: Arc<Mutex<HashMap<String,Checkpoint>>>,
+    }
+
+    #[async_trait]
+    impl AppDB for MemoryDB {
+        async fn init_from_db(&self, _app: &AppState) -> Result<(),WDQSErr> {
+            Ok(())
+        }
+
+        async fn db_conn(&self) -> Result<Conn, mysql_async::Error> {
+            Err(mysql_async::Error::Other(Box::new(WDQSErr::String("MemoryDB::db_conn".into()))))
+        }
+
+        async fn add_to_table_list(&self, _table: &DatabaseTable) -> Result<(),WDQSErr> {
+            Ok(())
+        }
+
+        async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(),WDQSErr> {
+            self.checkpoints.lock().unwrap().insert(checkpoint.input.to_owned(),checkpoint.to_owned());
+            Ok(())
+        }
+
+        async fn get_checkpoint(&self, input: &str) -> Result<Option<Checkpoint>,WDQSErr> {
+            Ok(self.checkpoints.lock().unwrap().get(input).cloned())
+        }
+
+        async fn prepare_text(&self, _text_chunk: &[String]) -> Result<(),WDQSErr> {
+            Ok(())
+        }
+
+        async fn force_flush(&self, command: &str, value_chunk: &[Vec<DbOperationCacheValue>]) -> Result<Vec<(String, Vec<String>)>,WDQSErr> {
+            if self.flushes.fetch_add(1, Ordering::SeqCst)+1==self.failing_flush.load(Ordering::SeqCst) {
+                return Err("MemoryDB::force_flush: Insert failed".into());
+            }
+            let table = command.split('`').nth(1).unwrap_or_default();
+            let mut rows = self.rows.lock().unwrap();
+            for values in value_chunk {
+                let values: Vec<String> = values.iter().map(|v|v.as_sql_stdout()).collect();
+                rows.push(format!("{table}|{}",values.join(",")));
+            }
+            Ok(vec![])
+        }
+
+        async fn run_query(&self, _app: &AppState, _query: &QueryTriples) -> Result<HashMap<String,DatabaseQueryResult>,WDQSErr> {
+            Err("MemoryDB::run_query: Not supported".into())
+        }
+    }
+
+    /// An AppState from config.json that imports into `db`
+    pub fn app_with_db(db: &MemoryDB) -> AppState {
+        let mut config = AppState::get_config_from_file("config.json").unwrap();
+        config["db_type"] = "mysql_stdout".into();
+        let mut app = AppState::from_config(&config);
+        app.db_interface = Arc::new(Box::new(db.to_owned()));
+        app
+    }
+
+    /// An AppState from config.json without database, that knows the tables for the given N-Quads
+    pub fn app_with_tables(quads: &[&str]) -> AppState {
+        let mut config = AppState::get_config_from_file("config.json").unwrap();
+        config["db_type"] = "mysql_stdout".into();
+        let app = AppState::from_config(&config);
+        for quad in quads {
+            let (s,p,o,graph) = ntriples::parse_quad_line(quad).unwrap().unwrap();
+            let graph = graph.map(|graph|graph.to_graph_name().unwrap());
+            let table = DatabaseTable::new(&s.to_element().unwrap(), &p.to_element().unwrap(), &o.to_element().unwrap(), graph.as_deref());
+            app.tables.insert(table.name.to_owned(),table);
+        }
+        app
+    }
 
     #[test]
     fn test_replace_prefix() {
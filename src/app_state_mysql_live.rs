@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use std::{time::Duration, collections::HashMap};
 use mysql_async::{prelude::*,Conn,Opts,OptsBuilder,PoolConstraints,PoolOpts};
 use serde_json::{Value, json};
-use crate::{error::*, database_table::DatabaseTable, db_operation_cache::DbOperationCacheValue, query_triples::{QueryTriples, DatabaseQueryResult}, app_state::*};
+use crate::{error::*, checkpoint::Checkpoint, database_table::DatabaseTable, db_operation_cache::DbOperationCacheValue, query_triples::{QueryTriples, DatabaseQueryResult}, app_state::*};
 
 #[derive(Debug, Clone)]
 pub struct AppStateLiveMySQL {
@@ -38,6 +38,7 @@ impl AppDB for AppStateLiveMySQL {
         let mut conn = self.db_conn().await?;
         conn.exec_drop(MYSQL_CREATE_TEXTS_TABLE, ()).await?;
         conn.exec_drop(MYSQL_CREATE_TABLE_LIST_TABLE, ()).await?;
+        conn.exec_drop(MYSQL_CREATE_CHECKPOINTS_TABLE, ()).await?;
         let sql = r#"SELECT `name`,`json` FROM `table_list`"# ;
         let results = conn
             .exec_iter(sql, ()).await?
@@ -65,6 +66,19 @@ impl AppDB for AppStateLiveMySQL {
         Ok(())
     }
 
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(),WDQSErr> {
+        let sql = "REPLACE INTO `checkpoints` (`input`,`line`,`offset`) VALUES(:input,:line,:offset)";
+        let (input,line,offset) = (checkpoint.input.to_owned(),checkpoint.line,checkpoint.offset);
+        self.db_conn().await?.exec_drop(sql, params!{input,line,offset}).await?;
+        Ok(())
+    }
+
+    async fn get_checkpoint(&self, input: &str) -> Result<Option<Checkpoint>,WDQSErr> {
+        let sql = "SELECT `line`,`offset` FROM `checkpoints` WHERE `input`=:input";
+        let row: Option<(u64,u64)> = self.db_conn().await?.exec_first(sql, params!{input}).await?;
+        Ok(row.map(|(line,offset)|Checkpoint { input: input.to_string(), line, offset }))
+    }
+
     async fn prepare_text(&self, text_chunk: &[String]) -> Result<(),WDQSErr> {
         let question_marks = vec!["(?)"; text_chunk.len()].join(",");
         let sql = format!("INSERT IGNORE INTO `texts` (`value`) VALUES {question_marks}");
@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use std::collections::HashMap;
 use mysql_async::Conn;
 use serde_json::{Value, json};
-use crate::{error::*, database_table::DatabaseTable, db_operation_cache::DbOperationCacheValue, query_triples::{QueryTriples, DatabaseQueryResult}, app_state::*};
+use crate::{error::*, checkpoint::Checkpoint, database_table::DatabaseTable, db_operation_cache::DbOperationCacheValue, query_triples::{QueryTriples, DatabaseQueryResult}, app_state::*};
 
 #[derive(Debug, Clone)]
 pub struct AppStateStdoutMySQL {
@@ -27,6 +27,7 @@ impl AppDB for AppStateStdoutMySQL {
     async fn init_from_db(&self, _app: &AppState) -> Result<(),WDQSErr> {
         println!("{MYSQL_CREATE_TEXTS_TABLE};");
         println!("{MYSQL_CREATE_TABLE_LIST_TABLE};");
+        println!("{MYSQL_CREATE_CHECKPOINTS_TABLE};");
         Ok(())
     }
 
@@ -45,6 +46,17 @@ impl AppDB for AppStateStdoutMySQL {
         Ok(())
     }
 
+    async fn set_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(),WDQSErr> {
+        let input = Self::sql_escape(&checkpoint.input);
+        let sql = format!("REPLACE INTO `checkpoints` (`input`,`line`,`offset`) VALUES(\"{input}\",{},{})",checkpoint.line,checkpoint.offset);
+        println!("{sql};");
+        Ok(())
+    }
+
+    async fn get_checkpoint(&self, _input: &str) -> Result<Option<Checkpoint>,WDQSErr> {
+        Err("AppStateStdoutMySQL::get_checkpoint: Checkpoints can only be read from a database".into())
+    }
+
     async fn prepare_text(&self, text_chunk: &[String]) -> Result<(),WDQSErr> {
         let values = self.sql_group_escape(text_chunk);
         let sql = format!("INSERT IGNORE INTO `texts` (`value`) VALUES {values}");
@@ -0,0 +1,134 @@
+use std::io::{self, BufRead};
+use crate::error::WDQSErr;
+
+/// The position in an import input up to which all triples have been written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checkpoint {
+    pub input: String,
+    pub line: u64,
+    pub offset: u64, // Bytes of the uncompressed input
+}
+
+/// A line of an import input, with its number and the byte offset of its start
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputLine {
+    pub number: u64,
+    pub offset: u64,
+    pub text: String,
+}
+
+/// The lines of an import input, keeping track of the line number and byte offset after the last line
+pub struct InputLines<T: BufRead> {
+    reader: T,
+    input: String,
+    line: u64,
+    offset: u64,
+}
+
+impl<T: BufRead> InputLines<T> {
+    pub fn new(reader: T, input: &str) -> Self {
+        Self {
+            reader,
+            input: input.to_string(),
+            line: 0,
+            offset: 0,
+        }
+    }
+
+    /// Continues counting from a checkpoint, for a reader that was positioned at its offset
+    pub fn continue_from(mut self, checkpoint: &Checkpoint) -> Self {
+        self.line = checkpoint.line;
+        self.offset = checkpoint.offset;
+        self
+    }
+
+    /// Reads past all lines before the checkpoint
+    pub fn skip_to(&mut self, checkpoint: &Checkpoint) -> Result<(),WDQSErr> {
+        let mut buffer = vec![];
+        while self.line<checkpoint.line {
+            buffer.clear();
+            if self.read_line(&mut buffer)?==0 {
+                return Err(WDQSErr::String(format!("InputLines::skip_to: '{}' ends before line {}",self.input,checkpoint.line)));
+            }
+        }
+        if self.offset!=checkpoint.offset {
+            return Err(WDQSErr::String(format!("InputLines::skip_to: Line {} of '{}' is at byte {}, not {}",self.line,self.input,self.offset,checkpoint.offset)));
+        }
+        Ok(())
+    }
+
+    fn read_line(&mut self, buffer: &mut Vec<u8>) -> io::Result<usize> {
+        let bytes = self.reader.read_until(b'\n', buffer)?;
+        if bytes>0 {
+            self.line += 1;
+            self.offset += bytes as u64;
+        }
+        Ok(bytes)
+    }
+
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// A checkpoint at the position after the last line read
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            input: self.input.to_owned(),
+            line: self.line,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<T: BufRead> Iterator for InputLines<T> {
+    type Item = Result<String,WDQSErr>;
+
+    /// The next line, without the line break
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buffer = vec![];
+        match self.read_line(&mut buffer) {
+            Ok(0) => None,
+            Ok(_) => {
+                if buffer.ends_with(b"\n") {
+                    buffer.pop();
+                    if buffer.ends_with(b"\r") {
+                        buffer.pop();
+                    }
+                }
+                Some(String::from_utf8(buffer).map_err(|e|e.into()))
+            }
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_lines() {
+        let input = "a\r\nb\n\n\u{e9}\nlast";
+        let mut lines = InputLines::new(input.as_bytes(), "test");
+        assert_eq!(lines.next().unwrap().unwrap(),"a");
+        assert_eq!(lines.next().unwrap().unwrap(),"b");
+        let checkpoint = lines.checkpoint();
+        assert_eq!((checkpoint.line,checkpoint.offset),(2,5));
+        assert_eq!(lines.by_ref().map(|line|line.unwrap()).collect::<Vec<_>>(),vec!["","\u{e9}","last"]);
+        assert_eq!((lines.line(),lines.offset()),(5,input.len() as u64));
+
+        let mut lines = InputLines::new(input.as_bytes(), "test");
+        lines.skip_to(&checkpoint).unwrap();
+        assert_eq!(lines.next().unwrap().unwrap(),"");
+        let mut lines = InputLines::new(&input.as_bytes()[5..], "test").continue_from(&checkpoint);
+        assert_eq!(lines.next().unwrap().unwrap(),"");
+        assert_eq!(lines.checkpoint().offset,6);
+        let mut lines = InputLines::new("a\n".as_bytes(), "test");
+        assert!(lines.skip_to(&checkpoint).is_err());
+        assert!(matches!(InputLines::new(&[0xFF,b'\n'][..], "test").next(),Some(Err(WDQSErr::FromUtf8(_)))));
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Serialize, Deserialize};
+use sha1::{Digest, Sha1};
 use crate::{element::Element, type_part::TypePart, db_operation_cache::DbOperationCacheValue};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -9,14 +10,19 @@ pub struct DatabaseTable {
     names: (String,String,String),
     property: String,
     values: Vec<DbOperationCacheValue>,
+    #[serde(default)]
+    graph: Option<String>, // Named graph; None is the default graph
 }
 
 impl DatabaseTable {
-    pub fn new(s: &Element, p: &Element, o: &Element) -> Self {
+    pub fn new(s: &Element, p: &Element, o: &Element, graph: Option<&str>) -> Self {
         let subject_label = s.get_table_name();
         let prop_label = p.get_table_name();
         let object_label = o.get_table_name();
-        let name = format!("data__{prop_label}__{subject_label}__{object_label}");
+        let mut name = format!("data__{prop_label}__{subject_label}__{object_label}");
+        if let Some(graph) = graph { // Graph IRIs can be long, so each graph gets a short hash as namespace
+            name = format!("{name}__g{}",&format!("{:x}",Sha1::digest(graph.as_bytes()))[..10]);
+        }
         if name.len()>64 { // Paranoia
             eprintln!("ATTENTION: DatabaseTable::new: Table name `{name}` has more than 64 characters, not valid in MySQL");
         }
@@ -27,9 +33,14 @@ impl DatabaseTable {
             names: (s.name().to_string(),p.name().to_string(),o.name().to_string()),
             property: prop_label,
             values: p.values(),
+            graph: graph.map(|graph|graph.to_string()),
         }
     }
 
+    pub fn graph(&self) -> Option<&str> {
+        self.graph.as_deref()
+    }
+
     pub fn property(&self) -> &str {
         &self.property
     }
@@ -1,57 +1,77 @@
-use std::{sync::Arc, collections::HashMap};
+use std::{sync::Arc, collections::{HashMap, hash_map::DefaultHasher}, hash::{Hash, Hasher}};
 use futures::future::join_all;
 use tokio::sync::Mutex;
 use crate::{error::*, element::Element, db_operation_cache::DbOperationCache, app_state::AppState};
 
+const INSERT_CACHE_SHARDS: usize = 64;
+
+type InsertCacheShard = Mutex<HashMap<String,DbOperationCache>>;
 
 #[derive(Debug, Clone)]
 pub struct DatabaseWrapper {
     app: Arc<AppState>,
-    insert_cache: Arc<Mutex<HashMap<String,DbOperationCache>>>,
+    insert_caches: Arc<Vec<InsertCacheShard>>, // Per-table caches, sharded by table name so that tables do not wait for each other
 }
 
 impl DatabaseWrapper {
     pub fn new(app: Arc<AppState>) -> Self {
         Self {
             app,
-            insert_cache: Arc::new(Mutex::new(HashMap::new())),
+            insert_caches: Arc::new((0..INSERT_CACHE_SHARDS).map(|_|Mutex::new(HashMap::new())).collect()),
         }
     }
 
+    fn insert_cache_shard(&self, table_name: &str) -> &InsertCacheShard {
+        let mut hasher = DefaultHasher::new();
+        table_name.hash(&mut hasher);
+        &self.insert_caches[hasher.finish() as usize % self.insert_caches.len()]
+    }
+
     pub async fn add(&self, s: Element, p: &Element, o: Element) -> Result<(),WDQSErr> {
-        let table = self.app.table(&s,p,&o).await?;
+        self.add_to_graph(s, p, o, None).await
+    }
+
+    /// Adds a triple to a named graph, or with None, to the default graph
+    pub async fn add_to_graph(&self, s: Element, p: &Element, o: Element, graph: Option<&str>) -> Result<(),WDQSErr> {
+        let table = self.app.table(&s,p,&o,graph).await?;
         let mut values = s.values();
         values.append(&mut o.values());
 
-        let mut ic = self.insert_cache.lock().await;
-        match ic.get_mut(&table.name) {
-            Some(cache) => cache.add(values, &self.app).await,
-            None => {
-                ic.entry(table.name.to_owned())
-                    .or_insert(DbOperationCache::new(&s,&o,&table))
-                    .add(values, &self.app)
-                    .await
-            }
+        let full_cache = self.insert_cache_shard(&table.name)
+            .lock()
+            .await
+            .entry(table.name.to_owned())
+            .or_insert_with(||DbOperationCache::new(&s,&o,&table))
+            .add(values, &self.app)?;
+        // Written without holding the lock, so other tables in the shard can be added to meanwhile
+        match full_cache {
+            Some(mut cache) => cache.force_flush(&self.app).await,
+            None => Ok(()),
         }
     }
 
     pub async fn flush_insert_caches(&self) -> Result<(),WDQSErr> {
         let mut tasks = vec![];
-        for (_,mut cache) in self.insert_cache.lock().await.drain() {
-            let app = self.app.clone();
-            tasks.push(tokio::spawn(async move { cache.force_flush(&app).await }));
+        for shard in self.insert_caches.iter() {
+            for (_,mut cache) in shard.lock().await.drain() {
+                let app = self.app.clone();
+                tasks.push(tokio::spawn(async move { cache.force_flush(&app).await }));
+            }
         }
         Self::first_err(join_all(tasks).await, true)?;
         Ok(())
     }
 
     pub fn first_err(results: Vec<Result<Result<(), WDQSErr>, tokio::task::JoinError>>, exit: bool) -> Result<(),WDQSErr> {
-        let errors: Vec<_> = results
-            .iter()
-            .filter_map(|result|result.as_ref().ok()) // Remove Join errors to get to the WDQSErr
-            .filter(|result|result.is_err())
+        let errors: Vec<WDQSErr> = results
+            .into_iter()
+            .filter_map(|result|match result {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e),
+                Err(e) => Some(WDQSErr::String(format!("DatabaseWrapper: Task failed: {e}"))), // Like a panic during an insert
+            })
             .collect();
-        if let Some(Err(e)) = errors.get(0) {
+        if let Some(e) = errors.first() {
             if errors.len()>1 || !exit {
                 eprintln!("{errors:?}");
             }
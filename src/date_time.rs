@@ -8,6 +8,14 @@ lazy_static! {
 
 pub type YearType = i64;
 
+/// wikibase:timePrecision of a plain xsd:dateTime
+pub const PRECISION_SECOND: u8 = 14;
+pub const PRECISION_DAY: u8 = 11;
+/// Proleptic Gregorian calendar
+pub const GREGORIAN_Q: u32 = 1985727;
+/// Proleptic Julian calendar
+pub const JULIAN_Q: u32 = 1985786;
+
 #[derive(Clone, Debug)]
 pub struct DateTime {
     year: YearType,
@@ -16,6 +24,69 @@ pub struct DateTime {
     hour: u8,
     minute: u8,
     second: u8,
+    precision: u8, // wikibase:timePrecision, 0 (billion years) to 14 (second)
+    timezone: i16, // wikibase:timeTimezone, offset in minutes
+    calendar_q: u32, // wikibase:timeCalendarModel
+}
+
+impl DateTime {
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn timezone(&self) -> i16 {
+        self.timezone
+    }
+
+    pub fn calendar_q(&self) -> u32 {
+        self.calendar_q
+    }
+
+    pub fn set_precision(&mut self, precision: u8) {
+        self.precision = precision;
+    }
+
+    pub fn set_timezone(&mut self, timezone: i16) {
+        self.timezone = timezone;
+    }
+
+    pub fn set_calendar_q(&mut self, calendar_q: u32) {
+        self.calendar_q = calendar_q;
+    }
+
+    /// Orders like the time value; timezone and calendar model do not change the (UTC, Gregorian) value
+    pub fn sort_key(&self) -> (YearType,u8,u8,u8,u8,u8) {
+        (self.year,self.month,self.day,self.hour,self.minute,self.second)
+    }
+
+    /// Year as in xsd:dateTime, at least four digits
+    fn year_string(&self) -> String {
+        match self.year<0 {
+            true => format!("-{:04}",self.year.unsigned_abs()),
+            false => format!("{:04}",self.year),
+        }
+    }
+
+    /// Combines the columns into the value read by `from_sql_values`: "xsd:dateTime|precision|timezone|calendar"
+    pub fn sql_expression(columns: &[String]) -> String {
+        let pad = |column: &String| format!("LPAD({column},2,'0')");
+        let year = format!("CONCAT(IF({y}<0,'-',''),LPAD(ABS({y}),GREATEST(4,LENGTH(ABS({y}))),'0'))",y=columns[0]);
+        format!("CONCAT({year},'-',{},'-',{},'T',{},':',{},':',{},'Z|',{},'|',{},'|',{})",
+            pad(&columns[1]),pad(&columns[2]),pad(&columns[3]),pad(&columns[4]),pad(&columns[5]),columns[6],columns[7],columns[8])
+    }
+
+    /// The xsd:dateTime of an SQL value created by `sql_expression`
+    pub fn sql_str_expression(sql: &str) -> String {
+        format!("SUBSTRING_INDEX({sql},'|',1)")
+    }
+
+    /// A number that sorts an SQL value created by `sql_expression` like `sort_key`
+    pub fn sql_order_expression(sql: &str) -> String {
+        let s = Self::sql_str_expression(sql);
+        let year = format!("CAST(SUBSTRING_INDEX({s},'-',IF(LEFT({s},1)='-',2,1)) AS DECIMAL(30,0))");
+        let rest = format!("CAST(REPLACE(REPLACE(REPLACE(REPLACE(RIGHT({s},15),'-',''),'T',''),':',''),'Z','') AS DECIMAL(10,0))");
+        format!("({year}*10000000000+{rest})")
+    }
 }
 
 impl ElementType for DateTime {
@@ -28,25 +99,47 @@ impl ElementType for DateTime {
                 hour: caps.get(4)?.as_str().parse::<u8>().ok()?,
                 minute: caps.get(5)?.as_str().parse::<u8>().ok()?,
                 second: caps.get(6)?.as_str().parse::<u8>().ok()?,
+                precision: PRECISION_SECOND,
+                timezone: 0,
+                calendar_q: GREGORIAN_Q,
             }));
         }
         None
     }
 
-    fn from_sql_values(name:&str, _value: &Vec<String>) -> Option<Box<Self>> {
-        match name {
-            "DateTime" => todo!(),//DateTime::from_str(&value[0].parse::<String>().unwrap()),
-            _ => None,
+    /// Either the single value of `DateTime::sql_expression`, or the nine columns
+    fn from_sql_values(name:&str, value: &Vec<String>) -> Option<Box<Self>> {
+        if name!="DateTime" {
+            return None;
         }
+        if value.len()==9 {
+            return Some(Box::new(Self {
+                year: value[0].parse().ok()?,
+                month: value[1].parse().ok()?,
+                day: value[2].parse().ok()?,
+                hour: value[3].parse().ok()?,
+                minute: value[4].parse().ok()?,
+                second: value[5].parse().ok()?,
+                precision: value[6].parse().ok()?,
+                timezone: value[7].parse().ok()?,
+                calendar_q: value[8].parse().ok()?,
+            }));
+        }
+        let mut parts = value.first()?.split('|');
+        let mut ret = DateTime::from_str(parts.next()?)?;
+        if let (Some(precision),Some(timezone),Some(calendar_q)) = (parts.next(),parts.next(),parts.next()) {
+            ret.precision = precision.parse().ok()?;
+            ret.timezone = timezone.parse().ok()?;
+            ret.calendar_q = calendar_q.parse().ok()?;
+        }
+        Some(ret)
     }
 
     fn get_type_parts(&self) -> Vec<crate::type_part::TypePart>  {
-        vec![TypePart::I16,TypePart::U8,TypePart::U8,TypePart::U8,TypePart::U8,TypePart::U8]
+        vec![TypePart::I64,TypePart::U8,TypePart::U8,TypePart::U8,TypePart::U8,TypePart::U8,TypePart::U8,TypePart::I16,TypePart::U32]
     }
 
     fn values(&self) -> Vec<DbOperationCacheValue> {
-        //let safe_s = self.s.replace('"',""); // TODO FIXME
-        //format!("(SELECT `id` FROM `texts` WHERE `value`=\"{safe_s}\")")
         vec![
             DbOperationCacheValue::I64(self.year),
             DbOperationCacheValue::U8(self.month),
@@ -54,11 +147,15 @@ impl ElementType for DateTime {
             DbOperationCacheValue::U8(self.hour),
             DbOperationCacheValue::U8(self.minute),
             DbOperationCacheValue::U8(self.second),
+            DbOperationCacheValue::U8(self.precision),
+            DbOperationCacheValue::I16(self.timezone),
+            DbOperationCacheValue::U32(self.calendar_q),
             ]
     }
 
+    /// The xsd:dateTime value; precision, timezone and calendar model are kept separately, as in Wikibase RDF
     fn to_string(&self) -> String  {
-        format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}Z", self.year, self.month, self.day, self.hour, self.minute, self.second)
+        format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}Z", self.year_string(), self.month, self.day, self.hour, self.minute, self.second)
     }
 
     fn name(&self) -> &str  {
@@ -70,10 +167,29 @@ impl ElementType for DateTime {
     }
 
     fn to_url(&self) -> String  {
-        self.to_string() // TODO CHECKME FIXME
+        self.to_string()
     }
 
     fn sql_var_from_name(_name: &str, prefix: &str) -> Option<Vec<String>>  {
-        Some(vec![format!("{prefix}0"),format!("{prefix}1"),format!("{prefix}2")])
+        Some((0..9).map(|num|format!("{prefix}{num}")).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let big_bang = DateTime::from_str("-13798000000-01-01T00:00:00Z").unwrap();
+        assert_eq!(big_bang.to_string(),"-13798000000-01-01T00:00:00Z");
+        assert!(matches!(big_bang.values()[0],DbOperationCacheValue::I64(-13798000000)));
+        let caesar = DateTime::from_sql_values("DateTime",&vec!["-0044-03-15T00:00:00Z|11|0|1985786".to_string()]).unwrap();
+        assert_eq!((caesar.precision(),caesar.timezone(),caesar.calendar_q()),(11,0,JULIAN_Q));
+        assert_eq!(caesar.to_string(),"-0044-03-15T00:00:00Z");
+        let columns: Vec<String> = ["476","9","4","0","0","0","9","-300","1985727"].iter().map(|s|s.to_string()).collect();
+        let odoacer = DateTime::from_sql_values("DateTime",&columns).unwrap();
+        assert_eq!((odoacer.to_string().as_str(),odoacer.precision(),odoacer.timezone()),("0476-09-04T00:00:00Z",9,-300));
+        assert!(big_bang.sort_key()<caesar.sort_key() && caesar.sort_key()<odoacer.sort_key());
     }
-}
\ No newline at end of file
+}
@@ -104,16 +104,18 @@ impl DbOperationCache {
         }
     }
 
-    pub async fn add(&mut self, values: Vec<DbOperationCacheValue>, app: &AppState) -> Result<(),WDQSErr> {
+    /// Adds a row; once the batch size is reached, returns all rows as a cache to write, and this one is empty again
+    pub fn add(&mut self, values: Vec<DbOperationCacheValue>, app: &AppState) -> Result<Option<DbOperationCache>,WDQSErr> {
         if values.is_empty() {
             return Err(format!("DbOperationCache::add: Nothing to do for {}",self.command).into());
         }
 
         self.values.push(values);
         if self.values.len()>=app.insert_batch_size {
-            self.force_flush(app).await?;
+            let values = std::mem::take(&mut self.values);
+            return Ok(Some(Self { command: self.command.to_owned(), values }));
         }
-        Ok(())
+        Ok(None)
     }
 
     fn create_command(k: &Element, v: &Element, table: &DatabaseTable) -> String {
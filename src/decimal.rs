@@ -0,0 +1,136 @@
+use regex::Regex;
+
+use crate::{element_type::ElementType, type_part::TypePart, db_operation_cache::DbOperationCacheValue};
+
+lazy_static! {
+    static ref RE_DECIMAL: Regex = Regex::new(r#"^([+-]?)(\d*)(?:\.(\d*))?$"#).expect("RE_DECIMAL does not parse");
+}
+
+/// Digits before and after the decimal point that fit into TypePart::Decimal
+pub const MAX_INTEGER_DIGITS: usize = 35;
+pub const MAX_FRACTION_DIGITS: usize = 30;
+
+/// An exact xsd:decimal, kept as its canonical lexical form
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decimal {
+    negative: bool,
+    integer: String, // Without leading zeros
+    fraction: String, // Without trailing zeros
+}
+
+impl Decimal {
+    /// The shortest decimal that reads back as `f`, or None if it is not finite or does not fit
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if !f.is_finite() {
+            return None;
+        }
+        Some(*Self::from_str(&format!("{f}"))?)
+    }
+
+    /// Whether `s` is an xsd:decimal, even one that does not fit
+    pub fn is_lexical(s: &str) -> bool {
+        RE_DECIMAL.is_match(s) && s.chars().any(|c|c.is_ascii_digit())
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse::<f64>().unwrap_or(f64::NAN)
+    }
+}
+
+impl ElementType for Decimal {
+    /// Parses an xsd:decimal; values that do not fit into TypePart::Decimal are None rather than rounded
+    fn from_str(s: &str) -> Option<Box<Self>> {
+        let caps = RE_DECIMAL.captures(s)?;
+        let integer = caps.get(2).map(|m|m.as_str()).unwrap_or_default();
+        let fraction = caps.get(3).map(|m|m.as_str()).unwrap_or_default();
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        let integer = integer.trim_start_matches('0').to_string();
+        let fraction = fraction.trim_end_matches('0').to_string();
+        if integer.len()>MAX_INTEGER_DIGITS || fraction.len()>MAX_FRACTION_DIGITS {
+            return None;
+        }
+        let negative = caps.get(1).map(|m|m.as_str())==Some("-") && !(integer.is_empty() && fraction.is_empty());
+        Some(Box::new(Self { negative, integer, fraction }))
+    }
+
+    /// Computed values, like AVG(), can have more fractional digits than a column, or an exponent; these are truncated
+    fn from_sql_values(name:&str, value: &Vec<String>) -> Option<Box<Self>> {
+        if name!="Decimal" {
+            return None;
+        }
+        let v = value.first()?;
+        if let Some(ret) = Self::from_str(v) {
+            return Some(ret);
+        }
+        match v.split_once('.') {
+            Some((integer,fraction)) if fraction.len()>MAX_FRACTION_DIGITS && fraction.chars().all(|c|c.is_ascii_digit()) => {
+                Self::from_str(&format!("{integer}.{}",&fraction[..MAX_FRACTION_DIGITS]))
+            }
+            _ => {
+                let f = v.parse::<f64>().ok()?;
+                Self::from_f64(f).map(Box::new).or_else(||Self::from_str(&format!("{f:.*}",MAX_FRACTION_DIGITS)))
+            }
+        }
+    }
+
+    fn get_type_parts(&self) -> Vec<crate::type_part::TypePart>  {
+        vec![TypePart::Decimal]
+    }
+
+    fn values(&self) -> Vec<DbOperationCacheValue> {
+        vec![DbOperationCacheValue::Expression(self.to_string())]
+    }
+
+    fn to_string(&self) -> String  {
+        let sign = if self.negative { "-" } else { "" };
+        let integer = if self.integer.is_empty() { "0" } else { &self.integer };
+        match self.fraction.is_empty() {
+            true => format!("{sign}{integer}"),
+            false => format!("{sign}{integer}.{}",self.fraction),
+        }
+    }
+
+    fn name(&self) -> &str  {
+        "Decimal"
+    }
+
+    fn table_name(&self) -> String  {
+        self.name().to_string()
+    }
+
+    fn to_url(&self) -> String  {
+        self.to_string()
+    }
+
+    fn sql_var_from_name(_name: &str, prefix: &str) -> Option<Vec<String>>  {
+        Some(vec![format!("{prefix}0")])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let d = |s: &str| Decimal::from_str(s).map(|d|d.to_string());
+        assert_eq!(d("+0012.3400"),Some("12.34".to_string()));
+        assert_eq!(d("-0.000000000000000000000000000001"),Some("-0.000000000000000000000000000001".to_string()));
+        assert_eq!(d("-0.0"),Some("0".to_string()));
+        assert_eq!(d(".5"),Some("0.5".to_string()));
+        assert_eq!(d("123456789012345678901234567890.123456789"),Some("123456789012345678901234567890.123456789".to_string()));
+        // As returned by MySQL for DECIMAL(65,30)
+        assert_eq!(Decimal::from_sql_values("Decimal",&vec!["-1.500000000000000000000000000000".to_string()]).unwrap().to_string(),"-1.5");
+        assert_eq!(d("1.0000000000000000000000000000001"),None);
+        let avg = Decimal::from_sql_values("Decimal",&vec!["0.3333333333333333333333333333333333".to_string()]).unwrap();
+        assert_eq!(avg.to_string(),"0.333333333333333333333333333333");
+        assert_eq!(Decimal::from_sql_values("Decimal",&vec!["2.5e-3".to_string()]).unwrap().to_string(),"0.0025");
+        assert_eq!(Decimal::from_sql_values("Decimal",&vec!["1e-40".to_string()]).unwrap().to_string(),"0");
+        assert_eq!(d("1e5"),None);
+        assert!(Decimal::is_lexical("1.0000000000000000000000000000001") && !Decimal::is_lexical("1e5") && !Decimal::is_lexical("."));
+        assert_eq!(d("."),None);
+        assert_eq!(Decimal::from_f64(1.5e3).map(|d|d.to_string()),Some("1500".to_string()));
+    }
+}
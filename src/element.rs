@@ -1,19 +1,18 @@
 use regex::Regex;
-
-/* TODO
-- Coordinates on other planets (moon, Mars etc), encoded like:
-<http://www.wikidata.org/entity/statement/Q45326-DEEEF3C4-21CA-4250-960F-62BE491BFA2C> <http://www.wikidata.org/prop/statement/P625> \"<http://www.wikidata.org/entity/Q2565> Point(336 73)\"^^<http://www.opengis.net/ont/geosparql#wktLiteral> .
- */
+use std::num::IntErrorKind;
 
 use crate::date_time::DateTime;
+use crate::decimal::Decimal;
 use crate::db_operation_cache::DbOperationCacheValue;
 use crate::element_type::ElementType;
 use crate::entity_statement::EntityStatement;
 use crate::lat_lon::LatLon;
+use crate::quantity::Quantity;
 use crate::text_id::TextId;
 use crate::type_part::TypePart;
 use crate::entity::Entity;
 use crate::uuid::{UUID40, UUID32};
+use crate::sparql_results::SparqlValue;
 
 lazy_static! {
     static ref RE_WIKI_URL: Regex = Regex::new(r#"^https?://(.+?)/wiki/(.+)$"#).expect("RE_WIKI_URL does not parse");
@@ -23,6 +22,7 @@ lazy_static! {
 pub enum Element {
     Text(TextId),
     TextInLanguage((TextId,TextId)), // (text,language)
+    TypedLiteral((TextId,TextId)), // (text,datatype IRI), for datatypes without their own type
     WikiPage((TextId,TextId)), // (server,page)
     Entity(Entity),
     EntityStatement(EntityStatement),
@@ -42,9 +42,11 @@ pub enum Element {
     Value(UUID32),
     DateTime(DateTime),
     LatLon(LatLon),
+    Quantity(Quantity),
     Int(i64),
-    Float(f64),
+    Decimal(Decimal),
     Url(TextId),
+    BlankNode(TextId), // Label
     WikibaseOntology(String),
     SchemaOrg(String),
     W3Owl(String),
@@ -157,10 +159,50 @@ impl Element {
         }
     }
 
+    /// Creates an element from a typed literal, eg "42"^^<http://www.w3.org/2001/XMLSchema#integer>
+    pub fn from_typed_literal(s: &str, type_s: &str) -> Option<Self> {
+        match type_s {
+            "http://www.w3.org/2001/XMLSchema#dateTime" => Some(Element::DateTime(*DateTime::from_str(s)?)),
+            "http://www.opengis.net/ont/geosparql#wktLiteral" => Some(Element::LatLon(*LatLon::from_str(s)?)),
+            "http://www.w3.org/2001/XMLSchema#decimal" => match Decimal::from_str(s) {
+                Some(d) => Some(Element::Decimal(*d)),
+                None if Decimal::is_lexical(s) => Some(Element::TypedLiteral((TextId::new(s),TextId::new(type_s)))), // Does not fit into DECIMAL(65,30)
+                None => None,
+            },
+            // Kept as written, as DECIMAL(65,30) can not hold every double, nor INF and NaN
+            "http://www.w3.org/2001/XMLSchema#double" => match s.parse::<f64>() {
+                Ok(_) => Some(Element::TypedLiteral((TextId::new(s),TextId::new(type_s)))),
+                Err(_) => None,
+            },
+            "http://www.w3.org/2001/XMLSchema#integer" => match s.parse::<i64>() {
+                Ok(i) => Some(Element::Int(i)),
+                Err(e) if matches!(e.kind(),IntErrorKind::PosOverflow|IntErrorKind::NegOverflow) => match Decimal::from_str(s) {
+                    Some(d) => Some(Element::Decimal(*d)), // Beyond 64 bit
+                    None => Some(Element::TypedLiteral((TextId::new(s),TextId::new(type_s)))), // Beyond DECIMAL(65,30)
+                },
+                Err(_) => None,
+            },
+            "http://www.w3.org/2001/XMLSchema#string" => Some(Element::Text(TextId::new(s))),
+            _ => Some(Element::TypedLiteral((TextId::new(s),TextId::new(type_s)))),
+        }
+    }
+
+    /// Creates an element from a literal with optional datatype or language
+    pub fn from_literal(s: &str, datatype: Option<&str>, language: Option<&str>) -> Option<Self> {
+        if let Some(language) = language {
+            return Some(Element::TextInLanguage((TextId::new(s),TextId::new(language))));
+        }
+        match datatype {
+            Some(type_s) => Self::from_typed_literal(s, type_s),
+            None => Some(Element::Text(TextId::new(s))),
+        }
+    }
+
     pub fn name(&self) -> &str {
         match self {
             Element::Text(_) => "Text",
             Element::TextInLanguage(_) => "TextInLanguage",
+            Element::TypedLiteral(_) => "TypedLiteral",
             Element::WikiPage(_) => "WikiPage",
             Element::Entity(e) => e.name(),
             Element::EntityStatement(_) => "EntityStatement",
@@ -180,9 +222,11 @@ impl Element {
             Element::Value(_) => "Value",
             Element::DateTime(_) => "DateTime",
             Element::LatLon(_) => "LatLon",
+            Element::Quantity(_) => "Quantity",
             Element::Int(_) => "Integer",
-            Element::Float(_) => "Decimal",
+            Element::Decimal(_) => "Decimal",
             Element::Url(_) => "Url",
+            Element::BlankNode(_) => "BlankNode",
             Element::W3Owl(_) => "W3Owl",
             Element::WikibaseOntology(_) => "WikibaseOntology",
             Element::SchemaOrg(_) => "SchemaOrg",
@@ -205,18 +249,242 @@ impl Element {
         if let Some(lat_lon) = LatLon::from_sql_values(name, &value) {
             return Element::LatLon(*lat_lon);
         }
-        // TODO TextId?
-        Element::Url(value[0].to_owned().into())
+        if let Some(quantity) = Quantity::from_sql_values(name, value) {
+            return Element::Quantity(*quantity);
+        }
+        if let Some(date_time) = DateTime::from_sql_values(name, value) {
+            return Element::DateTime(*date_time);
+        }
+        if let Some(es) = EntityStatement::from_sql_values(name, value) {
+            return Element::EntityStatement(*es);
+        }
+        let v = value[0].to_owned();
+        match name {
+            "Text" => Element::Text(TextId::new(&v)),
+            "TextInLanguage" => {
+                match value[0].rsplit_once('@') {
+                    Some((text,"")) => Element::Text(TextId::new(text)),
+                    Some((text,language)) => Element::TextInLanguage((TextId::new(text),TextId::new(language))),
+                    None => Element::Text(TextId::new(&value[0])),
+                }
+            }
+            "TypedLiteral" => {
+                match value[0].rsplit_once("^^") { // IRIs can not contain ^
+                    Some((text,datatype)) => Element::TypedLiteral((TextId::new(text),TextId::new(datatype))),
+                    None => Element::Text(TextId::new(&value[0])),
+                }
+            }
+            "BlankNode" => Element::BlankNode(TextId::new(&v)),
+            "WikiPage" => {
+                match value[0].split_once('|') {
+                    Some((server,page)) => Element::WikiPage((server.into(),page.into())),
+                    None => Element::Url(value[0].to_owned().into()),
+                }
+            }
+            "Reference" => UUID40::from_str(&v).map(|uuid|Element::Reference(*uuid)).unwrap_or(Element::Url(v.into())),
+            "Value" => UUID32::from_str(&v).map(|uuid|Element::Value(*uuid)).unwrap_or(Element::Url(v.into())),
+            "Integer" => v.parse::<i64>().map(Element::Int).unwrap_or(Element::Url(v.into())),
+            "Decimal" => Decimal::from_sql_values(name, value).map(|d|Element::Decimal(*d)).unwrap_or(Element::Url(v.into())),
+            "Property" => Entity::from_sql_values("EntityProp",value).map(|p|Element::Property(*p)).unwrap_or(Element::Url(v.into())),
+            "PropertyDirect" => Element::PropertyDirect(v),
+            "PropDirectNorm" => Element::PropertyDirectNormalized(v),
+            "PropStatement" => Element::PropertyStatement(v),
+            "PropStatementValue" => Element::PropertyStatementValue(v),
+            "PropStatementValueNorm" => Element::PropertyStatementValueNormalized(v),
+            "PropRef" => Element::PropertyReference(v),
+            "PropReferenceValue" => Element::PropertyReferenceValue(v),
+            "PropReferenceValueNorm" => Element::PropertyReferenceValueNormalized(v),
+            "PropQual" => Element::PropertyQualifier(v),
+            "PropQualValue" => Element::PropertyQualifierValue(v),
+            "PropQualValueNorm" => Element::PropertyQualifierValueNormalized(v),
+            "WikibaseOntology" => Element::WikibaseOntology(v),
+            "SchemaOrg" => Element::SchemaOrg(v),
+            "W3Owl" => Element::W3Owl(v),
+            "RdfSchemaLabel" => Element::RdfSchemaLabel,
+            "WasDerivedFrom" => Element::WasDerivedFrom,
+            "PurlLanguage" => Element::PurlLanguage,
+            "W3RdfSyntaxNsType" => Element::W3RdfSyntaxNsType,
+            "W3SkosCoreAltLabel" => Element::W3SkosCoreAltLabel,
+            "W3OntolexLexicalForm" => Element::W3OntolexLexicalForm,
+            "W3OntolexRepresentation" => Element::W3OntolexRepresentation,
+            "W3SkosCorePrefLabel" => Element::W3SkosCorePrefLabel,
+            "CreativeCommonsLicense" => Element::CreativeCommonsLicense,
+            _ => Element::Url(v.into()), // TODO TextId?
+        }
+    }
+
+    /// The IRI of this element, or None for literals
+    pub fn to_iri(&self) -> Option<String> {
+        Some(match self {
+            Element::Entity(e) => e.to_url(),
+            Element::EntityStatement(es) => es.to_url(),
+            Element::Property(p) => format!("http://www.wikidata.org/prop/{}",p.to_string()),
+            Element::PropertyDirect(s) => format!("http://www.wikidata.org/prop/direct/{s}"),
+            Element::PropertyDirectNormalized(s) => format!("http://www.wikidata.org/prop/direct-normalized/{s}"),
+            Element::PropertyStatement(s) => format!("http://www.wikidata.org/prop/statement/{s}"),
+            Element::PropertyStatementValue(s) => format!("http://www.wikidata.org/prop/statement/value/{s}"),
+            Element::PropertyStatementValueNormalized(s) => format!("http://www.wikidata.org/prop/statement/value-normalized/{s}"),
+            Element::PropertyReference(s) => format!("http://www.wikidata.org/prop/reference/{s}"),
+            Element::PropertyReferenceValue(s) => format!("http://www.wikidata.org/prop/reference/value/{s}"),
+            Element::PropertyReferenceValueNormalized(s) => format!("http://www.wikidata.org/prop/reference/value-normalized/{s}"),
+            Element::PropertyQualifier(s) => format!("http://www.wikidata.org/prop/qualifier/{s}"),
+            Element::PropertyQualifierValue(s) => format!("http://www.wikidata.org/prop/qualifier/value/{s}"),
+            Element::PropertyQualifierValueNormalized(s) => format!("http://www.wikidata.org/prop/qualifier/value-normalized/{s}"),
+            Element::Reference(uuid) => uuid.to_url(),
+            Element::Value(uuid) => uuid.to_url(),
+            Element::Url(u) => u.to_string(),
+            Element::BlankNode(_) => return None,
+            Element::WikiPage((server,page)) => format!("https://{}/wiki/{}",server.to_string(),page.to_string()),
+            Element::WikibaseOntology(s) => format!("http://wikiba.se/ontology#{s}"),
+            Element::SchemaOrg(s) => format!("http://schema.org/{s}"),
+            Element::W3Owl(s) => format!("http://www.w3.org/2002/07/owl#{s}"),
+            Element::RdfSchemaLabel => "http://www.w3.org/2000/01/rdf-schema#label".to_string(),
+            Element::WasDerivedFrom => "http://www.w3.org/ns/prov#wasDerivedFrom".to_string(),
+            Element::PurlLanguage => "http://purl.org/dc/terms/language".to_string(),
+            Element::W3RdfSyntaxNsType => "http://www.w3.org/1999/02/22-rdf-syntax-ns#type".to_string(),
+            Element::W3SkosCoreAltLabel => "http://www.w3.org/2004/02/skos/core#altLabel".to_string(),
+            Element::W3SkosCorePrefLabel => "http://www.w3.org/2004/02/skos/core#prefLabel".to_string(),
+            Element::W3OntolexLexicalForm => "http://www.w3.org/ns/lemon/ontolex#lexicalForm".to_string(),
+            Element::W3OntolexRepresentation => "http://www.w3.org/ns/lemon/ontolex#representation".to_string(),
+            Element::CreativeCommonsLicense => "http://creativecommons.org/ns#license".to_string(),
+            Element::Text(_) | Element::TextInLanguage(_) | Element::TypedLiteral(_) | Element::DateTime(_) | Element::LatLon(_) | Element::Quantity(_) | Element::Int(_) | Element::Decimal(_) => return None,
+        })
+    }
+
+    /// The RDF term for this element, as used in query results
+    pub fn to_sparql_value(&self) -> SparqlValue {
+        if let Some(iri) = self.to_iri() {
+            return SparqlValue::Uri(iri);
+        }
+        let typed = |value: String, datatype: &str| SparqlValue::Literal { value, language: None, datatype: Some(datatype.to_string()) };
+        match self {
+            Element::BlankNode(label) => SparqlValue::BlankNode(label.to_string()),
+            Element::TextInLanguage((text,language)) => SparqlValue::Literal { value: text.to_string(), language: Some(language.to_string()), datatype: None },
+            Element::TypedLiteral((text,datatype)) => typed(text.to_string(),&datatype.to_string()),
+            Element::DateTime(dt) => typed(dt.to_string(),"http://www.w3.org/2001/XMLSchema#dateTime"),
+            Element::LatLon(ll) => typed(ll.to_string(),"http://www.opengis.net/ont/geosparql#wktLiteral"),
+            Element::Int(i) => typed(format!("{i}"),"http://www.w3.org/2001/XMLSchema#integer"),
+            Element::Decimal(d) => typed(d.to_string(),"http://www.w3.org/2001/XMLSchema#decimal"),
+            Element::Text(text) => SparqlValue::Literal { value: text.to_string(), language: None, datatype: None },
+            Element::Quantity(q) => SparqlValue::Literal { value: q.to_string(), language: None, datatype: None },
+            _ => SparqlValue::Literal { value: self.to_string().unwrap_or_default(), language: None, datatype: None },
+        }
     }
 
     pub fn to_string(&self) -> Option<String> {
         match self { // TODO more types?
             Element::Entity(e) => Some(e.to_url()),
             Element::Text(t) => Some(t.to_string()),
+            Element::TextInLanguage((t,_)) => Some(t.to_string()),
+            Element::TypedLiteral((t,_)) => Some(t.to_string()),
+            Element::WikiPage((server,page)) => Some(format!("https://{}/wiki/{}",server.to_string(),page.to_string())),
+            Element::Url(u) => Some(u.to_string()),
             _ => None
         }
     }
 
+    /// Returns a single SQL expression that reads back an element stored under type `name`, with column `prefix` ("k" or "v")
+    pub fn sql_expression_from_name(name: &str, prefix: &str) -> Option<String> {
+        let columns: Vec<String> = Self::sql_var_from_name(name, prefix).iter().map(|c|format!("`{c}`")).collect();
+        Self::sql_expression(name, &columns)
+    }
+
+    /// Combines the SQL expressions for the parts of an element into a single value, as read by `from_sql_values`
+    pub fn sql_expression(name: &str, columns: &[String]) -> Option<String> {
+        let text = |column: &String| format!("(SELECT `value` FROM `texts` WHERE `id`={column})");
+        match (name,columns.len()) {
+            (_,0) => None,
+            ("Text",1) | ("Url",1) | ("BlankNode",1) => Some(text(&columns[0])),
+            ("TextInLanguage",2) => Some(format!("CONCAT({},'@',{})",text(&columns[0]),text(&columns[1]))),
+            ("TypedLiteral",2) => Some(format!("CONCAT({},'^^',{})",text(&columns[0]),text(&columns[1]))),
+            ("WikiPage",2) => Some(format!("CONCAT({},'|',{})",text(&columns[0]),text(&columns[1]))),
+            ("Reference",1) | ("Value",1) => Some(format!("LOWER(HEX({}))",columns[0])),
+            ("DateTime",9) => Some(DateTime::sql_expression(columns)),
+            ("Quantity",5) => Some(format!("CONCAT_WS('|',{})",columns.join(","))),
+            ("LatLon",2) => Some(format!("CONCAT('<http://www.wikidata.org/entity/Q',{},'> ',ST_AsText({}))",columns[1],columns[0])),
+            ("EntityStatement",2) => Some(format!("CONCAT('Q',{},'-',LOWER(HEX({})))",columns[0],columns[1])), // TODO entity type is not part of the name; assume items
+            (_,1) => Some(columns[0].to_owned()),
+            (_,_) => Some(format!("CONCAT_WS('-',{})",columns.join(","))),
+        }
+    }
+
+    /// This element as a SQL expression comparable to `sql_expression_from_name`
+    pub fn sql_encoded(&self) -> Option<String> {
+        let number_of_columns = Self::sql_var_from_name(self.name(), "").len();
+        let columns: Vec<String> = self.values().iter().take(number_of_columns).map(|v|v.as_sql_stdout()).collect();
+        if columns.len()!=number_of_columns {
+            return None;
+        }
+        Self::sql_expression(self.name(), &columns)
+    }
+
+    /// Whether elements of this type are literals rather than IRIs
+    pub fn is_literal_name(name: &str) -> bool {
+        matches!(name,"Text"|"TextInLanguage"|"TypedLiteral"|"DateTime"|"LatLon"|"Quantity"|"Integer"|"Decimal")
+    }
+
+    /// SQL expression that sorts values like SPARQL ORDER BY, or None if that is not possible
+    pub fn sql_order_expression(name: &str, sql: &str) -> Option<String> {
+        match name {
+            "Integer" | "Decimal" => Some(sql.to_string()),
+            "BlankNode" => Some(format!("CAST({sql} AS BINARY)")),
+            "DateTime" => Some(DateTime::sql_order_expression(sql)),
+            "LatLon" | "Quantity" => None,
+            _ => Some(format!("CAST({} AS BINARY)",Self::sql_str_expression(name, sql)?)),
+        }
+    }
+
+    /// SPARQL `str()` of an SQL value created by `sql_expression_from_name`
+    pub fn sql_str_expression(name: &str, sql: &str) -> Option<String> {
+        let prefix = match name {
+            "Text" | "Url" => return Some(sql.to_string()),
+            "BlankNode" => return None, // str() is undefined for blank nodes
+            "DateTime" => return Some(DateTime::sql_str_expression(sql)),
+            "LatLon" => {
+                // Earth coordinates are written without globe, like LatLon::to_string
+                let globe = format!("SUBSTRING_INDEX({sql},'> ',1)");
+                let wkt = format!("CONCAT('Point',SUBSTRING(SUBSTRING_INDEX({sql},'> ',-1),6))");
+                return Some(format!("IF({globe}='<http://www.wikidata.org/entity/Q2',{wkt},CONCAT({globe},'> ',{wkt}))"));
+            }
+            "TextInLanguage" => return Some(format!("SUBSTRING({sql},1,CHAR_LENGTH({sql})-CHAR_LENGTH(SUBSTRING_INDEX({sql},'@',-1))-1)")),
+            "TypedLiteral" => return Some(format!("SUBSTRING({sql},1,CHAR_LENGTH({sql})-CHAR_LENGTH(SUBSTRING_INDEX({sql},'^^',-1))-2)")),
+            "Integer" => return Some(format!("CAST({sql} AS CHAR)")),
+            "Decimal" => { // DECIMAL columns have trailing zeros
+                let s = format!("CAST({sql} AS CHAR)");
+                return Some(format!("IF(LOCATE('.',{s})>0,TRIM(TRAILING '.' FROM TRIM(TRAILING '0' FROM {s})),{s})"));
+            }
+            "WikiPage" => return Some(format!("CONCAT('https://',REPLACE({sql},'|','/wiki/'))")),
+            "EntityItem" => "http://www.wikidata.org/entity/Q",
+            "EntityProp" => "http://www.wikidata.org/entity/P",
+            "EntityLexeme" => "http://www.wikidata.org/entity/L",
+            "EntityMedia" => "http://commons.wikimedia.org/entity/M",
+            "Property" => "http://www.wikidata.org/prop/P",
+            "PropertyDirect" => "http://www.wikidata.org/prop/direct/",
+            "PropDirectNorm" => "http://www.wikidata.org/prop/direct-normalized/",
+            "PropStatement" => "http://www.wikidata.org/prop/statement/",
+            "PropStatementValue" => "http://www.wikidata.org/prop/statement/value/",
+            "PropStatementValueNorm" => "http://www.wikidata.org/prop/statement/value-normalized/",
+            "PropRef" => "http://www.wikidata.org/prop/reference/",
+            "PropReferenceValue" => "http://www.wikidata.org/prop/reference/value/",
+            "PropReferenceValueNorm" => "http://www.wikidata.org/prop/reference/value-normalized/",
+            "PropQual" => "http://www.wikidata.org/prop/qualifier/",
+            "PropQualValue" => "http://www.wikidata.org/prop/qualifier/value/",
+            "PropQualValueNorm" => "http://www.wikidata.org/prop/qualifier/value-normalized/",
+            "Reference" => "http://www.wikidata.org/reference/",
+            "Value" => "http://www.wikidata.org/value/",
+            "EntityStatement" => "http://www.wikidata.org/entity/statement/",
+            "WikibaseOntology" => "http://wikiba.se/ontology#",
+            "SchemaOrg" => "http://schema.org/",
+            "W3Owl" => "http://www.w3.org/2002/07/owl#",
+            _ if Self::is_literal_name(name) => return None,
+            _ => { // Constant IRIs
+                let iri = Self::from_sql_values(name, &vec![String::new()]).to_iri().filter(|iri|!iri.is_empty())?;
+                return Some(format!("'{iri}'"));
+            }
+        };
+        Some(format!("CONCAT('{prefix}',{sql})"))
+    }
+
     pub fn sql_var_from_name(name: &str, prefix: &str) -> Vec<String> {
         // check Entity
         if let Some(ret) = Entity::sql_var_from_name(name, prefix) {
@@ -225,24 +493,32 @@ impl Element {
         match name {
             "Text" => vec![format!("{prefix}0")],
             "TextInLanguage" => vec![format!("{prefix}0"),format!("{prefix}1")],
+            "TypedLiteral" => vec![format!("{prefix}0"),format!("{prefix}1")],
             "WikiPage" => vec![format!("{prefix}0"),format!("{prefix}1")],
             "EntityStatement" => EntityStatement::sql_var_from_name(name, prefix).unwrap(),
             "Property" => vec![format!("{prefix}0")],
             "PropertyDirect" => vec![format!("{prefix}0")],
-            "PropertyDirectNorm" => vec![format!("{prefix}0")],
-            "PropertyStatement" => vec![format!("{prefix}0")],
+            "PropDirectNorm" => vec![format!("{prefix}0")],
+            "PropStatement" => vec![format!("{prefix}0")],
             "PropStatementValue" => vec![format!("{prefix}0")],
             "PropStatementValueNorm" => vec![format!("{prefix}0")],
             "PropRef" => vec![format!("{prefix}0")],
-            "PropRefValue" => vec![format!("{prefix}0")],
-            "PropRefValueNorm" => vec![format!("{prefix}0")],
+            "PropReferenceValue" => vec![format!("{prefix}0")],
+            "PropReferenceValueNorm" => vec![format!("{prefix}0")],
             "PropQual" => vec![format!("{prefix}0")],
             "PropQualValue" => vec![format!("{prefix}0")],
             "PropQualValueNorm" => vec![format!("{prefix}0")],
             "DateTime" => DateTime::sql_var_from_name(name, prefix).unwrap(),
+            "LatLon" => LatLon::sql_var_from_name(name, prefix).unwrap(),
+            "Quantity" => Quantity::sql_var_from_name(name, prefix).unwrap(),
             "Reference" => vec![format!("{prefix}0")],
             "Value" => vec![format!("{prefix}0")],
-            "Url" => vec![format!("{prefix}0")],
+            "Url" | "BlankNode" => vec![format!("{prefix}0")],
+            "Integer" => vec![format!("{prefix}0")],
+            "Decimal" => vec![format!("{prefix}0")],
+            "WikibaseOntology" => vec![format!("{prefix}0")],
+            "SchemaOrg" => vec![format!("{prefix}0")],
+            "W3Owl" => vec![format!("{prefix}0")],
             other => {
                 eprintln!("Element::sql_var_from_name: for '{other}'");
                 vec![]
@@ -266,16 +542,19 @@ impl Element {
             Element::PropertyQualifierValue(s) => format!("PropQualValue_{s}"),
             Element::PropertyQualifierValueNormalized(s) => format!("PropQualValueNorm_{s}"),
             Element::Url(_) => self.name().to_string(),
+            Element::BlankNode(_) => self.name().to_string(),
             Element::Text(_) => self.name().to_string(),
             Element::TextInLanguage(_) => self.name().to_string(),
+            Element::TypedLiteral(_) => self.name().to_string(),
             Element::WikiPage(_) => self.name().to_string(),
             Element::EntityStatement(es) => es.table_name(),
             Element::Reference(_) => self.name().to_string(),
             Element::Value(_) => self.name().to_string(),
             Element::DateTime(_) => self.name().to_string(),
             Element::LatLon(_) => self.name().to_string(),
+            Element::Quantity(_) => self.name().to_string(),
             Element::Int(_) => self.name().to_string(),
-            Element::Float(_) => self.name().to_string(),
+            Element::Decimal(_) => self.name().to_string(),
             Element::WikibaseOntology(s) => format!("WO{s}"),
             Element::SchemaOrg(s) => format!("SchemaOrg{s}"),
             Element::W3Owl(s) => format!("W3Owl_{s}"),
@@ -295,9 +574,11 @@ impl Element {
         match self {
             Element::Text(_) => vec![TypePart::Int],
             Element::TextInLanguage(_) => vec![TypePart::Int,TypePart::Int], // TODO use get_type_parts
+            Element::TypedLiteral(_) => vec![TypePart::Int,TypePart::Int],
             Element::WikiPage(_) => vec![TypePart::Int,TypePart::Int], // TODO use get_type_parts
             Element::Entity(e) => e.get_type_parts(),
             Element::LatLon(l) => l.get_type_parts(),
+            Element::Quantity(q) => q.get_type_parts(),
             Element::DateTime(dt) => dt.get_type_parts(),
             Element::Reference(r) => r.get_type_parts(),
             Element::EntityStatement(es) => es.get_type_parts(),
@@ -313,10 +594,11 @@ impl Element {
             Element::PropertyQualifier(_) => vec![TypePart::ShortText],
             Element::PropertyQualifierValue(_) => vec![TypePart::ShortText],
             Element::PropertyQualifierValueNormalized(_) => vec![TypePart::ShortText],
-            Element::Int(_) => vec![TypePart::Int],
-            Element::Float(_) => vec![TypePart::Float],
+            Element::Int(_) => vec![TypePart::I64],
+            Element::Decimal(d) => d.get_type_parts(),
             Element::Value(_) => vec![TypePart::Text],
             Element::Url(_) => vec![TypePart::Int],
+            Element::BlankNode(_) => vec![TypePart::Int],
             Element::WikibaseOntology(_) => vec![TypePart::ShortText],
             Element::SchemaOrg(_) => vec![TypePart::ShortText],
             Element::W3Owl(_) => vec![TypePart::ShortText],
@@ -347,12 +629,17 @@ impl Element {
                 til.0.values()[0].to_owned(),
                 til.1.values()[0].to_owned(),
                 ],
+            Element::TypedLiteral(tl) => vec![
+                tl.0.values()[0].to_owned(),
+                tl.1.values()[0].to_owned(),
+                ],
             Element::WikiPage(wp) => vec![
                 wp.0.values()[0].to_owned(),
                 wp.1.values()[0].to_owned(),
                 ],
             Element::Text(t) => t.values(),
             Element::LatLon(l) => l.values(),
+            Element::Quantity(q) => q.values(),
             Element::Entity(e) => e.values(),
             Element::EntityStatement(es) => es.values(),
             Element::DateTime(dt) => dt.values(),
@@ -371,8 +658,9 @@ impl Element {
             Element::Reference(uuid) => uuid.values(),
             Element::Value(uuid) => uuid.values(),
             Element::Url(s) => s.values(),
+            Element::BlankNode(label) => label.values(),
             Element::Int(s) => vec![DbOperationCacheValue::Expression(format!("{s}"))],
-            Element::Float(s) => vec![DbOperationCacheValue::Expression(format!("{s}"))],
+            Element::Decimal(d) => d.values(),
             Element::WikibaseOntology(s) => vec![s.into()],
             Element::SchemaOrg(s) => vec![s.into()],
             Element::W3Owl(s) => vec![s.into()],
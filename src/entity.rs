@@ -22,6 +22,17 @@ pub enum Entity {
     Unknown(String),
 }
 
+impl Entity {
+    /// Lexeme forms and senses come back either as two columns, or as one "L-F" column
+    fn sql_pair(value: &[String]) -> Option<(u32,u8)> {
+        let (first,second) = match value.len() {
+            1 => value[0].split_once('-')?,
+            _ => (value.first()?.as_str(),value.get(1)?.as_str()),
+        };
+        Some((first.parse::<u32>().ok()?,second.parse::<u8>().ok()?))
+    }
+}
+
 impl ElementType for Entity {
     fn from_str(s: &str) -> Option<Box<Self>> {
         if let Some(caps) = RE_ENTITY_ITEM.captures(&s) {
@@ -121,7 +132,7 @@ impl ElementType for Entity {
             "EntityProp" => vec![format!("{prefix}0")],
             "EntityMedia" => vec![format!("{prefix}0")],
             "EntityLexeme" => vec![format!("{prefix}0")],
-            "EntityLexemeForm" => vec![format!("{prefix}0"),format!("{prefix}1")],
+            "EntityLexemeF" => vec![format!("{prefix}0"),format!("{prefix}1")],
             "EntityLexemeS" => vec![format!("{prefix}0"),format!("{prefix}1")],
             "EntityUnknown" => vec![format!("{prefix}0")],
             _ => return None,
@@ -134,14 +145,8 @@ impl ElementType for Entity {
             "EntityProp" => Entity::Property(value[0].parse::<u16>().unwrap()),
             "EntityMedia" => Entity::Media(value[0].parse::<u32>().unwrap()),
             "EntityLexeme" => Entity::Lexeme(value[0].parse::<u32>().unwrap()),
-            "EntityLexemeForm" => Entity::LexemeForm((
-                value[0].parse::<u32>().unwrap(),
-                value[1].parse::<u8>().unwrap()
-            )),
-            "EntityLexemeS" => Entity::LexemeSense((
-                value[0].parse::<u32>().unwrap(),
-                value[1].parse::<u8>().unwrap()
-            )),
+            "EntityLexemeF" => Entity::LexemeForm(Self::sql_pair(value)?),
+            "EntityLexemeS" => Entity::LexemeSense(Self::sql_pair(value)?),
             "EntityUnknown" => Entity::Unknown(value[0].to_string()),
             _ => return None
         }))
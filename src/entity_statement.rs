@@ -22,9 +22,9 @@ impl ElementType for EntityStatement {
         Some(Box::new(ret))
     }
 
-    fn from_sql_values(name:&str, _value: &Vec<String>) -> Option<Box<Self>> {
+    fn from_sql_values(name:&str, value: &Vec<String>) -> Option<Box<Self>> {
         match name {
-            "EntityStatement" => todo!(),//EntityStatement::from_str(&value[0].parse::<String>().unwrap()),
+            "EntityStatement" => EntityStatement::from_str(value.first()?),
             _ => None,
         }
     }
@@ -54,18 +54,13 @@ impl ElementType for EntityStatement {
     }
 
     fn to_url(&self) -> String  {
-        self.to_string() // TODO CHECKME FIXME
+        format!("http://www.wikidata.org/entity/statement/{}-{}",self.entity.to_string(),self.uuid.to_dashed())
     }
 
     fn sql_var_from_name(name: &str, prefix: &str) -> Option<Vec<String>>  {
         if name=="EntityStatement" {
-            let mut ret = Entity::sql_var_from_name(name, prefix).unwrap_or(vec![]);
-            ret.append(&mut UUID32::sql_var_from_name(name, prefix).unwrap_or(vec![]));
-            if ret.is_empty() {
-                None
-            } else {
-                Some(ret)
-            }
+            // Entity ID, then UUID; the entity type is not part of the name, but all entity types use one column
+            Some(vec![format!("{prefix}0"),format!("{prefix}1")])
         } else {
             None
         }
@@ -14,6 +14,22 @@ pub enum WDQSErr {
 
 impl std::error::Error for WDQSErr {}
 
+impl WDQSErr {
+    /// The name of the variant, as a machine-readable reason
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WDQSErr::String(_) => "String",
+            WDQSErr::MySQL(_) => "MySQL",
+            WDQSErr::IO(_) => "IO",
+            WDQSErr::Serde(_) => "Serde",
+            WDQSErr::ParseInt(_) => "ParseInt",
+            WDQSErr::FromUtf8(_) => "FromUtf8",
+            WDQSErr::ParserError(_) => "ParserError",
+            WDQSErr::NomError(_) => "NomError",
+        }
+    }
+}
+
 impl std::fmt::Display for WDQSErr {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
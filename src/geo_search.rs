@@ -0,0 +1,158 @@
+use crate::lat_lon::{LatLon, EARTH_Q};
+
+/// Mean radius in km of a globe, by Q-id; unknown globes are treated like Earth
+pub fn globe_radius(globe_q: u32) -> f64 {
+    match globe_q {
+        405 => 1737.4, // Moon
+        111 => 3389.5, // Mars
+        308 => 2439.7, // Mercury
+        313 => 6051.8, // Venus
+        _ => 6371.0,
+    }
+}
+
+/// The POINT in an SQL value created by `Element::sql_expression` for LatLon
+pub fn sql_point(latlon: &str) -> String {
+    format!("ST_GeomFromText(SUBSTRING_INDEX({latlon},'> ',-1))")
+}
+
+/// The globe Q-id in an SQL value created by `Element::sql_expression` for LatLon
+pub fn sql_globe(latlon: &str) -> String {
+    format!("SUBSTRING_INDEX(SUBSTRING_INDEX({latlon},'> ',1),'Q',-1)")
+}
+
+/// Longitude in [-180,180); other globes, like Mars, may use 0 to 360
+pub fn normalize_longitude(longitude: f64) -> f64 {
+    match (-180.0..180.0).contains(&longitude) {
+        true => longitude, // Without rounding errors
+        false => (longitude+180.0).rem_euclid(360.0)-180.0,
+    }
+}
+
+/// An SQL POINT with its longitude in [-180,180), as ST_Distance_Sphere fails for other longitudes
+pub fn sql_normalized_point(point: &str) -> String {
+    format!("Point(MOD(ST_X({point})+540,360)-180,ST_Y({point}))")
+}
+
+/// Distance in km between two SQL LatLon values on the same globe, like geof:distance; NULL across globes
+pub fn sql_distance_between(a: &str, b: &str) -> String {
+    let radius: Vec<String> = [405,111,308,313].iter().map(|q|format!("WHEN {q} THEN {}",globe_radius(*q)*1000.0)).collect();
+    let radius = format!("CASE {} {} ELSE {} END",sql_globe(a),radius.join(" "),globe_radius(EARTH_Q)*1000.0);
+    format!("IF({}={},ST_Distance_Sphere({},{},{radius})/1000,NULL)",sql_globe(a),sql_globe(b),sql_normalized_point(&sql_point(a)),sql_normalized_point(&sql_point(b)))
+}
+
+/// Spatial search of the wikibase:around and wikibase:box services
+#[derive(Debug, Clone)]
+pub enum GeoSearch {
+    Around { center: LatLon, radius: f64, globe_q: u32 }, // radius in km
+    Box { south_west: LatLon, north_east: LatLon, globe_q: u32 },
+}
+
+impl GeoSearch {
+    fn globe_q(&self) -> u32 {
+        match self {
+            GeoSearch::Around { globe_q, .. } | GeoSearch::Box { globe_q, .. } => *globe_q,
+        }
+    }
+
+    /// (west,south,east,north) in degrees, so the spatial index can be used.
+    /// A box across the antimeridian is split in two; on other globes than Earth, the parts west of 0 are also searched at longitudes from 180 to 360.
+    fn bounding_boxes(&self) -> Vec<(f64,f64,f64,f64)> {
+        let (west,south,east,north) = match self {
+            GeoSearch::Around { center, radius, globe_q } => {
+                let km_per_degree = globe_radius(*globe_q)*std::f64::consts::PI/180.0;
+                let latitude_delta = radius/km_per_degree;
+                let longitude_delta = radius/(km_per_degree*center.latitude().to_radians().cos().max(0.0001));
+                let longitude = normalize_longitude(center.longitude());
+                let (west,east) = if longitude_delta>=180.0 {
+                    (-180.0,180.0)
+                } else {
+                    (longitude-longitude_delta,longitude+longitude_delta)
+                };
+                (west,(center.latitude()-latitude_delta).max(-90.0),east,(center.latitude()+latitude_delta).min(90.0))
+            }
+            GeoSearch::Box { south_west, north_east, .. } => (south_west.longitude(),south_west.latitude(),north_east.longitude(),north_east.latitude()),
+        };
+        // West in [-180,180), east in (-180,180]
+        let (west,east) = if east-west>=360.0 { (-180.0,180.0) } else { (normalize_longitude(west),-normalize_longitude(-east)) };
+        let mut ret = if west<=east {
+            vec![(west,south,east,north)]
+        } else {
+            vec![(west,south,180.0,north),(-180.0,south,east,north)]
+        };
+        if self.globe_q()!=EARTH_Q {
+            let shifted: Vec<_> = ret.iter().filter(|(west,..)|*west<0.0).map(|(west,south,east,north)|(west+360.0,*south,east.min(0.0)+360.0,*north)).collect();
+            ret.extend(shifted);
+        }
+        ret
+    }
+
+    /// Condition on a POINT column and its globe column
+    pub fn sql_condition(&self, point: &str, globe: &str) -> String {
+        let contains: Vec<String> = self.bounding_boxes().iter().map(|(west,south,east,north)|
+            format!("MBRContains(ST_GeomFromText('Polygon(({west} {south},{east} {south},{east} {north},{west} {north},{west} {south}))'),{point})")
+        ).collect();
+        let contains = match contains.len() {
+            1 => contains.join(""),
+            _ => format!("({})",contains.join(" OR ")),
+        };
+        let mut ret = format!("{globe}={} AND {contains}",self.globe_q());
+        if let (Some(distance),GeoSearch::Around { radius, .. }) = (self.sql_distance(point),self) {
+            ret += &format!(" AND {distance}<={radius}");
+        }
+        ret
+    }
+
+    /// Distance in km of a POINT column from the center of wikibase:around
+    pub fn sql_distance(&self, point: &str) -> Option<String> {
+        match self {
+            GeoSearch::Around { center, globe_q, .. } => {
+                let center = LatLon::new(center.latitude(),normalize_longitude(center.longitude()),*globe_q);
+                Some(format!("ST_Distance_Sphere({},ST_GeomFromText('{}'),{})/1000",sql_normalized_point(point),center.to_wkt(),globe_radius(*globe_q)*1000.0))
+            }
+            GeoSearch::Box { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounding_box() {
+        let degree = globe_radius(EARTH_Q)*std::f64::consts::PI/180.0;
+        let around = GeoSearch::Around { center: LatLon::new(0.0,10.0,EARTH_Q), radius: degree, globe_q: EARTH_Q };
+        let boxes = around.bounding_boxes();
+        let (west,south,east,north) = boxes[0];
+        assert_eq!(boxes.len(),1);
+        assert!((west-9.0).abs()<1e-9 && (east-11.0).abs()<1e-9);
+        assert!((south+1.0).abs()<1e-9 && (north-1.0).abs()<1e-9);
+        let polar = GeoSearch::Around { center: LatLon::new(89.9,0.0,EARTH_Q), radius: 100.0, globe_q: EARTH_Q };
+        assert_eq!(polar.bounding_boxes(),vec![(-180.0,polar.bounding_boxes()[0].1,180.0,90.0)]);
+        let moon = GeoSearch::Box { south_west: LatLon::new(1.0,2.0,405), north_east: LatLon::new(3.0,4.0,405), globe_q: 405 };
+        assert_eq!(moon.sql_condition("`v0`","`v1`"),"`v1`=405 AND MBRContains(ST_GeomFromText('Polygon((2 1,4 1,4 3,2 3,2 1))'),`v0`)");
+        assert!(moon.sql_distance("`v0`").is_none());
+    }
+
+    #[test]
+    fn test_antimeridian() {
+        let contains = |boxes: &[(f64,f64,f64,f64)], longitude: f64, latitude: f64| boxes.iter().any(|(west,south,east,north)|(*west..=*east).contains(&longitude) && (*south..=*north).contains(&latitude));
+        let around = GeoSearch::Around { center: LatLon::new(0.0,179.5,EARTH_Q), radius: 100.0, globe_q: EARTH_Q };
+        let boxes = around.bounding_boxes();
+        assert_eq!(boxes.len(),2);
+        assert!(contains(&boxes,-179.8,0.0) && contains(&boxes,179.0,0.0) && !contains(&boxes,0.0,0.0));
+        assert!(around.sql_condition("`v0`","`v1`").starts_with("`v1`=2 AND (MBRContains(ST_GeomFromText('Polygon((178.") );
+        // cornerWest east of cornerEast
+        let pacific = GeoSearch::Box { south_west: LatLon::new(-10.0,170.0,EARTH_Q), north_east: LatLon::new(10.0,-170.0,EARTH_Q), globe_q: EARTH_Q };
+        assert_eq!(pacific.bounding_boxes(),vec![(170.0,-10.0,180.0,10.0),(-180.0,-10.0,-170.0,10.0)]);
+        assert_eq!(pacific.sql_condition("`v0`","`v1`"),"`v1`=2 AND (MBRContains(ST_GeomFromText('Polygon((170 -10,180 -10,180 10,170 10,170 -10))'),`v0`) OR MBRContains(ST_GeomFromText('Polygon((-180 -10,-170 -10,-170 10,-180 10,-180 -10))'),`v0`))");
+        let world = GeoSearch::Box { south_west: LatLon::new(-90.0,-180.0,EARTH_Q), north_east: LatLon::new(90.0,180.0,EARTH_Q), globe_q: EARTH_Q };
+        assert_eq!(world.bounding_boxes(),vec![(-180.0,-90.0,180.0,90.0)]);
+        // Mars longitudes from 0 to 360
+        let mars = GeoSearch::Around { center: LatLon::new(73.0,336.0,111), radius: 10.0, globe_q: 111 };
+        let boxes = mars.bounding_boxes();
+        assert!(contains(&boxes,336.0,73.0) && contains(&boxes,-24.0,73.0));
+        assert_eq!(mars.sql_distance("`v0`").unwrap(),"ST_Distance_Sphere(Point(MOD(ST_X(`v0`)+540,360)-180,ST_Y(`v0`)),ST_GeomFromText('Point(-24 73)'),3389500)/1000");
+    }
+}
@@ -0,0 +1,505 @@
+use std::collections::HashSet;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use regex::Regex;
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use crate::{element::Element, element_type::ElementType, entity::Entity, entity_statement::EntityStatement, error::WDQSErr, date_time::{DateTime, JULIAN_Q, PRECISION_DAY}, decimal::Decimal, lat_lon::{LatLon, EARTH_Q}, quantity::UNIT_ONE_Q, text_id::TextId, uuid::{UUID32, UUID40}, value_node::{ValueNode, Triple}};
+
+lazy_static! {
+    static ref RE_TIME: Regex = Regex::new(r#"^([+-]?)(\d+)-(\d{2})-(\d{2})T(\d{2}:\d{2}:\d{2})Z$"#).expect("RE_TIME does not parse");
+}
+
+/// Wiki groups by site ID suffix; "wiki" last, as the others end with it, too
+const SITE_GROUPS: [(&str,&str); 8] = [
+    ("wikivoyage","wikivoyage"),
+    ("wikiquote","wikiquote"),
+    ("wikisource","wikisource"),
+    ("wikibooks","wikibooks"),
+    ("wikinews","wikinews"),
+    ("wikiversity","wikiversity"),
+    ("wiktionary","wiktionary"),
+    ("wiki","wikipedia"),
+];
+
+/// The value of a snak; for time, quantity and coordinate values also the value node
+enum SnakValue {
+    Value(Element,Option<Element>),
+    SomeValue(Element),
+    NoValue,
+}
+
+/// Parses one line of a JSON dump, which holds one entity; the `[` and `]` lines have no triples
+pub fn parse_line(line: &str) -> Result<Vec<Triple>,WDQSErr> {
+    let line = line.trim().trim_end_matches(',');
+    if line.is_empty() || line=="[" || line=="]" {
+        return Ok(vec![]);
+    }
+    let json: Value = serde_json::from_str(line)?;
+    JsonEntity::new(&json)?.triples(&json)
+}
+
+/// Maps an entity of a Wikibase JSON dump to the triples of the Wikibase RDF mapping.
+/// Value nodes are named by a hash of their JSON value, not by the hash used in RDF dumps, and normalized values (`psn:`, `wdtn:` etc) are not created.
+/// Snaks with value types that cannot be stored are left out.
+struct JsonEntity {
+    id: String,
+    subject: Element,
+    triples: Vec<Triple>,
+    nodes: HashSet<String>, // Value nodes and references that were added already
+}
+
+impl JsonEntity {
+    fn new(json: &Value) -> Result<Self,WDQSErr> {
+        let id = json["id"].as_str().ok_or_else(||WDQSErr::ParserError("JSON dump: Entity without ID".into()))?;
+        let subject = entity(id).ok_or_else(||WDQSErr::ParserError(format!("JSON dump: Bad entity ID {id}")))?;
+        Ok(Self { id: id.to_string(), subject, triples: vec![], nodes: HashSet::new() })
+    }
+
+    fn triples(mut self, json: &Value) -> Result<Vec<Triple>,WDQSErr> {
+        self.add_data(json);
+        self.add_type(json);
+        self.add_terms(json);
+        self.add_sitelinks(json);
+        self.add_claims(json)?;
+        Ok(self.triples)
+    }
+
+    fn add(&mut self, s: &Element, p: Element, o: Element) {
+        self.triples.push((s.to_owned(),p,o));
+    }
+
+    /// The data node, <https://www.wikidata.org/wiki/Special:EntityData/ID>, with revision, modification time and counts
+    fn add_data(&mut self, json: &Value) {
+        let data = Element::WikiPage((TextId::new("www.wikidata.org"),TextId::new(&format!("Special:EntityData/{}",self.id))));
+        self.add(&data, Element::W3RdfSyntaxNsType, schema("Dataset"));
+        self.add(&data, schema("about"), self.subject.to_owned());
+        if let Some(revision) = json["lastrevid"].as_i64() {
+            self.add(&data, schema("version"), Element::Int(revision));
+        }
+        if let Some(modified) = json["modified"].as_str().and_then(DateTime::from_str) {
+            self.add(&data, schema("dateModified"), Element::DateTime(*modified));
+        }
+        let statements: Vec<&Value> = values(claims(json)).filter_map(|statements|statements.as_array()).flatten().collect();
+        let identifiers = statements.iter().filter(|statement|statement["mainsnak"]["datatype"]=="external-id").count();
+        self.add(&data, ontology("statements"), Element::Int(statements.len() as i64));
+        self.add(&data, ontology("identifiers"), Element::Int(identifiers as i64));
+        if let Some(sitelinks) = json["sitelinks"].as_object() {
+            self.add(&data, ontology("sitelinks"), Element::Int(sitelinks.len() as i64));
+        }
+    }
+
+    /// Entity type; for properties, also the datatype and the predicates used for them
+    fn add_type(&mut self, json: &Value) {
+        if let Some(entity_type) = json["type"].as_str() {
+            self.add(&self.subject.to_owned(), Element::W3RdfSyntaxNsType, ontology(&camel_case(entity_type)));
+        }
+        let (datatype,Element::Entity(property)) = (json["datatype"].as_str(),self.subject.to_owned()) else { return };
+        let Some(datatype) = datatype else { return };
+        let subject = self.subject.to_owned();
+        let id = self.id.to_owned();
+        self.add(&subject, ontology("propertyType"), ontology(&camel_case(datatype)));
+        let predicates = [
+            ("directClaim",Element::PropertyDirect(id.to_owned())),
+            ("claim",Element::Property(property)),
+            ("statementProperty",Element::PropertyStatement(id.to_owned())),
+            ("statementValue",Element::PropertyStatementValue(id.to_owned())),
+            ("qualifier",Element::PropertyQualifier(id.to_owned())),
+            ("qualifierValue",Element::PropertyQualifierValue(id.to_owned())),
+            ("reference",Element::PropertyReference(id.to_owned())),
+            ("referenceValue",Element::PropertyReferenceValue(id.to_owned())),
+            ("novalue",novalue(&id)),
+        ];
+        for (predicate,object) in predicates {
+            self.add(&subject, ontology(predicate), object);
+        }
+    }
+
+    /// Labels, descriptions and aliases
+    fn add_terms(&mut self, json: &Value) {
+        let subject = self.subject.to_owned();
+        for label in values(&json["labels"]).filter_map(text_in_language) {
+            for predicate in [Element::RdfSchemaLabel,Element::W3SkosCorePrefLabel,schema("name")] {
+                self.add(&subject, predicate, label.to_owned());
+            }
+        }
+        for description in values(&json["descriptions"]).filter_map(text_in_language) {
+            self.add(&subject, schema("description"), description);
+        }
+        let aliases = values(&json["aliases"]).filter_map(|aliases|aliases.as_array()).flatten();
+        for alias in aliases.filter_map(text_in_language) {
+            self.add(&subject, Element::W3SkosCoreAltLabel, alias);
+        }
+    }
+
+    fn add_sitelinks(&mut self, json: &Value) {
+        for sitelink in values(&json["sitelinks"]) {
+            let (Some(site),Some(title)) = (sitelink["site"].as_str(),sitelink["title"].as_str()) else { continue };
+            let Some((server,language,group)) = site_server(site) else { continue };
+            let page = Element::WikiPage((TextId::new(&server),TextId::new(&title.replace(' ',"_"))));
+            let site_root = Element::Url(TextId::new(&format!("https://{server}/")));
+            self.add(&page, Element::W3RdfSyntaxNsType, schema("Article"));
+            self.add(&page, schema("about"), self.subject.to_owned());
+            self.add(&page, schema("inLanguage"), Element::Text(TextId::new(&language)));
+            self.add(&page, schema("isPartOf"), site_root.to_owned());
+            self.add(&page, schema("name"), Element::TextInLanguage((TextId::new(title),TextId::new(&language))));
+            for badge in sitelink["badges"].as_array().into_iter().flatten().filter_map(|badge|entity(badge.as_str()?)) {
+                self.add(&page, ontology("badge"), badge);
+            }
+            self.add(&site_root, ontology("wikiGroup"), Element::Text(TextId::new(&group)));
+        }
+    }
+
+    fn add_claims(&mut self, json: &Value) -> Result<(),WDQSErr> {
+        let Some(claims) = claims(json).as_object() else { return Ok(()) };
+        for (property,statements) in claims {
+            let statements = statements.as_array().map(|a|a.as_slice()).unwrap_or_default();
+            // Truthy statements are the preferred ones, or else the normal ones
+            let best_rank = match statements.iter().any(|statement|statement["rank"]=="preferred") {
+                true => "preferred",
+                false => "normal",
+            };
+            for statement in statements {
+                self.add_statement(property, statement, best_rank)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_statement(&mut self, property: &str, statement: &Value, best_rank: &str) -> Result<(),WDQSErr> {
+        let id = statement["id"].as_str().unwrap_or_default();
+        let node = id.split_once('$')
+            .and_then(|(entity,uuid)|EntityStatement::from_str(&format!("{entity}-{uuid}")))
+            .map(|es|Element::EntityStatement(*es))
+            .ok_or_else(||WDQSErr::ParserError(format!("JSON dump: Bad statement ID '{id}' in {}",self.id)))?;
+        let p = Entity::from_str(property).ok_or_else(||WDQSErr::ParserError(format!("JSON dump: Bad property {property} in {}",self.id)))?;
+        let subject = self.subject.to_owned();
+        let rank = statement["rank"].as_str().unwrap_or("normal");
+        let is_best = rank==best_rank;
+        self.add(&subject, Element::Property(*p), node.to_owned());
+        self.add(&node, Element::W3RdfSyntaxNsType, ontology("Statement"));
+        if is_best {
+            self.add(&node, Element::W3RdfSyntaxNsType, ontology("BestRank"));
+        }
+        self.add(&node, ontology("rank"), ontology(&format!("{}Rank",camel_case(rank))));
+        match self.snak_value(&node, &statement["mainsnak"]) {
+            Some(SnakValue::Value(value,value_node)) => {
+                if is_best {
+                    self.add(&subject, Element::PropertyDirect(property.to_string()), value.to_owned());
+                }
+                self.add(&node, Element::PropertyStatement(property.to_string()), value);
+                if let Some(value_node) = value_node {
+                    self.add(&node, Element::PropertyStatementValue(property.to_string()), value_node);
+                }
+            }
+            Some(SnakValue::SomeValue(blank_node)) => {
+                if is_best {
+                    self.add(&subject, Element::PropertyDirect(property.to_string()), blank_node.to_owned());
+                }
+                self.add(&node, Element::PropertyStatement(property.to_string()), blank_node);
+            }
+            Some(SnakValue::NoValue) => {
+                if is_best {
+                    self.add(&subject, Element::W3RdfSyntaxNsType, novalue(property));
+                }
+                self.add(&node, Element::W3RdfSyntaxNsType, novalue(property));
+            }
+            None => {}
+        }
+        for qualifier in values(&statement["qualifiers"]).filter_map(|snaks|snaks.as_array()).flatten() {
+            self.add_snak(&node, qualifier, Element::PropertyQualifier, Element::PropertyQualifierValue);
+        }
+        for reference in statement["references"].as_array().into_iter().flatten() {
+            let Some(hash) = reference["hash"].as_str() else { continue };
+            let Some(uuid) = UUID40::from_str(hash) else { continue };
+            let reference_node = Element::Reference(*uuid);
+            self.add(&node, Element::WasDerivedFrom, reference_node.to_owned());
+            if !self.nodes.insert(hash.to_string()) {
+                continue;
+            }
+            for snak in values(&reference["snaks"]).filter_map(|snaks|snaks.as_array()).flatten() {
+                self.add_snak(&reference_node, snak, Element::PropertyReference, Element::PropertyReferenceValue);
+            }
+        }
+        Ok(())
+    }
+
+    /// A qualifier or reference snak
+    fn add_snak(&mut self, subject: &Element, snak: &Value, simple: fn(String) -> Element, full: fn(String) -> Element) {
+        let Some(property) = snak["property"].as_str() else { return };
+        match self.snak_value(subject, snak) {
+            Some(SnakValue::Value(value,value_node)) => {
+                self.add(subject, simple(property.to_string()), value);
+                if let Some(value_node) = value_node {
+                    self.add(subject, full(property.to_string()), value_node);
+                }
+            }
+            Some(SnakValue::SomeValue(blank_node)) => self.add(subject, simple(property.to_string()), blank_node),
+            Some(SnakValue::NoValue) => self.add(subject, Element::W3RdfSyntaxNsType, novalue(property)),
+            None => {}
+        }
+    }
+
+    /// The value of a snak of `subject`; None if the value cannot be stored
+    fn snak_value(&mut self, subject: &Element, snak: &Value) -> Option<SnakValue> {
+        match snak["snaktype"].as_str()? {
+            "novalue" => return Some(SnakValue::NoValue),
+            // A blank node per snak and subject, so unknown values do not become the same
+            "somevalue" => return Some(SnakValue::SomeValue(Element::BlankNode(TextId::new(&hash(&format!("{} {snak}",subject.to_iri()?)))))),
+            _ => {}
+        }
+        let datavalue = &snak["datavalue"];
+        let value = &datavalue["value"];
+        match datavalue["type"].as_str()? {
+            "wikibase-entityid" => Some(SnakValue::Value(entity(&entity_id(value)?)?,None)),
+            "string" => Some(SnakValue::Value(string_value(snak["datatype"].as_str().unwrap_or("string"), value.as_str()?)?,None)),
+            "monolingualtext" => Some(SnakValue::Value(text_in_language(value)?,None)),
+            "time" => self.time_value(value),
+            "quantity" => self.quantity_value(value),
+            "globecoordinate" => self.coordinate_value(value),
+            _ => None,
+        }
+    }
+
+    fn time_value(&mut self, value: &Value) -> Option<SnakValue> {
+        let precision = u8::try_from(value["precision"].as_u64()?).ok()?;
+        let timezone = value["timezone"].as_i64().unwrap_or(0);
+        let calendar = entity_iri(value["calendarmodel"].as_str()?)?;
+        let julian = matches!(calendar,Element::Entity(Entity::Item(JULIAN_Q)));
+        let date_time = DateTime::from_str(&clean_time(value["time"].as_str()?, precision, julian)?)?;
+        let value_node = self.value_node(value, vec![
+            (Element::W3RdfSyntaxNsType,ontology("TimeValue")),
+            (ontology("timeValue"),Element::DateTime(*date_time.to_owned())),
+            (ontology("timePrecision"),Element::Int(precision as i64)),
+            (ontology("timeTimezone"),Element::Int(timezone)),
+            (ontology("timeCalendarModel"),calendar),
+        ]);
+        Some(SnakValue::Value(Element::DateTime(*date_time),value_node))
+    }
+
+    fn quantity_value(&mut self, value: &Value) -> Option<SnakValue> {
+        let number = |name: &str| Some(Element::Decimal(*Decimal::from_str(value[name].as_str()?)?));
+        let amount = number("amount")?;
+        let unit = match value["unit"].as_str()? {
+            "1" => Element::Entity(Entity::Item(UNIT_ONE_Q)),
+            unit => entity_iri(unit)?,
+        };
+        let mut parts = vec![(Element::W3RdfSyntaxNsType,ontology("QuantityValue")),(ontology("quantityAmount"),amount.to_owned())];
+        if let (Some(upper),Some(lower)) = (number("upperBound"),number("lowerBound")) {
+            parts.push((ontology("quantityUpperBound"),upper));
+            parts.push((ontology("quantityLowerBound"),lower));
+        }
+        parts.push((ontology("quantityUnit"),unit));
+        let value_node = self.value_node(value, parts);
+        Some(SnakValue::Value(amount,value_node))
+    }
+
+    fn coordinate_value(&mut self, value: &Value) -> Option<SnakValue> {
+        let (latitude,longitude) = (value["latitude"].as_f64()?,value["longitude"].as_f64()?);
+        let globe = match value["globe"].as_str() {
+            Some(globe) => entity_iri(globe)?,
+            None => Element::Entity(Entity::Item(EARTH_Q)),
+        };
+        let Element::Entity(Entity::Item(globe_q)) = globe else { return None };
+        let mut parts = vec![
+            (Element::W3RdfSyntaxNsType,ontology("GlobecoordinateValue")),
+            (ontology("geoLatitude"),Element::Decimal(Decimal::from_f64(latitude)?)),
+            (ontology("geoLongitude"),Element::Decimal(Decimal::from_f64(longitude)?)),
+        ];
+        if let Some(precision) = value["precision"].as_f64().and_then(Decimal::from_f64) {
+            parts.push((ontology("geoPrecision"),Element::Decimal(precision)));
+        }
+        parts.push((ontology("geoGlobe"),globe));
+        let value_node = self.value_node(value, parts);
+        Some(SnakValue::Value(Element::LatLon(LatLon::new(latitude, longitude, globe_q)),value_node))
+    }
+
+    /// Adds the triples of a value node, unless the entity has the same value already, and returns the node
+    fn value_node(&mut self, value: &Value, parts: Vec<(Element,Element)>) -> Option<Element> {
+        let hash = hash(&value.to_string());
+        let node = Element::Value(*UUID32::from_str(&hash[..32])?);
+        if self.nodes.insert(hash) {
+            let mut value_node = ValueNode::default();
+            for (p,o) in parts {
+                value_node.add((node.to_owned(),p,o));
+            }
+            self.triples.append(&mut value_node.flush().unwrap_or_default());
+        }
+        Some(node)
+    }
+}
+
+fn ontology(s: &str) -> Element {
+    Element::WikibaseOntology(s.to_string())
+}
+
+fn schema(s: &str) -> Element {
+    Element::SchemaOrg(s.to_string())
+}
+
+fn entity(id: &str) -> Option<Element> {
+    Some(Element::Entity(*Entity::from_str(id)?))
+}
+
+fn entity_iri(iri: &str) -> Option<Element> {
+    entity(iri.strip_prefix("http://www.wikidata.org/entity/")?)
+}
+
+fn novalue(property: &str) -> Element {
+    Element::Url(TextId::new(&format!("http://www.wikidata.org/prop/novalue/{property}")))
+}
+
+/// Entity types and datatypes as wikibase: names, eg "external-id" => "ExternalId"
+fn camel_case(s: &str) -> String {
+    s.split('-').map(|part| {
+        let mut chars = part.chars();
+        chars.next().map(|c|c.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+    }).collect()
+}
+
+fn hash(s: &str) -> String {
+    format!("{:x}",Sha1::digest(s.as_bytes()))
+}
+
+/// The values of a JSON object, eg labels by language
+fn values(json: &Value) -> impl Iterator<Item=&Value> {
+    json.as_object().into_iter().flat_map(|object|object.values())
+}
+
+/// Items have "claims", MediaInfo entities "statements"
+fn claims(json: &Value) -> &Value {
+    match json.get("claims") {
+        Some(claims) => claims,
+        None => &json["statements"],
+    }
+}
+
+/// A term, or a monolingual text value
+fn text_in_language(json: &Value) -> Option<Element> {
+    let text = json["value"].as_str().or(json["text"].as_str())?;
+    Some(Element::TextInLanguage((TextId::new(text),TextId::new(json["language"].as_str()?))))
+}
+
+/// Older dumps have no "id" in entity values
+fn entity_id(value: &Value) -> Option<String> {
+    if let Some(id) = value["id"].as_str() {
+        return Some(id.to_string());
+    }
+    let letter = match value["entity-type"].as_str()? {
+        "item" => "Q",
+        "property" => "P",
+        "lexeme" => "L",
+        _ => return None,
+    };
+    Some(format!("{letter}{}",value["numeric-id"].as_u64()?))
+}
+
+/// String values are IRIs for some datatypes
+fn string_value(datatype: &str, s: &str) -> Option<Element> {
+    let encoded = || utf8_percent_encode(&s.replace(' ',"_"), NON_ALPHANUMERIC).to_string();
+    match datatype {
+        "url" => Element::from_str(s),
+        "commonsMedia" => Element::from_str(&format!("http://commons.wikimedia.org/wiki/Special:FilePath/{}",encoded())),
+        "geo-shape" | "tabular-data" => Element::from_str(&format!("http://commons.wikimedia.org/data/main/{}",encoded())),
+        _ => Some(Element::Text(TextId::new(s))),
+    }
+}
+
+/// Server, language and wiki group of a site ID, eg "enwikivoyage" => ("en.wikivoyage.org","en","wikivoyage")
+fn site_server(site: &str) -> Option<(String,String,String)> {
+    let special = match site {
+        "commonswiki" => Some(("commons.wikimedia.org","commons")),
+        "specieswiki" => Some(("species.wikimedia.org","species")),
+        "metawiki" => Some(("meta.wikimedia.org","meta")),
+        "wikidatawiki" => Some(("www.wikidata.org","wikidata")),
+        "mediawikiwiki" => Some(("www.mediawiki.org","mediawiki")),
+        "sourceswiki" => Some(("wikisource.org","sources")),
+        "wikimaniawiki" => Some(("wikimania.wikimedia.org","wikimania")),
+        "wikifunctionswiki" => Some(("www.wikifunctions.org","wikifunctions")),
+        _ => None,
+    };
+    if let Some((server,group)) = special {
+        return Some((server.to_string(),"en".to_string(),group.to_string()));
+    }
+    let (language,group) = SITE_GROUPS.iter().find_map(|(suffix,group)|Some((site.strip_suffix(suffix)?,group)))?;
+    let language = match language.replace('_',"-").as_str() {
+        "" => return None,
+        "be-x-old" => "be-tarask".to_string(),
+        language => language.to_string(),
+    };
+    Some((format!("{language}.{group}.org"),language,group.to_string()))
+}
+
+/// The xsd:dateTime of a Wikibase time as in the RDF mapping: without '+', with month and day 00 as 01,
+/// and days in the Julian calendar converted to the Gregorian calendar
+fn clean_time(time: &str, precision: u8, julian: bool) -> Option<String> {
+    let caps = RE_TIME.captures(time)?;
+    let mut year = caps[2].parse::<i64>().ok()?;
+    if &caps[1]=="-" {
+        year = -year;
+    }
+    let mut month = caps[3].parse::<i64>().ok()?.max(1);
+    let mut day = caps[4].parse::<i64>().ok()?.max(1);
+    if julian && precision>=PRECISION_DAY {
+        (year,month,day) = gregorian_from_julian(year, month, day);
+    }
+    let year = match year<0 {
+        true => format!("-{:04}",year.unsigned_abs()),
+        false => format!("{year:04}"),
+    };
+    Some(format!("{year}-{month:02}-{day:02}T{}Z",&caps[5]))
+}
+
+/// Converts via the Julian day number
+fn gregorian_from_julian(year: i64, month: i64, day: i64) -> (i64,i64,i64) {
+    let a = (14-month)/12;
+    let y = year+4800-a;
+    let m = month+12*a-3;
+    let jdn = day + (153*m+2)/5 + 365*y + y.div_euclid(4) - 32083;
+    let a = jdn+32044;
+    let b = (4*a+3).div_euclid(146097);
+    let c = a-(146097*b).div_euclid(4);
+    let d = (4*c+3)/1461;
+    let e = c-1461*d/4;
+    let m = (5*e+2)/153;
+    (100*b+d-4800+m/10, m+3-12*(m/10), e-(153*m+2)/5+1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entity() {
+        let line = r#"{"type":"item","id":"Q42","lastrevid":123,"modified":"2024-01-02T03:04:05Z",
+            "labels":{"en":{"language":"en","value":"Douglas Adams"}},
+            "aliases":{"en":[{"language":"en","value":"DNA"}]},
+            "sitelinks":{"enwiki":{"site":"enwiki","title":"Douglas Adams","badges":["Q17437796"]}},
+            "claims":{
+                "P31":[{"id":"Q42$F078E5B3-F9A8-480E-B7AC-D97778CBBEF9","rank":"normal","type":"statement",
+                    "mainsnak":{"snaktype":"value","property":"P31","datatype":"wikibase-item","datavalue":{"type":"wikibase-entityid","value":{"entity-type":"item","numeric-id":5}}},
+                    "references":[{"hash":"fa278ebfc458360e5aed63d5058cca83c46134f1","snaks":{"P248":[{"snaktype":"value","property":"P248","datavalue":{"type":"wikibase-entityid","value":{"id":"Q36578"}}}]}}]}],
+                "P569":[
+                    {"id":"Q42$D8404CDA-25E4-4334-AF13-A3290BCD9C0F","rank":"preferred","mainsnak":{"snaktype":"value","property":"P569","datavalue":{"type":"time","value":{"time":"+1582-10-05T00:00:00Z","timezone":0,"precision":11,"calendarmodel":"http://www.wikidata.org/entity/Q1985786"}}},
+                        "qualifiers":{"P1480":[{"snaktype":"novalue","property":"P1480"}]}},
+                    {"id":"Q42$65EA9C32-B26C-469B-84FE-FC612B71D159","rank":"normal","mainsnak":{"snaktype":"somevalue","property":"P569"}}],
+                "P2048":[{"id":"Q42$1d7d0ea9-412f-8b5b-ba8d-405ab9ecf026","rank":"normal","mainsnak":{"snaktype":"value","property":"P2048","datavalue":{"type":"quantity","value":{"amount":"+1.96","unit":"http://www.wikidata.org/entity/Q11573"}}}}]}}"#;
+        let triples: Vec<String> = parse_line(&format!("{},",line.replace('\n',""))).unwrap().iter()
+            .map(|(s,p,o)|[s,p,o].iter().map(|e|e.to_sparql_value().value().to_string()).collect::<Vec<_>>().join(" "))
+            .collect();
+        let has = |s: &str| triples.iter().any(|t|t==s);
+        assert!(has("http://www.wikidata.org/entity/Q42 http://www.w3.org/2000/01/rdf-schema#label Douglas Adams"));
+        assert!(has("http://www.wikidata.org/entity/Q42 http://www.w3.org/2004/02/skos/core#altLabel DNA"));
+        assert!(has("https://www.wikidata.org/wiki/Special:EntityData/Q42 http://wikiba.se/ontology#statements 4"));
+        assert!(has("https://en.wikipedia.org/wiki/Douglas_Adams http://wikiba.se/ontology#badge http://www.wikidata.org/entity/Q17437796"));
+        assert!(has("http://www.wikidata.org/entity/Q42 http://www.wikidata.org/prop/direct/P31 http://www.wikidata.org/entity/Q5"));
+        assert!(has("http://www.wikidata.org/reference/fa278ebfc458360e5aed63d5058cca83c46134f1 http://www.wikidata.org/prop/reference/P248 http://www.wikidata.org/entity/Q36578"));
+        // Julian to Gregorian; only the preferred statement is truthy
+        assert!(has("http://www.wikidata.org/entity/Q42 http://www.wikidata.org/prop/direct/P569 1582-10-15T00:00:00Z"));
+        assert_eq!(triples.iter().filter(|t|t.contains("/prop/direct/P569")).count(),1);
+        assert!(has("http://www.wikidata.org/entity/statement/Q42-D8404CDA-25E4-4334-AF13-A3290BCD9C0F http://www.w3.org/1999/02/22-rdf-syntax-ns#type http://www.wikidata.org/prop/novalue/P1480"));
+        // Quantity value node triples are combined
+        assert!(triples.iter().any(|t|t.ends_with("http://wikiba.se/ontology#quantityValue 1.96 <http://www.wikidata.org/entity/Q11573>")));
+        assert!(!triples.iter().any(|t|t.contains("quantityAmount")));
+        assert!(parse_line("[").unwrap().is_empty());
+    }
+}
@@ -3,10 +3,13 @@ use regex::Regex;
 use crate::{element_type::ElementType, type_part::TypePart, db_operation_cache::DbOperationCacheValue};
 
 lazy_static! {
-    static ref RE_POINT: Regex = Regex::new(r#"^Point\(([+-]?[0-9.]+)\s+([+-]?[0-9.]+)\)$"#).expect("RE_POINT does not parse");
-    static ref RE_POINT_GLOBE: Regex = Regex::new(r#"^<http://www.wikidata.org/entity/Q(\d+)> Point\(([+-]?[0-9.]+)\s+([+-]?[0-9.]+)\)$"#).expect("RE_POINT does not parse");
+    // WKT is "Point(longitude latitude)"; MySQL ST_AsText() writes "POINT(..)" and may use exponents
+    static ref RE_POINT: Regex = Regex::new(r#"^(?i:Point)\(([+-]?[0-9.]+(?:[eE][+-]?\d+)?)\s+([+-]?[0-9.]+(?:[eE][+-]?\d+)?)\)$"#).expect("RE_POINT does not parse");
+    static ref RE_POINT_GLOBE: Regex = Regex::new(r#"^<http://www.wikidata.org/entity/Q(\d+)>\s*(?i:Point)\(([+-]?[0-9.]+(?:[eE][+-]?\d+)?)\s+([+-]?[0-9.]+(?:[eE][+-]?\d+)?)\)$"#).expect("RE_POINT_GLOBE does not parse");
 }
 
+pub const EARTH_Q: u32 = 2;
+
 #[derive(Clone, Debug)]
 pub struct LatLon {
     latitude: f64,
@@ -14,30 +17,58 @@ pub struct LatLon {
     globe_q: u32,
 }
 
+impl LatLon {
+    pub fn new(latitude: f64, longitude: f64, globe_q: u32) -> Self {
+        Self { latitude, longitude, globe_q }
+    }
+
+    pub fn latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    pub fn longitude(&self) -> f64 {
+        self.longitude
+    }
+
+    pub fn globe_q(&self) -> u32 {
+        self.globe_q
+    }
+
+    /// WKT point, longitude first
+    pub fn to_wkt(&self) -> String {
+        format!("Point({} {})", self.longitude, self.latitude)
+    }
+}
+
 impl ElementType for LatLon {
     fn from_str(s: &str) -> Option<Box<Self>> {
-        if let Some(caps) = RE_POINT.captures(&s) {
+        if let Some(caps) = RE_POINT.captures(s) {
             return Some(Box::new(LatLon {
-                latitude: caps.get(1)?.as_str().parse::<f64>().ok()?,
-                longitude: caps.get(2)?.as_str().parse::<f64>().ok()?,
-                globe_q: 2,
+                longitude: caps.get(1)?.as_str().parse::<f64>().ok()?,
+                latitude: caps.get(2)?.as_str().parse::<f64>().ok()?,
+                globe_q: EARTH_Q,
             }));
         }
-        if let Some(caps) = RE_POINT_GLOBE.captures(&s) {
+        if let Some(caps) = RE_POINT_GLOBE.captures(s) {
             return Some(Box::new(LatLon {
-                latitude: caps.get(2)?.as_str().parse::<f64>().ok()?,
-                longitude: caps.get(3)?.as_str().parse::<f64>().ok()?,
+                longitude: caps.get(2)?.as_str().parse::<f64>().ok()?,
+                latitude: caps.get(3)?.as_str().parse::<f64>().ok()?,
                 globe_q: caps.get(1)?.as_str().parse::<u32>().ok()?,
             }));
         }
         None
     }
 
+    /// Either the single value of `Element::sql_expression`, or the point as WKT and the globe Q-id
     fn from_sql_values(name:&str, value: &Vec<String>) -> Option<Box<Self>> {
-        match name {
-            "LatLon" => LatLon::from_str(&value[0].parse::<String>().ok()?), // TODO globe
-            _ => None,
+        if name!="LatLon" {
+            return None;
+        }
+        let mut ret = LatLon::from_str(value.first()?)?;
+        if let Some(globe_q) = value.get(1) {
+            ret.globe_q = globe_q.parse::<u32>().ok()?;
         }
+        Some(ret)
     }
 
     fn get_type_parts(&self) -> Vec<crate::type_part::TypePart>  {
@@ -46,13 +77,17 @@ impl ElementType for LatLon {
 
     fn values(&self) -> Vec<DbOperationCacheValue> {
         vec![
-            DbOperationCacheValue::Expression(format!("PointFromText(\"{}\")", self.to_string())),
+            DbOperationCacheValue::Expression(format!("PointFromText(\"{}\")", self.to_wkt())),
             DbOperationCacheValue::U32(self.globe_q),
             ]
     }
 
+    /// The wktLiteral value; points not on Earth are prefixed with their globe
     fn to_string(&self) -> String  {
-        format!("Point({} {}) on http://www.wikidata.org/entity/Q{}", self.latitude, self.longitude, self.globe_q)
+        match self.globe_q {
+            EARTH_Q => self.to_wkt(),
+            globe_q => format!("<http://www.wikidata.org/entity/Q{globe_q}> {}", self.to_wkt()),
+        }
     }
 
     fn name(&self) -> &str  {
@@ -64,10 +99,30 @@ impl ElementType for LatLon {
     }
 
     fn to_url(&self) -> String  {
-        self.to_string() // TODO CHECKME FIXME
+        self.to_string()
     }
 
     fn sql_var_from_name(_name: &str, prefix: &str) -> Option<Vec<String>>  {
         Some(vec![format!("{prefix}0"), format!("{prefix}1")])
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let point = LatLon::from_str("Point(13.4 52.5)").unwrap();
+        assert_eq!((point.latitude(),point.longitude(),point.globe_q()),(52.5,13.4,2));
+        assert_eq!(point.to_string(),"Point(13.4 52.5)");
+        let mars = LatLon::from_str("<http://www.wikidata.org/entity/Q111> Point(336 73)").unwrap();
+        assert_eq!((mars.latitude(),mars.longitude(),mars.globe_q()),(73.0,336.0,111));
+        assert_eq!(mars.to_string(),"<http://www.wikidata.org/entity/Q111> Point(336 73)");
+        // As returned by Element::sql_expression
+        let from_sql = LatLon::from_sql_values("LatLon",&vec!["<http://www.wikidata.org/entity/Q405> POINT(-1.5e-5 2)".to_string()]).unwrap();
+        assert_eq!((from_sql.latitude(),from_sql.longitude(),from_sql.globe_q()),(2.0,-1.5e-5,405));
+        let from_sql = LatLon::from_sql_values("LatLon",&vec!["POINT(1 2)".to_string(),"111".to_string()]).unwrap();
+        assert_eq!(from_sql.to_string(),"<http://www.wikidata.org/entity/Q111> Point(1 2)");
+        assert!(matches!(&point.values()[0],DbOperationCacheValue::Expression(e) if e=="PointFromText(\"Point(13.4 52.5)\")"));
+    }
+}
@@ -5,29 +5,46 @@ extern crate tokio;
 
 use app_state::AppState;
 use error::WDQSErr;
+use parser::InputFormat;
 use query_triples::QueryTriples;
+use sparql_query::SparqlQuery;
+use sparql_results::SparqlResultFormat;
 use std::sync::Arc;
 
 pub mod app_state;
 pub mod app_state_mysql_live;
 pub mod app_state_mysql_stdout;
+pub mod checkpoint;
 pub mod database_table;
 pub mod database_wrapper;
 pub mod date_time;
 pub mod db_operation_cache;
+pub mod decimal;
 pub mod element;
 pub mod element_type;
 pub mod entity;
 pub mod entity_statement;
 pub mod error;
+pub mod geo_search;
+pub mod json_dump;
 pub mod lat_lon;
+pub mod ntriples;
 pub mod parser;
+pub mod quantity;
 pub mod query_part;
 pub mod query_triples;
+pub mod reject_file;
+pub mod sparql_expression;
+pub mod sparql_parser;
+pub mod sparql_query;
+pub mod sparql_results;
+pub mod sparql_server;
 pub mod string_storage;
 pub mod text_id;
+pub mod turtle;
 pub mod type_part;
 pub mod uuid;
+pub mod value_node;
 
 use clap::{Arg, Command};
 use serde_json::json;
@@ -43,7 +60,60 @@ async fn main() -> Result<(), WDQSErr> {
                 .short('i')
                 .long("import")
                 .value_name("FILE")
-                .help("Import triples from FILE (NT dump, plain text or gz/bzip)")
+                .help("Import triples from FILE, or from stdin for '-' (plain text, gzip, bzip2, zstd or xz)")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("input-format")
+                .long("input-format")
+                .value_name("FORMAT")
+                .help("Format for --import: nt, nq, ttl or json; default from the file name, or nt")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .help("Continue --import after the last checkpoint of that file")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("input-name")
+                .long("input-name")
+                .value_name("NAME")
+                .help("Store the checkpoints of --import - under NAME; required to --resume from stdin")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("reject-file")
+                .long("reject-file")
+                .value_name("FILE")
+                .help("Append the lines that --import can not parse to FILE, as JSON lines")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("query")
+                .short('q')
+                .long("query")
+                .value_name("FILE")
+                .help("Run the SPARQL SELECT query in FILE")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_name("FORMAT")
+                .help("Output format for --query results: json, xml, csv or tsv")
+                .default_value("json")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("server")
+                .short('s')
+                .long("server")
+                .value_name("PORT")
+                .help("Serve SPARQL queries at http://localhost:PORT/sparql")
+                .value_parser(clap::value_parser!(u16))
                 .num_args(1),
         )
         .arg(
@@ -56,6 +126,17 @@ async fn main() -> Result<(), WDQSErr> {
         )
         .get_matches();
 
+    if let Some(filename) = matches.get_one::<String>("import") {
+        let input_name = matches.get_one::<String>("input-name");
+        // Without a name, the checkpoints of different stdin imports would overwrite each other
+        if filename=="-" && matches.get_flag("resume") && input_name.is_none() {
+            return Err(WDQSErr::String("--resume from stdin requires --input-name".into()));
+        }
+        if filename!="-" && input_name.is_some() {
+            return Err(WDQSErr::String("--input-name is only used with --import -".into()));
+        }
+    }
+
     let mut config = AppState::get_config_from_file("config.json").unwrap();
     if let Some(dbtype) = matches.get_one::<String>("dbtype") {
         config["db_type"] = json!(dbtype);
@@ -63,8 +144,30 @@ async fn main() -> Result<(), WDQSErr> {
     let app = Arc::new(AppState::from_config(&config));
     app.init_from_db().await?;
     if let Some(filename) = matches.get_one::<String>("import") {
-        let parser = parser::Parser::new(app.clone());
-        parser.import_from_file(filename).await?;
+        let format = match matches.get_one::<String>("input-format") {
+            Some(format) => Some(InputFormat::from_name(format).ok_or_else(||WDQSErr::String(format!("Unknown input format '{format}'")))?),
+            None => None,
+        };
+        let resume = matches.get_flag("resume");
+        let mut parser = parser::Parser::new(app.clone());
+        if let Some(reject_file) = matches.get_one::<String>("reject-file") {
+            parser.set_reject_file(reject_file)?;
+        }
+        match filename.as_str() {
+            "-" => {
+                let input_name = matches.get_one::<String>("input-name").map(|name|name.as_str()).unwrap_or("-");
+                parser.import_from_reader(std::io::stdin(), format.unwrap_or_default(), input_name, resume).await?
+            }
+            filename => parser.import_from_file(filename, format, resume).await?,
+        }
+    } else if let Some(port) = matches.get_one::<u16>("server") {
+        let server = sparql_server::SparqlServer::new(app.clone());
+        server.run(*port).await?;
+    } else if let Some(filename) = matches.get_one::<String>("query") {
+        let query = SparqlQuery::parse(&std::fs::read_to_string(filename)?)?;
+        let format = matches.get_one::<String>("format").map(|s|s.as_str()).unwrap_or_default();
+        let format = SparqlResultFormat::from_name(format).ok_or_else(||WDQSErr::String(format!("Unknown result format '{format}'")))?;
+        print!("{}", query.results(&app).await?.serialize(format));
     } else {
         // query
         let mut qt1 = QueryTriples::from_str(&app, "?person", "wdt:P31", "wd:Q5").await?;
@@ -0,0 +1,373 @@
+use nom::{IResult, bytes::complete::{tag, take_while, take_while1}, branch::alt, character::complete::{char, satisfy}, combinator::{opt, map, recognize, eof}, multi::many0, sequence::{preceded, pair}, error::{VerboseError, ErrorKind, ParseError}};
+use crate::{element::Element, error::WDQSErr, text_id::TextId};
+
+type Res<T, U> = IResult<T, U, VerboseError<T>>;
+
+/// An RDF term as written in N-Triples, with escapes decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Iri(String),
+    BlankNode(String),
+    Literal { value: String, datatype: Option<String>, language: Option<String> },
+}
+
+pub type TermTriple = (Term,Term,Term);
+
+/// A triple with the graph label of N-Quads; None is the default graph
+pub type TermQuad = (Term,Term,Term,Option<Term>);
+
+impl Term {
+    pub fn to_element(&self) -> Result<Element,WDQSErr> {
+        let element = match self {
+            Term::Iri(iri) => Element::from_str(iri),
+            Term::BlankNode(label) => Some(Element::BlankNode(TextId::new(label))),
+            Term::Literal { value, datatype, language } => Element::from_literal(value, datatype.as_deref(), language.as_deref()),
+        };
+        element.ok_or_else(||WDQSErr::ParserError(format!("N-Triples: Unsupported term {self:?}")))
+    }
+
+    /// The name of a graph; blank node graph labels keep their `_:` prefix
+    pub fn to_graph_name(&self) -> Result<String,WDQSErr> {
+        match self {
+            Term::Iri(iri) => Ok(iri.to_owned()),
+            Term::BlankNode(label) => Ok(format!("_:{label}")),
+            Term::Literal { .. } => Err(WDQSErr::ParserError(format!("N-Quads: Literal {self:?} can not be a graph label"))),
+        }
+    }
+}
+
+/// Parses one line of an N-Triples document; empty and comment-only lines have no triple
+pub fn parse_line(line: &str) -> Result<Option<TermTriple>,WDQSErr> {
+    parse_statement_line(line, triple, "N-Triples")
+}
+
+/// Parses one line of an N-Quads document; empty and comment-only lines have no quad
+pub fn parse_quad_line(line: &str) -> Result<Option<TermQuad>,WDQSErr> {
+    parse_statement_line(line, quad, "N-Quads")
+}
+
+fn parse_statement_line<T>(line: &str, statement: fn(&str) -> Res<&str, T>, format: &str) -> Result<Option<T>,WDQSErr> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    match line_contents(line, statement) {
+        Ok((_,ret)) => Ok(ret),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            let at: String = e.errors.first().map(|(input,_)|input.chars().take(40).collect()).unwrap_or_default();
+            Err(WDQSErr::ParserError(format!("{format}: Syntax error at '{at}'")))
+        }
+        Err(e) => Err(WDQSErr::ParserError(e.to_string())),
+    }
+}
+
+pub fn error<T>(input: &str) -> Res<&str, T> {
+    Err(nom::Err::Error(VerboseError::from_error_kind(input, ErrorKind::Tag)))
+}
+
+fn ws(input: &str) -> Res<&str, &str> {
+    take_while(|c|c==' ' || c=='\t')(input)
+}
+
+fn comment(input: &str) -> Res<&str, &str> {
+    recognize(preceded(char('#'), take_while(|_|true)))(input)
+}
+
+fn line_contents<T>(input: &str, statement: fn(&str) -> Res<&str, T>) -> Res<&str, Option<T>> {
+    let (input,_) = ws(input)?;
+    let (input,ret) = opt(statement)(input)?;
+    let (input,_) = ws(input)?;
+    let (input,_) = opt(comment)(input)?;
+    let (input,_) = eof(input)?;
+    Ok((input,ret))
+}
+
+fn subject_predicate_object(input: &str) -> Res<&str, TermTriple> {
+    let (input,s) = alt((iri_ref,blank_node_label))(input)?;
+    let (input,_) = ws(input)?;
+    let (input,p) = iri_ref(input)?;
+    let (input,_) = ws(input)?;
+    let (input,o) = alt((iri_ref,blank_node_label,literal))(input)?;
+    Ok((input,(s,p,o)))
+}
+
+fn triple(input: &str) -> Res<&str, TermTriple> {
+    let (input,ret) = subject_predicate_object(input)?;
+    let (input,_) = ws(input)?;
+    let (input,_) = char('.')(input)?;
+    Ok((input,ret))
+}
+
+fn quad(input: &str) -> Res<&str, TermQuad> {
+    let (input,(s,p,o)) = subject_predicate_object(input)?;
+    let (input,_) = ws(input)?;
+    let (input,graph) = opt(alt((iri_ref,blank_node_label)))(input)?;
+    let (input,_) = ws(input)?;
+    let (input,_) = char('.')(input)?;
+    Ok((input,(s,p,o,graph)))
+}
+
+fn hex_value(input: &str, digits: usize) -> Res<&str, char> {
+    let hex: String = input.chars().take(digits).collect();
+    if hex.len()!=digits || !hex.chars().all(|c|c.is_ascii_hexdigit()) {
+        return error(input);
+    }
+    match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+        Some(c) => Ok((&input[digits..],c)),
+        None => error(input),
+    }
+}
+
+/// `\uXXXX` or `\UXXXXXXXX`
+pub fn uchar(input: &str) -> Res<&str, char> {
+    alt((
+        |input| { let (input,_) = tag("\\u")(input)?; hex_value(input, 4) },
+        |input| { let (input,_) = tag("\\U")(input)?; hex_value(input, 8) },
+    ))(input)
+}
+
+/// `\t`, `\b`, `\n`, `\r`, `\f`, `\"`, `\'` or `\\`
+pub fn echar(input: &str) -> Res<&str, char> {
+    let (input,_) = char('\\')(input)?;
+    let (input,c) = satisfy(|c|"tbnrf\"'\\".contains(c))(input)?;
+    let c = match c {
+        't' => '\t',
+        'b' => '\u{8}',
+        'n' => '\n',
+        'r' => '\r',
+        'f' => '\u{c}',
+        c => c,
+    };
+    Ok((input,c))
+}
+
+/// The decoded IRI between `<` and `>`, which can be relative
+pub fn iri_ref_relative(input: &str) -> Res<&str, String> {
+    let (input,_) = char('<')(input)?;
+    let (input,parts) = many0(alt((
+        map(take_while1(|c:char|c>' ' && !"<>\"{}|^`\\".contains(c)),|s:&str|s.to_string()),
+        map(uchar,|c|c.to_string()),
+    )))(input)?;
+    let (input,_) = char('>')(input)?;
+    let iri = parts.concat();
+    // Decoded characters must be allowed in IRIs, too
+    if iri.chars().any(|c|c<=' ' || "<>\"{}|^`\\".contains(c)) {
+        return error(input);
+    }
+    Ok((input,iri))
+}
+
+/// Whether the IRI starts with a scheme
+pub fn is_absolute_iri(iri: &str) -> bool {
+    let scheme = iri.split_once(':').map(|(scheme,_)|scheme).unwrap_or_default();
+    scheme.starts_with(|c:char|c.is_ascii_alphabetic()) && scheme.chars().all(|c|c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+/// `<IRI>`; only absolute IRIs are allowed
+fn iri_ref(input: &str) -> Res<&str, Term> {
+    let (input,iri) = iri_ref_relative(input)?;
+    if !is_absolute_iri(&iri) {
+        return error(input);
+    }
+    Ok((input,Term::Iri(iri)))
+}
+
+pub fn is_pn_chars_base(c: char) -> bool {
+    matches!(c,'A'..='Z' | 'a'..='z' | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}' | '\u{F8}'..='\u{2FF}' | '\u{370}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}' | '\u{2070}'..='\u{218F}' | '\u{2C00}'..='\u{2FEF}' | '\u{3001}'..='\u{D7FF}' | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}' | '\u{10000}'..='\u{EFFFF}')
+}
+
+fn is_pn_chars_u(c: char) -> bool {
+    is_pn_chars_base(c) || c=='_' || c==':'
+}
+
+pub fn is_pn_chars(c: char) -> bool {
+    is_pn_chars_u(c) || c=='-' || c.is_ascii_digit() || c=='\u{B7}' || ('\u{300}'..='\u{36F}').contains(&c) || ('\u{203F}'..='\u{2040}').contains(&c)
+}
+
+/// `_:label`; the label can contain, but not end with, '.'
+pub fn blank_node_label(input: &str) -> Res<&str, Term> {
+    let (input,_) = tag("_:")(input)?;
+    let (_,label) = recognize(pair(satisfy(|c|is_pn_chars_u(c) || c.is_ascii_digit()), take_while(|c|is_pn_chars(c) || c=='.')))(input)?;
+    let label = label.trim_end_matches('.');
+    Ok((&input[label.len()..],Term::BlankNode(label.to_string())))
+}
+
+fn string_literal_quote(input: &str) -> Res<&str, String> {
+    let (input,_) = char('"')(input)?;
+    let (input,parts) = many0(alt((
+        map(take_while1(|c|!"\"\\\n\r".contains(c)),|s:&str|s.to_string()),
+        map(uchar,|c|c.to_string()),
+        map(echar,|c|c.to_string()),
+    )))(input)?;
+    let (input,_) = char('"')(input)?;
+    Ok((input,parts.concat()))
+}
+
+pub fn lang_tag(input: &str) -> Res<&str, &str> {
+    let (input,_) = char('@')(input)?;
+    recognize(pair(take_while1(|c:char|c.is_ascii_alphabetic()), many0(pair(char('-'), take_while1(|c:char|c.is_ascii_alphanumeric())))))(input)
+}
+
+pub fn literal(input: &str) -> Res<&str, Term> {
+    let (input,value) = string_literal_quote(input)?;
+    if let Ok((input,language)) = lang_tag(input) {
+        return Ok((input,Term::Literal { value, datatype: None, language: Some(language.to_string()) }));
+    }
+    if let Ok((input,_)) = tag::<_,_,VerboseError<&str>>("^^")(input) {
+        return match iri_ref(input)? {
+            (input,Term::Iri(datatype)) => Ok((input,Term::Literal { value, datatype: Some(datatype), language: None })),
+            _ => error(input),
+        };
+    }
+    Ok((input,Term::Literal { value, datatype: None, language: None }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sparql_results::SparqlValue, turtle::TurtleReader};
+
+    fn iri(s: &str) -> Term {
+        Term::Iri(s.to_string())
+    }
+
+    fn plain(s: &str) -> Term {
+        Term::Literal { value: s.to_string(), datatype: None, language: None }
+    }
+
+    const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+    const MF: &str = "http://www.w3.org/2001/sw/DataAccess/tests/test-manifest#";
+    const RDFT: &str = "http://www.w3.org/ns/rdftest#";
+
+    /// Runs every entry of the W3C N-Triples 1.1 test suite (https://github.com/w3c/rdf-tests, rdf/rdf11/rdf-n-triples) in tests/ntriples.
+    /// A positive test passes if all lines parse and can be stored, a negative test if a line is rejected.
+    #[test]
+    fn test_w3c_suite() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ntriples");
+        let mut reader = TurtleReader::default();
+        let mut triples = reader.add_line("@base <https://w3c.github.io/rdf-tests/rdf/rdf11/rdf-n-triples/manifest.ttl> .").unwrap();
+        for line in std::fs::read_to_string(dir.join("manifest.ttl")).unwrap().lines() {
+            triples.extend(reader.add_line(line).unwrap());
+        }
+        triples.extend(reader.finish().unwrap());
+        let object = |s: &Term, p: &str| triples.iter().find(|(s2,p2,_)|s2==s && *p2==iri(p)).map(|(_,_,o)|o.to_owned()).unwrap();
+        let (manifest,_,_) = triples.iter().find(|(_,p,o)|*p==iri(&format!("{RDF}type")) && *o==iri(&format!("{MF}Manifest"))).unwrap();
+        let mut list = object(manifest, &format!("{MF}entries"));
+        let mut files = vec![];
+        while list!=iri(&format!("{RDF}nil")) {
+            let entry = object(&list, &format!("{RDF}first"));
+            list = object(&list, &format!("{RDF}rest"));
+            let name = match object(&entry, &format!("{MF}name")) {
+                Term::Literal { value, .. } => value,
+                name => panic!("Test name {name:?}"),
+            };
+            let file = match object(&entry, &format!("{MF}action")) {
+                Term::Iri(action) => action.rsplit('/').next().unwrap().to_string(),
+                action => panic!("{name}: action {action:?}"),
+            };
+            let text = std::fs::read_to_string(dir.join(&file)).unwrap();
+            let results: Vec<_> = text.lines().map(parse_line).collect();
+            match object(&entry, &format!("{RDF}type")) {
+                test_type if test_type==iri(&format!("{RDFT}TestNTriplesPositiveSyntax")) => for (line,result) in text.lines().zip(results) {
+                    let triple = result.unwrap_or_else(|e|panic!("{name} should parse: {line} {e:?}"));
+                    for term in triple.into_iter().flat_map(|(s,p,o)|[s,p,o]) {
+                        assert!(term.to_element().is_ok(),"{name} should be stored: {term:?}");
+                    }
+                }
+                test_type if test_type==iri(&format!("{RDFT}TestNTriplesNegativeSyntax")) => {
+                    assert!(results.iter().any(|result|result.is_err()),"{name} should be rejected");
+                }
+                test_type => panic!("{name}: Unknown test type {test_type:?}"),
+            }
+            files.push(file);
+        }
+        // Every test file is in the manifest
+        let mut all_files: Vec<String> = std::fs::read_dir(&dir).unwrap().map(|entry|entry.unwrap().file_name().to_string_lossy().to_string()).filter(|name|name.ends_with(".nt")).collect();
+        all_files.sort();
+        files.sort();
+        assert_eq!(files,all_files);
+    }
+
+    // Cases that the W3C suite does not cover
+    #[test]
+    fn test_negative_syntax() {
+        let negative = [
+            ("missing final dot","<http://example/s> <http://example/p> <http://example/o>"),
+            ("two final dots","<http://example/s> <http://example/p> <http://example/o> . ."),
+            ("literal subject","\"s\" <http://example/p> <http://example/o> ."),
+            ("blank node predicate","<http://example/s> _:p <http://example/o> ."),
+            ("blank node label starting with -","_:-a <http://example/p> <http://example/o> ."),
+        ];
+        for (name,line) in negative {
+            assert!(parse_line(line).is_err(),"{name} should be rejected: {line}");
+        }
+    }
+
+    #[test]
+    fn test_decoded_terms() {
+        let (s,p,o) = parse_line("_:a.b<http://example/\\u0053>\"\\\"\\u00e9\\U0001F600\\t\\\\\"@en-UK . # x").unwrap().unwrap();
+        assert_eq!(s,Term::BlankNode("a.b".into()));
+        assert_eq!(p,iri("http://example/S"));
+        assert_eq!(o,Term::Literal { value: "\"é😀\t\\".into(), datatype: None, language: Some("en-UK".into()) });
+        let (_,_,o) = parse_line("<http://example/s> <http://example/p> _:o.\r\n").unwrap().unwrap();
+        assert_eq!(o,Term::BlankNode("o".into()));
+        let (_,_,o) = parse_line("<http://example/s> <http://example/p> \"50%25 \\\"quoted\\\"\" .").unwrap().unwrap();
+        assert_eq!(o,plain("50%25 \"quoted\""));
+        assert_eq!(parse_line("  # comment only").unwrap(),None);
+    }
+
+    #[test]
+    fn test_unknown_datatypes() {
+        // Datatypes without their own element type keep their IRI
+        for datatype in ["http://www.w3.org/2001/XMLSchema#byte","http://www.w3.org/2001/XMLSchema#unsignedLong","http://example/custom"] {
+            let (_,_,o) = parse_line(&format!("<http://example/s> <http://example/p> \"123\"^^<{datatype}> .")).unwrap().unwrap();
+            let element = o.to_element().unwrap();
+            assert_eq!(element.name(),"TypedLiteral");
+            assert_eq!(element.to_sparql_value(),SparqlValue::Literal { value: "123".into(), language: None, datatype: Some(datatype.into()) });
+            let sql = Element::sql_expression_from_name("TypedLiteral", "v").unwrap();
+            assert_eq!(sql,"CONCAT((SELECT `value` FROM `texts` WHERE `id`=`v0`),'^^',(SELECT `value` FROM `texts` WHERE `id`=`v1`))");
+            let read = Element::from_sql_values("TypedLiteral", &vec![format!("12^^3^^{datatype}")]);
+            assert_eq!(read.to_sparql_value(),SparqlValue::Literal { value: "12^^3".into(), language: None, datatype: Some(datatype.into()) });
+        }
+        // Doubles, and numbers that do not fit into DECIMAL(65,30), keep their lexical form
+        let big = "1".repeat(40);
+        for (value,datatype) in [("INF","double"),("-INF","double"),("NaN","double"),("1.0E300","double"),("1.0E-31","double"),("1e3","double"),
+                (big.as_str(),"integer"),(big.as_str(),"decimal"),("1.0000000000000000000000000000001","decimal")] {
+            let datatype = format!("http://www.w3.org/2001/XMLSchema#{datatype}");
+            let (_,_,o) = parse_line(&format!("<http://example/s> <http://example/p> \"{value}\"^^<{datatype}> .")).unwrap().unwrap();
+            let element = o.to_element().unwrap();
+            assert_eq!(element.name(),"TypedLiteral");
+            assert_eq!(element.to_sparql_value(),SparqlValue::Literal { value: value.into(), language: None, datatype: Some(datatype) });
+        }
+        let (_,_,o) = parse_line("<http://example/s> <http://example/p> \"-9223372036854775809\"^^<http://www.w3.org/2001/XMLSchema#integer> .").unwrap().unwrap();
+        assert_eq!(o.to_element().unwrap().name(),"Decimal");
+        // Known datatypes with invalid values are still rejected
+        for (value,datatype) in [("x","decimal"),("1.5","integer"),("1,5","double")] {
+            let (_,_,o) = parse_line(&format!("<http://example/s> <http://example/p> \"{value}\"^^<http://www.w3.org/2001/XMLSchema#{datatype}> .")).unwrap().unwrap();
+            assert!(o.to_element().is_err());
+        }
+        let (_,_,o) = parse_line("<http://example/s> <http://example/p> \"x\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .").unwrap().unwrap();
+        assert!(o.to_element().is_err());
+    }
+
+    // Cases from the W3C N-Quads test suite (https://www.w3.org/2013/N-QuadsTests/), by test name
+    #[test]
+    fn test_quads() {
+        let (_,_,_,graph) = parse_quad_line("<http://example/s> <http://example/p> <http://example/o> <http://example/g> .").unwrap().unwrap();
+        assert_eq!(graph,Some(iri("http://example/g")));
+        let (_,_,o,graph) = parse_quad_line("<http://example/s> <http://example/p> \"o\"@en _:g.").unwrap().unwrap();
+        assert_eq!(o,Term::Literal { value: "o".into(), datatype: None, language: Some("en".into()) });
+        assert_eq!(graph.unwrap().to_graph_name().unwrap(),"_:g");
+        let (_,_,_,graph) = parse_quad_line("<http://example/s> <http://example/p> <http://example/o> . # default graph").unwrap().unwrap();
+        assert_eq!(graph,None);
+        let negative = [
+            ("nq-syntax-bad-literal-01","<http://example/s> <http://example/p> <http://example/o> \"o\" ."),
+            ("nq-syntax-bad-literal-02","<http://example/s> <http://example/p> <http://example/o> \"o\"@en ."),
+            ("nq-syntax-bad-uri-01","<http://example/s> <http://example/p> <http://example/o> <g> ."),
+            ("nq-syntax-bad-quint-01","<http://example/s> <http://example/p> <http://example/o> <http://example/g> <http://example/g> ."),
+        ];
+        for (name,line) in negative {
+            assert!(parse_quad_line(line).is_err(),"{name} should be rejected: {line}");
+        }
+        assert!(parse_line("<http://example/s> <http://example/p> <http://example/o> <http://example/g> .").is_err());
+    }
+}
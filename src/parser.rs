@@ -1,164 +1,632 @@
-use std::{io::{self, BufRead, Lines}, fs::File, sync::Arc};
-use nom::{IResult, bytes::complete::{tag, take_until, take_until1}, branch::alt, character::complete::space1, error::{VerboseError, VerboseErrorKind}};
-use tokio::sync::Mutex;
-use crate::{element::Element, app_state::AppState, error::WDQSErr, database_wrapper::DatabaseWrapper, lat_lon::LatLon, element_type::ElementType, date_time::DateTime};
+use std::{io::{self, BufRead, Cursor, Read, Seek, SeekFrom}, fs::File, future::Future, sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}}};
+use futures::future::join_all;
+use tokio::{sync::{mpsc::{self, error::TrySendError}, OwnedSemaphorePermit, Semaphore}, task::JoinHandle};
+use crate::{element::Element, app_state::AppState, checkpoint::{Checkpoint, InputLine, InputLines}, error::WDQSErr, database_wrapper::DatabaseWrapper, json_dump, ntriples::{self, TermTriple}, reject_file::RejectFile, turtle::TurtleReader, value_node::{ValueNode, Triple}};
 use bzip2::read::MultiBzDecoder;
-use flate2::read::GzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
 
-const MAX_CONCURRENT_THREADS: usize = 50000;
+const BUFFER_SIZE: usize = 1024*1024;
+const BATCH_SIZE: usize = 1024*1024; // Bytes of input lines per batch
+const BATCH_TRIPLES: usize = 10000; // Triples per batch of parsed Turtle
+const BATCHES_PER_WORKER: usize = 4;
+const COMPRESSION_SUFFIXES: [&str;4] = ["bz2","gz","zst","xz"];
+
+/// The format of an import, independent of its compression
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InputFormat {
+    #[default]
+    NTriples,
+    NQuads,
+    Turtle,
+    Json,
+}
+
+impl InputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nt" => Some(Self::NTriples),
+            "nq" => Some(Self::NQuads),
+            "ttl" => Some(Self::Turtle),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// The format from the file extension, after any compression suffix; N-Triples if unknown
+    pub fn from_filename(filename: &str) -> Self {
+        let filename = match filename.rsplit_once('.') {
+            Some((name,suffix)) if COMPRESSION_SUFFIXES.contains(&suffix) => name,
+            _ => filename,
+        };
+        filename.rsplit_once('.').and_then(|(_,extension)|Self::from_name(extension)).unwrap_or_default()
+    }
+}
+
+/// A triple with its graph
+type GraphTriple = (Triple,Option<String>);
+
+/// The lines of a batch, the reason for rejecting each line, if any, and the triples
+type ParsedBatch = (Vec<InputLine>,Vec<Option<WDQSErr>>,Vec<GraphTriple>);
+
+/// Work for a parser worker; value nodes do not span batches
+#[derive(Debug)]
+enum Batch {
+    NTriples(Vec<InputLine>),
+    NQuads(Vec<InputLine>),
+    Json(Vec<InputLine>),
+    Triples(Vec<Triple>), // Turtle is parsed by the reader, as prefixes and blank node labels carry over between lines
+}
+
+/// The reader sends batches through bounded channels, one per worker.
+/// Every batch holds a permit until it is done, so the reader waits while the workers are busy.
+struct Pipeline<T> {
+    senders: Vec<mpsc::Sender<(T,OwnedSemaphorePermit)>>,
+    next_worker: AtomicUsize,
+    permits: Arc<Semaphore>,
+    capacity: usize,
+    failed: Arc<AtomicBool>, // Set by a worker before it releases the permit of its failed batch
+    workers: Vec<JoinHandle<Result<(),WDQSErr>>>,
+}
+
+impl<T: Send + 'static> Pipeline<T> {
+    /// Starts the workers, which run `work` for their batches in order, and stop at the first error
+    fn new<F,Fut>(workers: usize, work: F) -> Self
+    where F: Fn(T) -> Fut + Clone + Send + 'static, Fut: Future<Output=Result<(),WDQSErr>> + Send {
+        let workers = workers.max(1);
+        let capacity = workers*BATCHES_PER_WORKER;
+        let failed = Arc::new(AtomicBool::new(false));
+        let (senders,workers) = (0..workers).map(|_| {
+            let (sender, mut receiver) = mpsc::channel::<(T,OwnedSemaphorePermit)>(BATCHES_PER_WORKER);
+            let (work,failed) = (work.clone(),failed.clone());
+            let worker = tokio::spawn(async move {
+                while let Some((batch,permit)) = receiver.recv().await {
+                    let result = work(batch).await;
+                    if result.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    drop(permit);
+                    result?;
+                }
+                Ok(())
+            });
+            (sender,worker)
+        }).unzip();
+        Self {
+            senders,
+            next_worker: AtomicUsize::new(0),
+            permits: Arc::new(Semaphore::new(capacity)),
+            capacity,
+            failed,
+            workers,
+        }
+    }
+
+    /// Waits for room in the pipeline, then queues the batch with the next worker that has room
+    async fn send(&self, batch: T) -> Result<(),WDQSErr> {
+        let permit = self.permits.clone().acquire_owned().await.map_err(|e|WDQSErr::String(e.to_string()))?;
+        let stopped = || WDQSErr::String("Pipeline::send: Parser workers have stopped".into());
+        let first = self.next_worker.fetch_add(1, Ordering::Relaxed);
+        let mut item = (batch,permit);
+        for num in 0..self.senders.len() {
+            match self.senders[(first+num)%self.senders.len()].try_send(item) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(returned)) => item = returned,
+                Err(TrySendError::Closed(_)) => return Err(stopped()),
+            }
+        }
+        // With a permit, some worker has room, unless it has stopped
+        self.senders[first%self.senders.len()].send(item).await.map_err(|_|stopped())
+    }
+
+    /// Waits until all queued batches are done; fails if a worker has stopped
+    async fn wait_until_done(&self) -> Result<(),WDQSErr> {
+        let _permits = self.permits.acquire_many(self.capacity as u32).await.map_err(|e|WDQSErr::String(e.to_string()))?;
+        if self.failed.load(Ordering::SeqCst) {
+            return Err("Pipeline::wait_until_done: Parser workers have stopped".into());
+        }
+        Ok(())
+    }
+
+    /// Lets the workers finish the queued batches; returns the first error of a worker
+    async fn finish(self) -> Result<(),WDQSErr> {
+        drop(self.senders);
+        for result in join_all(self.workers).await {
+            result.map_err(|e|WDQSErr::String(format!("Pipeline::finish: {e}")))??;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Parser {
+    app: Arc<AppState>,
     wrapper: Arc<DatabaseWrapper>,
+    rejects: Arc<RejectFile>,
 }
 
 impl Parser {
     pub fn new(app: Arc<AppState>) -> Self {
         Self {
-            wrapper: Arc::new(DatabaseWrapper::new(app)),
+            wrapper: Arc::new(DatabaseWrapper::new(app.clone())),
+            app,
+            rejects: Arc::new(RejectFile::default()),
         }
     }
 
-    fn parse_line(line: &str) -> Result<(Element,Element,Element),WDQSErr> {
-        type Res<T, U> = IResult<T, U, VerboseError<T>>;
-        
-        fn element_url(input: &str) -> Res<&str, Element> {
-            let (input, _) = tag("<")(input)?;
-            let (input, s) = take_until1(">")(input)?;
-            let (input, _) = tag(">")(input)?;
-            let element = Element::from_str(s).unwrap();
-            Ok((input, element))
+    /// Records lines that can not be parsed or inserted in a file, instead of printing them
+    pub fn set_reject_file(&mut self, filename: &str) -> Result<(),WDQSErr> {
+        self.rejects = Arc::new(RejectFile::create(filename)?);
+        Ok(())
+    }
+
+    /// Parses one N-Triples line; empty and comment-only lines have no triple
+    fn parse_line(line: &str) -> Result<Option<(Element,Element,Element)>,WDQSErr> {
+        match ntriples::parse_line(line)? {
+            Some(triple) => Ok(Some(Self::to_triple(triple)?)),
+            None => Ok(None),
         }
+    }
 
-        fn element_underscore(input: &str) -> Res<&str, Element> {
-            let (input, _) = tag("_")(input)?;
-            let (input, s) = take_until(" ")(input)?;
-            let element = Element::Text(s.into());
-            Ok((input, element))
+    fn to_triple((part1,part2,part3): TermTriple) -> Result<Triple,WDQSErr> {
+        let (part1,part2,part3) = (part1.to_element()?,part2.to_element()?,part3.to_element()?);
+        if let Element::Url(url) = &part2 {
+            Err(WDQSErr::String(format!("parse_line: Property is URL, but should not be: {url:?}")))
+        } else if part1.values().is_empty() && part3.values().is_empty() {
+            // Nothing to store in a row, like for a triple of constant IRIs
+            Err(WDQSErr::ParserError(format!("parse_line: Triple without values: {part1:?} {part2:?} {part3:?}")))
+        } else {
+            Ok((part1,part2,part3))
         }
+    }
 
-        fn string_type(input: &str) -> Res<&str, &str> {
-            let (input, _) = tag("^^")(input)?;
-            let (input, _) = tag("<")(input)?;
-            let (input, s) = take_until1(">")(input)?;
-            let (input, _) = tag(">")(input)?;
-            Ok((input, s))
+    fn parse_batch(batch: Batch) -> ParsedBatch {
+        match batch {
+            Batch::NTriples(lines) => Self::parse_batch_lines(lines, Self::parse_ntriples_lines),
+            Batch::NQuads(lines) => Self::parse_batch_lines(lines, Self::parse_quad_lines),
+            Batch::Json(lines) => Self::parse_batch_lines(lines, Self::parse_json_lines),
+            Batch::Triples(triples) => (vec![],vec![],triples.into_iter().map(|triple|(triple,None)).collect()),
         }
+    }
 
-        fn string_language(input: &str) -> Res<&str, &str> {
-            let (input, _) = tag("@")(input)?;
-            let (input, s) = take_until1(" ")(input)?;
-            Ok((input, s))
+    /// Parses and adds the triples of a batch; a line is rejected for the first error of its parsing.
+    /// Database errors are not the fault of a line, so they stop the import.
+    /// Parsing runs on the blocking thread pool, so it does not hold up the inserts of other workers.
+    async fn add_batch(wrapper: &DatabaseWrapper, rejects: &RejectFile, batch: Batch) -> Result<(),WDQSErr> {
+        let (lines,reasons,triples) = tokio::task::spawn_blocking(move||Self::parse_batch(batch)).await
+            .map_err(|e|WDQSErr::String(format!("Parser::add_batch: {e}")))?;
+        for ((part1,part2,part3),graph) in triples {
+            wrapper.add_to_graph(part1,&part2,part3,graph.as_deref()).await?;
+        }
+        for (line,reason) in lines.iter().zip(reasons) {
+            if let Some(reason) = reason {
+                rejects.reject(line, &reason)?;
+            }
         }
+        Ok(())
+    }
 
-        fn element_from_type(s: &str, type_s: &str) -> Option<Element> {
-            match type_s {
-                "http://www.w3.org/2001/XMLSchema#dateTime" => return Some(Element::DateTime(*DateTime::from_str(&s)?)),
-                "http://www.opengis.net/ont/geosparql#wktLiteral" => return Some(Element::LatLon(*LatLon::from_str(&s)?)),
-                "http://www.w3.org/2001/XMLSchema#decimal" => return Some(Element::Float(s.parse::<f64>().ok()?)),
-                "http://www.w3.org/2001/XMLSchema#double" => return Some(Element::Float(s.parse::<f64>().ok()?)), // For now, same as decimal
-                "http://www.w3.org/2001/XMLSchema#integer" => return Some(Element::Int(s.parse::<i64>().ok()?)),
-                _ => None,
+    fn parse_batch_lines(lines: Vec<InputLine>, parse: fn(&[InputLine], &mut [Option<WDQSErr>]) -> Vec<GraphTriple>) -> ParsedBatch {
+        let mut reasons = vec![None;lines.len()];
+        let triples = parse(&lines, &mut reasons);
+        (lines,reasons,triples)
+    }
+
+    fn parse_ntriples_lines(lines: &[InputLine], reasons: &mut [Option<WDQSErr>]) -> Vec<GraphTriple> {
+        let mut ret = vec![];
+        let mut value_node = ValueNode::default();
+        for (index,line) in lines.iter().enumerate() {
+            match Self::parse_line(&line.text) {
+                Ok(Some(triple)) if ValueNode::is_value_node_line(&line.text) => if let Some(triples) = value_node.add(triple) {
+                    ret.extend(triples.into_iter().map(|triple|(triple,None)));
+                }
+                Ok(Some(triple)) => ret.push((triple,None)),
+                Ok(None) => {}
+                Err(e) => reasons[index] = Some(e),
             }
         }
+        if let Some(triples) = value_node.flush() {
+            ret.extend(triples.into_iter().map(|triple|(triple,None)));
+        }
+        ret
+    }
 
-        fn element_string(input: &str) -> Res<&str, Element> {
-            let (input, _) = tag("\"")(input)?;
-            let (input, s) = take_until("\"")(input)?;
-            let (input, _) = tag("\"")(input)?;
-            if let Ok((input, type_s)) = string_type(input) {
-                let element = match element_from_type(s, type_s) {
-                    Some(element) => element,
-                    None => {
-                        eprintln!("element_string: type parsing has failed: '{input}' / '{type_s}'");
-                        return Err(nom::Err::Error(VerboseError { errors: vec![(input, VerboseErrorKind::Context("element_string: type parsing has failed"))] }));
-                    }
-                };
-                return Ok((input, element));
+    /// Parses N-Quads; triples without a graph label go to the default graph
+    fn parse_quad_lines(lines: &[InputLine], reasons: &mut [Option<WDQSErr>]) -> Vec<GraphTriple> {
+        let mut ret = vec![];
+        let mut value_node = ValueNode::default();
+        let mut value_node_graph = None;
+        for (index,line) in lines.iter().enumerate() {
+            let quad = ntriples::parse_quad_line(&line.text).and_then(|quad|match quad {
+                Some((s,p,o,graph)) => Ok(Some((Self::to_triple((s,p,o))?,graph.map(|graph|graph.to_graph_name()).transpose()?))),
+                None => Ok(None),
+            });
+            let (triple,graph) = match quad {
+                Ok(Some(quad)) => quad,
+                Ok(None) => continue,
+                Err(e) => {
+                    reasons[index] = Some(e);
+                    continue;
+                }
+            };
+            if !matches!(triple.0,Element::Value(_)) {
+                ret.push((triple,graph));
+                continue;
+            }
+            // A value node ends where its graph changes, too
+            if value_node_graph!=graph {
+                if let Some(triples) = value_node.flush() {
+                    ret.extend(triples.into_iter().map(|triple|(triple,value_node_graph.to_owned())));
+                }
+                value_node_graph = graph.to_owned();
+            }
+            if let Some(triples) = value_node.add(triple) {
+                ret.extend(triples.into_iter().map(|triple|(triple,graph.to_owned())));
+            }
+        }
+        if let Some(triples) = value_node.flush() {
+            ret.extend(triples.into_iter().map(|triple|(triple,value_node_graph.to_owned())));
+        }
+        ret
+    }
+
+    /// Parses a Wikibase JSON dump, one entity per line
+    fn parse_json_lines(lines: &[InputLine], reasons: &mut [Option<WDQSErr>]) -> Vec<GraphTriple> {
+        let mut ret = vec![];
+        for (index,line) in lines.iter().enumerate() {
+            match json_dump::parse_line(&line.text) {
+                Ok(triples) => ret.extend(triples.into_iter().map(|triple|(triple,None))),
+                Err(e) => reasons[index] = Some(e),
             }
-            if let Ok((input, language_s)) = string_language(input) {
-                let element = Element::TextInLanguage((s.into(),language_s.into()));
-                return Ok((input, element));
+        }
+        ret
+    }
+
+    /// The next line with its number and byte offset; lines that are not UTF-8 are rejected
+    fn next_line<T: BufRead>(&self, lines_iter: &mut InputLines<T>) -> Result<Option<InputLine>,WDQSErr> {
+        loop {
+            let (number,offset) = (lines_iter.line()+1,lines_iter.offset());
+            match lines_iter.next() {
+                Some(Ok(text)) => return Ok(Some(InputLine { number, offset, text })),
+                Some(Err(WDQSErr::FromUtf8(e))) => {
+                    let line = InputLine { number, offset, text: String::from_utf8_lossy(e.as_bytes()).to_string() };
+                    self.rejects.reject(&line, &WDQSErr::FromUtf8(e))?;
+                }
+                Some(Err(e)) => return Err(e),
+                None => return Ok(None),
             }
-            Ok((input, Element::Text(s.into())))
         }
+    }
 
-        fn element(input: &str) -> Res<&str, Element> {
-            alt((element_url,element_underscore,element_string))(input)
+    /// Sends the lines in batches to the pipeline.
+    /// Every `checkpoint_interval` lines, waits for the pipeline to be done, writes all pending inserts, and records the position.
+    async fn read_lines<T: BufRead>(&self, lines_iter: &mut InputLines<T>, pipeline: &Pipeline<Batch>, to_batch: fn(Vec<InputLine>) -> Batch) -> Result<(),WDQSErr> {
+        let mut batch = vec![];
+        let mut batch_bytes = 0;
+        let mut checkpoint_line = lines_iter.line();
+        while let Some(line) = self.next_line(lines_iter)? {
+            // Value node lines are consecutive, so batches and checkpoints are only before other lines
+            if !batch.is_empty() && !ValueNode::is_value_node_line(&line.text) {
+                let checkpoint_due = self.app.checkpoint_interval>0 && line.number>checkpoint_line+self.app.checkpoint_interval;
+                if checkpoint_due || batch_bytes>=BATCH_SIZE {
+                    pipeline.send(to_batch(std::mem::take(&mut batch))).await?;
+                    batch_bytes = 0;
+                }
+                if checkpoint_due {
+                    pipeline.wait_until_done().await?;
+                    self.wrapper.flush_insert_caches().await?;
+                    self.rejects.flush()?;
+                    checkpoint_line = line.number-1;
+                    self.app.set_checkpoint(&Checkpoint { line: checkpoint_line, offset: line.offset, ..lines_iter.checkpoint() }).await?;
+                }
+            }
+            batch_bytes += line.text.len();
+            batch.push(line);
+        }
+        if !batch.is_empty() {
+            pipeline.send(to_batch(batch)).await?;
         }
+        Ok(())
+    }
 
-        fn parse_line_sub(input: &str) -> Res<&str, (Element,Element,Element)> {
-            let (input,part1) = element(input)?;
-            let (input,_) = space1(input)?;
-            let (input,part2) = element(input)?;
-            let (input,_) = space1(input)?;
-            let (_,part3) = element(input)?;
-            Ok((input,(part1,part2,part3)))
+    /// Converts the triples of Turtle statements; value node triples are collected first.
+    /// Returns the first error, if any.
+    fn add_term_triples(value_node: &mut ValueNode, term_triples: Vec<TermTriple>, triples: &mut Vec<Triple>) -> Result<(),WDQSErr> {
+        let mut ret = Ok(());
+        for term_triple in term_triples {
+            match Self::to_triple(term_triple) {
+                Ok(triple) if matches!(triple.0,Element::Value(_)) => if let Some(value_triples) = value_node.add(triple) {
+                    triples.extend(value_triples);
+                }
+                Ok(triple) => triples.push(triple),
+                Err(e) => if ret.is_ok() {
+                    ret = Err(e);
+                }
+            }
         }
+        ret
+    }
 
-        fn parse_line_internal(line: &str) -> Result<(Element,Element,Element),WDQSErr> {
-            let (part1,part2,part3) = match parse_line_sub(line) {
-                Ok((_,part123)) => part123,
-                Err(e) => return Err(WDQSErr::String(e.to_string())),
-            };
-            if let Element::Url(url) = &part2 {
-                Err(WDQSErr::String(format!("parse_line: Property is URL, but should not be: {url:?}")))
-            } else {
-                Ok((part1,part2,part3))
+    async fn read_turtle_lines<T: BufRead>(&self, lines_iter: &mut InputLines<T>, pipeline: &Pipeline<Batch>) -> Result<(),WDQSErr> {
+        let mut value_node = ValueNode::default();
+        let mut reader = TurtleReader::default();
+        let mut triples = vec![];
+        while let Some(line) = self.next_line(lines_iter)? {
+            let result = reader.add_line(&line.text)
+                .and_then(|term_triples|Self::add_term_triples(&mut value_node, term_triples, &mut triples));
+            if let Err(e) = result {
+                self.rejects.reject(&line, &e)?;
+            }
+            if triples.len()>=BATCH_TRIPLES {
+                pipeline.send(Batch::Triples(std::mem::take(&mut triples))).await?;
             }
         }
+        let end = InputLine { number: lines_iter.line()+1, offset: lines_iter.offset(), text: String::new() };
+        let result = reader.finish()
+            .and_then(|term_triples|Self::add_term_triples(&mut value_node, term_triples, &mut triples));
+        if let Err(e) = result {
+            self.rejects.reject(&end, &e)?;
+        }
+        if let Some(value_triples) = value_node.flush() {
+            triples.extend(value_triples);
+        }
+        pipeline.send(Batch::Triples(triples)).await
+    }
 
-        match parse_line_internal(line) {
-            Ok(ret) => Ok(ret),
-            Err(e) => {
-                eprintln!("Parsing error");
-                Err(e)
-            },
+    /// Imports a file; the format is taken from the file name unless given.
+    /// With `resume`, the import continues after the last checkpoint for that file name.
+    pub async fn import_from_file(&self, filename: &str, format: Option<InputFormat>, resume: bool) -> Result<(),WDQSErr> {
+        let format = format.unwrap_or_else(||InputFormat::from_filename(filename));
+        let checkpoint = self.resume_checkpoint(filename, format, resume).await?;
+        let mut file = File::open(filename)?;
+        if let Some(checkpoint) = &checkpoint {
+            let mut magic = vec![];
+            file.by_ref().take(6).read_to_end(&mut magic)?;
+            if Self::compression(&magic).is_none() { // Uncompressed files can seek to the checkpoint
+                file.seek(SeekFrom::Start(checkpoint.offset))?;
+                let lines = InputLines::new(io::BufReader::with_capacity(BUFFER_SIZE, file), filename).continue_from(checkpoint);
+                return self.read_input(lines, format).await;
+            }
+            file.rewind()?;
         }
+        self.import_input(file, format, filename, checkpoint).await
+    }
 
+    /// Imports from any reader, like stdin; compression is detected from the data.
+    /// Checkpoints are stored under the name `input`; with `resume`, lines up to the last checkpoint are skipped.
+    pub async fn import_from_reader<R: Read + Send + 'static>(&self, reader: R, format: InputFormat, input: &str, resume: bool) -> Result<(),WDQSErr> {
+        let checkpoint = self.resume_checkpoint(input, format, resume).await?;
+        self.import_input(reader, format, input, checkpoint).await
     }
 
-    async fn read_lines<T: BufRead>(&self, lines_iter: &mut Lines<T>) -> Result<(),WDQSErr> {
-        let counter = Arc::new(Mutex::new(0 as usize));
-        while let Some(line) = lines_iter.next() {
-            if let Ok(line) = line {
+    async fn resume_checkpoint(&self, input: &str, format: InputFormat, resume: bool) -> Result<Option<Checkpoint>,WDQSErr> {
+        if !resume {
+            return Ok(None);
+        }
+        if format==InputFormat::Turtle { // Prefixes and blank node labels from before the checkpoint would be missing
+            return Err("Parser: Turtle imports can not be resumed".into());
+        }
+        self.app.get_checkpoint(input).await
+    }
 
-                while *counter.lock().await>MAX_CONCURRENT_THREADS {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+    async fn import_input<R: Read + Send + 'static>(&self, reader: R, format: InputFormat, input: &str, checkpoint: Option<Checkpoint>) -> Result<(),WDQSErr> {
+        let mut lines = InputLines::new(io::BufReader::with_capacity(BUFFER_SIZE, Self::decompress(reader)?), input);
+        if let Some(checkpoint) = &checkpoint {
+            lines.skip_to(checkpoint)?;
+        }
+        self.read_input(lines, format).await
+    }
+
+    async fn read_input<T: BufRead>(&self, mut lines: InputLines<T>, format: InputFormat) -> Result<(),WDQSErr> {
+        let (wrapper,rejects) = (self.wrapper.clone(),self.rejects.clone());
+        let pipeline = Pipeline::new(self.app.parallel_parsing, move |batch| {
+            let (wrapper,rejects) = (wrapper.clone(),rejects.clone());
+            async move { Self::add_batch(&wrapper, &rejects, batch).await }
+        });
+        let read = match format {
+            InputFormat::NTriples => self.read_lines(&mut lines, &pipeline, Batch::NTriples).await,
+            InputFormat::NQuads => self.read_lines(&mut lines, &pipeline, Batch::NQuads).await,
+            InputFormat::Turtle => self.read_turtle_lines(&mut lines, &pipeline).await,
+            InputFormat::Json => self.read_lines(&mut lines, &pipeline, Batch::Json).await,
+        };
+        pipeline.finish().await?;
+        read?;
+        self.wrapper.flush_insert_caches().await?;
+        self.rejects.finish()?;
+        if format==InputFormat::Turtle {
+            return Ok(());
+        }
+        // All triples have been written, so a resumed import has nothing left to do
+        self.app.set_checkpoint(&lines.checkpoint()).await
+    }
+
+    /// The compression of data starting with the `magic` bytes
+    fn compression(magic: &[u8]) -> Option<&'static str> {
+        match magic {
+            [0x1F,0x8B,..] => Some("gzip"),
+            [b'B',b'Z',b'h',..] => Some("bzip2"),
+            [0x28,0xB5,0x2F,0xFD,..] => Some("zstd"),
+            [0xFD,b'7',b'z',b'X',b'Z',0x00] => Some("xz"),
+            _ => None,
+        }
+    }
+
+    /// Wraps the reader in a gzip, bzip2, zstd or xz decoder, depending on the magic bytes at the start of the data
+    pub fn decompress<R: Read + Send + 'static>(mut reader: R) -> Result<Box<dyn Read + Send>,WDQSErr> {
+        let mut magic = vec![];
+        reader.by_ref().take(6).read_to_end(&mut magic)?;
+        let reader = Cursor::new(magic.to_owned()).chain(reader); // Put the magic bytes back
+        let ret: Box<dyn Read + Send> = match Self::compression(&magic) {
+            Some("gzip") => Box::new(MultiGzDecoder::new(reader)),
+            Some("bzip2") => Box::new(MultiBzDecoder::new(reader)),
+            Some("zstd") => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            Some("xz") => Box::new(XzDecoder::new_multi_decoder(reader)),
+            _ => Box::new(reader),
+        };
+        Ok(ret)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::app_state::tests::{MemoryDB, app_with_db};
+
+    #[test]
+    fn test_input_format() {
+        assert_eq!(InputFormat::from_filename("latest-all.ttl.bz2"),InputFormat::Turtle);
+        assert_eq!(InputFormat::from_filename("dump.nq.zst"),InputFormat::NQuads);
+        assert_eq!(InputFormat::from_filename("latest-all.json.gz"),InputFormat::Json);
+        assert_eq!(InputFormat::from_filename("triples.nt"),InputFormat::NTriples);
+        assert_eq!(InputFormat::from_filename("triples.xz"),InputFormat::NTriples);
+    }
+
+    #[test]
+    fn test_decompress() {
+        let text = b"<http://example/s> <http://example/p> <http://example/o> .\n".repeat(3);
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(&text).unwrap();
+        let mut bz = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        bz.write_all(&text).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(vec![], 6);
+        xz.write_all(&text).unwrap();
+        let inputs = [
+            ("plain",text.to_owned()),
+            ("gzip",gz.finish().unwrap()),
+            ("bzip2",bz.finish().unwrap()),
+            ("zstd",zstd::encode_all(text.as_slice(), 0).unwrap()),
+            ("xz",xz.finish().unwrap()),
+            ("short",b"<".to_vec()),
+        ];
+        for (name,input) in inputs {
+            let mut output = vec![];
+            Parser::decompress(Cursor::new(input.to_owned())).unwrap().read_to_end(&mut output).unwrap();
+            let expected = if name=="short" { input } else { text.to_owned() };
+            assert_eq!(output,expected,"{name}");
+        }
+    }
+
+    const TRIPLES: &str = "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .
+bad line
+<http://www.w3.org/2000/01/rdf-schema#label> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://www.w3.org/2000/01/rdf-schema#label> .
+<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .
+";
+
+    #[tokio::test]
+    async fn test_insert_errors() {
+        let db = MemoryDB::default();
+        let mut app = app_with_db(&db);
+        app.insert_batch_size = 1;
+        let app = Arc::new(app);
+        // Lines that can not be parsed are rejected
+        let parser = Parser::new(app.clone());
+        parser.import_from_reader(Cursor::new(TRIPLES), InputFormat::NTriples, "test", false).await.unwrap();
+        assert_eq!(parser.rejects.count(),2);
+        assert_eq!(db.rows.lock().unwrap().len(),2);
+        // A failed insert is not the fault of a line, so it stops the import
+        db.failing_flush.store(db.flushes.load(Ordering::SeqCst)+1, Ordering::SeqCst);
+        let parser = Parser::new(app.clone());
+        assert!(parser.import_from_reader(Cursor::new(TRIPLES), InputFormat::NTriples, "test", false).await.is_err());
+        assert_eq!(parser.rejects.count(),0);
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_failed_flush() {
+        let db = MemoryDB::default();
+        let mut app = app_with_db(&db);
+        app.checkpoint_interval = 2;
+        app.parallel_parsing = 1;
+        let app = Arc::new(app);
+        let input: String = (1..=6).map(|q|format!("<http://www.wikidata.org/entity/Q{q}> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .\n")).collect();
+        // Inserts are written at the checkpoints after lines 2 and 4; the second fails
+        db.failing_flush.store(2, Ordering::SeqCst);
+        let parser = Parser::new(app.clone());
+        assert!(parser.import_from_reader(Cursor::new(input.to_owned()), InputFormat::NTriples, "test", false).await.is_err());
+        let checkpoint = db.checkpoints.lock().unwrap()["test"].to_owned();
+        assert_eq!((checkpoint.line,checkpoint.offset),(2,input.lines().take(2).map(|line|line.len() as u64+1).sum()));
+        // Resuming writes the lines from the last successful checkpoint on
+        let parser = Parser::new(app.clone());
+        parser.import_from_reader(Cursor::new(input.to_owned()), InputFormat::NTriples, "test", true).await.unwrap();
+        let mut rows = db.rows.lock().unwrap().to_owned();
+        rows.sort();
+        assert_eq!(rows,(1..=6).map(|q|format!("data__PropertyDirect_P31__EntityItem__EntityItem|{q},5")).collect::<Vec<_>>());
+        assert_eq!(db.checkpoints.lock().unwrap()["test"].line,6);
+    }
+
+    /// A pipeline that records its batches, after waiting for a permit of `gate`
+    fn recording_pipeline(workers: usize, gate: Arc<Semaphore>) -> (Pipeline<usize>,Arc<std::sync::Mutex<Vec<usize>>>) {
+        let done = Arc::new(std::sync::Mutex::new(vec![]));
+        let recorded = done.clone();
+        let pipeline = Pipeline::new(workers, move |batch| {
+            let (gate,done) = (gate.clone(),done.clone());
+            async move {
+                gate.acquire().await.unwrap().forget();
+                if batch==usize::MAX {
+                    return Err(WDQSErr::String("failed batch".into()));
                 }
-        
-                let wrapper = self.wrapper.clone();
-                let counter = counter.clone();
-                *counter.lock().await += 1;
-                tokio::task::spawn(async move {
-                    match Self::parse_line(&line) {
-                        Ok((part1,part2,part3)) => {
-                            if let Err(e) = wrapper.add(part1,&part2,part3).await {
-                                eprintln!("WARPPER.ADD ERROR:{e} line:\n{line}\n")
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("PARSER ERROR:{e} line:\n{line}\n");
-                        }
-                    };
-                    *counter.lock().await -= 1;
-                });
+                done.lock().unwrap().push(batch);
+                Ok(())
             }
+        });
+        (pipeline,recorded)
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_order() {
+        let (pipeline,done) = recording_pipeline(1, Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)));
+        for batch in 0..20 {
+            pipeline.send(batch).await.unwrap();
         }
-        while *counter.lock().await>0 {
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        pipeline.finish().await.unwrap();
+        assert_eq!(*done.lock().unwrap(),(0..20).collect::<Vec<_>>());
+
+        // All batches sent before wait_until_done are done when it returns
+        let (pipeline,done) = recording_pipeline(3, Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)));
+        for batch in 0..20 {
+            pipeline.send(batch).await.unwrap();
         }
-        self.wrapper.flush_insert_caches().await
+        pipeline.wait_until_done().await.unwrap();
+        let mut batches = done.lock().unwrap().to_owned();
+        batches.sort();
+        assert_eq!(batches,(0..20).collect::<Vec<_>>());
+        pipeline.finish().await.unwrap();
     }
 
-    pub async fn import_from_file(&self, filename: &str) -> Result<(),WDQSErr> {
-        let file = File::open(filename)?;
-        let buffer_size = 1024*1024;
-        match filename.split('.').last() {
-            Some("bz2") => self.read_lines(&mut io::BufReader::with_capacity(buffer_size, MultiBzDecoder::new(file)).lines()).await,
-            Some("gz") => self.read_lines(&mut io::BufReader::with_capacity(buffer_size, GzDecoder::new(file)).lines()).await,
-            _ => self.read_lines(&mut io::BufReader::with_capacity(buffer_size, file).lines()).await,
+    #[tokio::test]
+    async fn test_pipeline_backpressure() {
+        let gate = Arc::new(Semaphore::new(0));
+        let (pipeline,done) = recording_pipeline(2, gate.clone());
+        let timeout = std::time::Duration::from_millis(100);
+        for batch in 0..2*BATCHES_PER_WORKER {
+            tokio::time::timeout(timeout, pipeline.send(batch)).await.unwrap().unwrap();
         }
+        // The pipeline is full while the workers are blocked
+        assert!(tokio::time::timeout(timeout, pipeline.send(100)).await.is_err());
+        gate.add_permits(1);
+        tokio::time::timeout(timeout, pipeline.send(101)).await.unwrap().unwrap();
+        gate.add_permits(Semaphore::MAX_PERMITS/2);
+        pipeline.finish().await.unwrap();
+        assert_eq!(done.lock().unwrap().len(),2*BATCHES_PER_WORKER+1);
     }
 
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn test_pipeline_error() {
+        let (pipeline,done) = recording_pipeline(2, Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)));
+        pipeline.send(1).await.unwrap();
+        pipeline.send(usize::MAX).await.unwrap();
+        // A stopped worker fails the next wait and the sends to it
+        assert!(pipeline.wait_until_done().await.is_err());
+        let mut sent = true;
+        for batch in 2..10 {
+            sent = sent && pipeline.send(batch).await.is_ok();
+        }
+        assert!(!sent);
+        match pipeline.finish().await {
+            Err(WDQSErr::String(e)) => assert_eq!(e,"failed batch"),
+            other => panic!("{other:?}"),
+        }
+        assert!(done.lock().unwrap().contains(&1));
+    }
+}
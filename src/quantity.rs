@@ -0,0 +1,145 @@
+use regex::Regex;
+
+use crate::{element_type::ElementType, type_part::TypePart, db_operation_cache::DbOperationCacheValue, decimal::Decimal};
+
+lazy_static! {
+    static ref RE_QUANTITY: Regex = Regex::new(r#"^([+-]?[0-9.]+)(?: \[([+-]?[0-9.]+),([+-]?[0-9.]+)\])?(?: <http://www.wikidata.org/entity/Q(\d+)>)?$"#).expect("RE_QUANTITY does not parse");
+}
+
+/// The wikibase: predicate that links a value node to its quantity table row
+pub const QUANTITY_PREDICATE: &str = "quantityValue";
+
+/// The unit "1", for quantities without unit
+pub const UNIT_ONE_Q: u32 = 199;
+
+/// A wikibase:QuantityValue node
+#[derive(Clone, Debug)]
+pub struct Quantity {
+    amount: Decimal,
+    bounds: Option<(Decimal,Decimal)>, // (lower,upper)
+    unit_q: u32,
+}
+
+impl Quantity {
+    pub fn new(amount: Decimal, bounds: Option<(Decimal,Decimal)>, unit_q: u32) -> Self {
+        Self { amount, bounds, unit_q }
+    }
+
+    pub fn amount(&self) -> &Decimal {
+        &self.amount
+    }
+
+    pub fn bounds(&self) -> Option<&(Decimal,Decimal)> {
+        self.bounds.as_ref()
+    }
+
+    pub fn unit_q(&self) -> u32 {
+        self.unit_q
+    }
+
+    /// For the wikibase: predicates of a quantity node, the column in the quantity table, its element type, and whether it only exists with bounds
+    pub fn component(predicate: &str) -> Option<(usize,&'static str,bool)> {
+        match predicate {
+            "quantityAmount" => Some((0,"Decimal",false)),
+            "quantityLowerBound" => Some((1,"Decimal",true)),
+            "quantityUpperBound" => Some((2,"Decimal",true)),
+            "quantityUnit" => Some((3,"EntityItem",false)),
+            _ => None,
+        }
+    }
+}
+
+impl ElementType for Quantity {
+    /// Parses the form written by `to_string`
+    fn from_str(s: &str) -> Option<Box<Self>> {
+        let caps = RE_QUANTITY.captures(s)?;
+        let bounds = match (caps.get(2),caps.get(3)) {
+            (Some(lower),Some(upper)) => Some((*Decimal::from_str(lower.as_str())?,*Decimal::from_str(upper.as_str())?)),
+            _ => None,
+        };
+        let unit_q = match caps.get(4) {
+            Some(unit_q) => unit_q.as_str().parse::<u32>().ok()?,
+            None => UNIT_ONE_Q,
+        };
+        Some(Box::new(Self { amount: *Decimal::from_str(caps.get(1)?.as_str())?, bounds, unit_q }))
+    }
+
+    /// The "|"-separated columns, as created by `Element::sql_expression`
+    fn from_sql_values(name:&str, value: &Vec<String>) -> Option<Box<Self>> {
+        if name!="Quantity" {
+            return None;
+        }
+        let parts: Vec<&str> = match value.len() {
+            1 => value[0].split('|').collect(),
+            _ => value.iter().map(|s|s.as_str()).collect(),
+        };
+        if parts.len()!=5 {
+            return None;
+        }
+        let bounds = match parts[4] {
+            "0" => None,
+            _ => Some((*Decimal::from_str(parts[1])?,*Decimal::from_str(parts[2])?)),
+        };
+        Some(Box::new(Self { amount: *Decimal::from_str(parts[0])?, bounds, unit_q: parts[3].parse::<u32>().ok()? }))
+    }
+
+    /// Amount, lower and upper bound, unit, and whether there are bounds; missing bounds are stored as the amount
+    fn get_type_parts(&self) -> Vec<crate::type_part::TypePart>  {
+        vec![TypePart::Decimal,TypePart::Decimal,TypePart::Decimal,TypePart::U32,TypePart::U8]
+    }
+
+    fn values(&self) -> Vec<DbOperationCacheValue> {
+        let (lower,upper) = self.bounds.as_ref().map(|(lower,upper)|(lower,upper)).unwrap_or((&self.amount,&self.amount));
+        vec![
+            DbOperationCacheValue::Expression(self.amount.to_string()),
+            DbOperationCacheValue::Expression(lower.to_string()),
+            DbOperationCacheValue::Expression(upper.to_string()),
+            DbOperationCacheValue::U32(self.unit_q),
+            DbOperationCacheValue::U8(self.bounds.is_some() as u8),
+            ]
+    }
+
+    fn to_string(&self) -> String  {
+        let mut ret = self.amount.to_string();
+        if let Some((lower,upper)) = &self.bounds {
+            ret += &format!(" [{},{}]",lower.to_string(),upper.to_string());
+        }
+        if self.unit_q!=UNIT_ONE_Q {
+            ret += &format!(" <http://www.wikidata.org/entity/Q{}>",self.unit_q);
+        }
+        ret
+    }
+
+    fn name(&self) -> &str  {
+        "Quantity"
+    }
+
+    fn table_name(&self) -> String  {
+        self.name().to_string()
+    }
+
+    fn to_url(&self) -> String  {
+        self.to_string()
+    }
+
+    fn sql_var_from_name(_name: &str, prefix: &str) -> Option<Vec<String>>  {
+        Some((0..5).map(|num|format!("{prefix}{num}")).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let area = Quantity::from_str("891.12 [891.1,891.14] <http://www.wikidata.org/entity/Q712226>").unwrap();
+        assert_eq!((area.amount().to_f64(),area.unit_q()),(891.12,712226));
+        assert_eq!(area.bounds().map(|(lower,upper)|(lower.to_string(),upper.to_string())),Some(("891.1".to_string(),"891.14".to_string())));
+        assert_eq!(area.to_string(),"891.12 [891.1,891.14] <http://www.wikidata.org/entity/Q712226>");
+        let population = Quantity::from_sql_values("Quantity",&vec!["3645000.000000000000000000000000000000|3645000|3645000|199|0".to_string()]).unwrap();
+        assert_eq!((population.bounds().is_none(),population.to_string().as_str()),(true,"3645000"));
+        let temperature = Quantity::from_sql_values("Quantity",&["-40","-41","-39","25267","1"].iter().map(|s|s.to_string()).collect()).unwrap();
+        assert_eq!(temperature.to_string(),"-40 [-41,-39] <http://www.wikidata.org/entity/Q25267>");
+    }
+}
@@ -1,14 +1,15 @@
 use std::{sync::Arc, collections::HashMap, fmt::{Display, self}};
+use serde::Serialize;
 
-use crate::{query_part::QueryPart, app_state::AppState, database_table::DatabaseTable, error::WDQSErr, type_part::TypePart, element::Element};
+use crate::{query_part::QueryPart, app_state::AppState, database_table::DatabaseTable, error::WDQSErr, type_part::TypePart, element::Element, app_state_mysql_stdout::AppStateStdoutMySQL, sparql_expression::{Aggregate, AggregateFunction}, db_operation_cache::DbOperationCacheValue, geo_search::GeoSearch, quantity::{Quantity, QUANTITY_PREDICATE}};
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct DatabaseQueryResult {
     pub variables: Vec<SqlVariable>,
     pub rows: Vec<Vec<Option<String>>>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct SqlVariable {
     name: String,
     kind: Option<String>,
@@ -21,24 +22,40 @@ impl Display for SqlVariable {
 }
 
 impl SqlVariable {
+    pub fn new(name: &str, kind: Option<&str>) -> Self {
+        Self { name: name.to_string(), kind: kind.map(|kind|kind.to_string()) }
+    }
+
     pub fn sql_value2string(&self,v: &mysql_async::Value) -> Option<String> {
         let v = match v {
             mysql_async::Value::NULL => return None,
             mysql_async::Value::Bytes(b) => String::from_utf8_lossy(b).to_string(),
             mysql_async::Value::Int(i) => format!("{i}"),
             mysql_async::Value::UInt(i)=> format!("{i}"),
-            mysql_async::Value::Float(_) => todo!(),
-            mysql_async::Value::Double(_) => todo!(),
+            mysql_async::Value::Float(f) => format!("{f}"),
+            mysql_async::Value::Double(f) => format!("{f}"),
             mysql_async::Value::Date(_, _, _, _, _, _, _) => todo!(),
             mysql_async::Value::Time(_, _, _, _, _, _) => todo!(),
         };
-        let element_name = self.kind.to_owned()?;
-        let element = Element::from_sql_values(&element_name,&vec![v]);
-        element.to_string()
+        Some(v)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
+
+    /// Decodes a raw SQL result value for this variable
+    pub fn to_element(&self, value: &str) -> Option<Element> {
+        let kind = self.kind.as_ref()?;
+        Some(Element::from_sql_values(kind,&vec![value.to_string()]))
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct SqlPart {
     pub sql: String,
     pub values: Vec<String>,
@@ -60,15 +77,45 @@ impl SqlPart {
         self.values.append(&mut other.values);
     }
 
+    /// Keeps only the rows matching `condition`, which refers to the variables as `t`.`name`
+    pub fn filter(&mut self, condition: &str) {
+        self.sql = format!("SELECT * FROM ({}) AS t WHERE {condition}",self.sql);
+    }
+
     pub fn union_all(&mut self, other: &mut SqlPart) {
         self.sql = format!("{} UNION ALL {}",self.sql,other.sql);
         self.values.append(&mut other.values);
     }
 }
 
+/// Solution modifiers that can be applied to each result group in SQL
+#[derive(Debug, Clone, Default)]
+pub struct SqlModifiers {
+    pub distinct: bool,
+    pub order_by: Vec<(String,bool)>, // (variable,descending)
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub variables: Vec<String>, // Result variables; others are only used for sorting
+}
+
 #[derive(Debug, Clone, Default)]
-struct QueryPartMeta {
-    variable: Option<String>,
+pub struct QueryPartMeta {
+    pub variable: Option<String>,
+}
+
+impl QueryPartMeta {
+    pub fn new(variable: Option<String>) -> Self {
+        Self { variable }
+    }
+}
+
+/// The graph a triple pattern is matched in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum GraphPart {
+    #[default]
+    Default, // The union of all graphs
+    Named(String),
+    Variable(String), // Any named graph, bound to the variable
 }
 
 #[derive(Debug, Clone)]
@@ -79,7 +126,9 @@ pub struct QueryTriples {
     s_meta: QueryPartMeta,
     p_meta: QueryPartMeta,
     o_meta: QueryPartMeta,
+    graph: GraphPart,
     pub result: HashMap<String,SqlPart>,
+    pub offset: usize, // Rows to skip after merging the result groups, if OFFSET is not applied in SQL
 }
 
 impl QueryTriples {
@@ -91,14 +140,32 @@ impl QueryTriples {
             s_meta: QueryPartMeta::default(),
             o_meta: QueryPartMeta::default(),
             p_meta: QueryPartMeta::default(),
+            graph: GraphPart::Default,
             result: HashMap::new(),
+            offset: 0,
         }
     }
 
+    /// The single solution without variables, to left join a leading OPTIONAL onto
+    pub fn empty_solution() -> Self {
+        let mut ret = Self::new(QueryPart::Unknown, QueryPart::Unknown, QueryPart::Unknown);
+        ret.result.insert("empty".to_string(),SqlPart { sql: "SELECT 1".to_string(), ..Default::default() });
+        ret
+    }
+
     pub async fn from_str(app: &Arc<AppState>, s: &str, p: &str, o: &str ) -> Result<Self,WDQSErr> {
-        let (s,s_meta) = Self::meta_part_from_string(s, &app)?;
-        let (p,p_meta) = Self::meta_part_from_string(p, &app)?;
-        let (o,o_meta) = Self::meta_part_from_string(o, &app)?;
+        let s = Self::meta_part_from_string(s, app)?;
+        let p = Self::meta_part_from_string(p, app)?;
+        let o = Self::meta_part_from_string(o, app)?;
+        Self::from_parts(app, s, p, o).await
+    }
+
+    pub async fn from_parts(app: &AppState, s: (QueryPart,QueryPartMeta), p: (QueryPart,QueryPartMeta), o: (QueryPart,QueryPartMeta)) -> Result<Self,WDQSErr> {
+        Self::from_parts_in_graph(app, s, p, o, GraphPart::Default).await
+    }
+
+    pub async fn from_parts_in_graph(app: &AppState, s: (QueryPart,QueryPartMeta), p: (QueryPart,QueryPartMeta), o: (QueryPart,QueryPartMeta), graph: GraphPart) -> Result<Self,WDQSErr> {
+        let ((s,s_meta),(p,p_meta),(o,o_meta)) = (s,p,o);
         let mut ret = Self {
             s, 
             p, 
@@ -106,7 +173,9 @@ impl QueryTriples {
             s_meta, 
             p_meta, 
             o_meta,
+            graph,
             result: HashMap::new(),
+            offset: 0,
         };
         ret.process(app).await?;
         Ok(ret)
@@ -148,11 +217,19 @@ impl QueryTriples {
         self.table_matches_part(&self.o, &table.names().2)
     }
 
+    fn table_matches_graph(&self, table: &DatabaseTable) -> bool {
+        match &self.graph {
+            GraphPart::Default => true,
+            GraphPart::Named(graph) => table.graph()==Some(graph.as_str()),
+            GraphPart::Variable(_) => table.graph().is_some(),
+        }
+    }
+
     async fn filter_tables(&self, app: &AppState) -> Vec<String> {
         let mut ret = vec![];
         for r in app.tables.iter() {
             let table = r.value();
-            if self.table_matches_property(table) && self.table_matches_subject(table) && self.table_matches_object(table) {
+            if self.table_matches_property(table) && self.table_matches_subject(table) && self.table_matches_object(table) && self.table_matches_graph(table) {
                 ret.push(r.key().to_owned());
             }
         }
@@ -164,13 +241,23 @@ impl QueryTriples {
         for table_name in tables {
             if let Some(table) = app.tables.get(&table_name) {
                 let names = table.names();
-                let key = format!("{}__{}__{}",names.0,names.1,names.2);
+                let key = self.group_key(&table, &names.2);
                 ret.entry(key).or_insert(vec![]).push(table_name);
             }
         }
         ret
     }
 
+    /// Tables with the same key have the same columns, with the same meaning
+    fn group_key(&self, table: &DatabaseTable, object_kind: &str) -> String {
+        let names = table.names();
+        let key = format!("{}__{}__{object_kind}",names.0,names.1);
+        match self.graph_return_param(table) {
+            Some((_,variable)) => format!("{key}__{}",variable.kind().unwrap_or_default()),
+            None => key,
+        }
+    }
+
     fn get_sql_conditions(&self, part: &QueryPart, key: &str) -> Vec<SqlPart> {
         match part {
             QueryPart::Element(element) => {
@@ -180,7 +267,11 @@ impl QueryTriples {
                     .filter(|(_num,part)|**part!=TypePart::Blank)
                     .map(|(num,_part)|num)
                     .zip(element.values().iter())
-                    .map(|(num,value)|SqlPart::new(format!("`{key}{num}`=?"),value.to_string()))
+                    .map(|(num,value)|SqlPart {
+                        sql: format!("`{key}{num}`={}",value.as_sql_placeholder()),
+                        values: value.as_sql_variable().into_iter().collect(),
+                        ..Default::default()
+                    })
                     .collect()
             }
             QueryPart::Unknown => vec![],
@@ -194,10 +285,7 @@ impl QueryTriples {
             let mut c = conditions.pop().unwrap(); // Safe
             conditions[0].and(&mut c);
         }
-        let mut condition = match conditions.pop() {
-            Some(condition) => condition,
-            None => return Ok(None),
-        };
+        let mut condition = conditions.pop().unwrap_or_default(); // No conditions means all rows
         condition.table = Some(table_name.to_string());
         Ok(Some(condition))
     }
@@ -219,7 +307,8 @@ impl QueryTriples {
                         return Err("QueryTriples::process_similar_tables: Parameter list if empty".into());
                     }
                     let params = params.join(",");
-                    part.sql = format!("SELECT {params} FROM `{table_name}` WHERE {}",part.sql);
+                    let where_clause = if part.sql.is_empty() { String::new() } else { format!(" WHERE {}",part.sql) };
+                    part.sql = format!("SELECT {params} FROM `{table_name}`{where_clause}");
                     part.variables = variables;
                 }
                 None => return Err("QueryTriples::process_similar_tables: Missing table name".into()),
@@ -234,6 +323,30 @@ impl QueryTriples {
         Ok(ret.pop())
     }
 
+    /// SQL literal for the predicate of all rows in a table, readable by Element::from_sql_values
+    fn predicate_constant(table: &DatabaseTable) -> String {
+        let values: Vec<String> = table.values().iter().map(|v|v.as_sql_stdout()).collect();
+        match values.len() {
+            0 => format!("\"{}\"",table.names().1),
+            1 => values[0].to_owned(),
+            _ => format!("CONCAT_WS('-',{})",values.join(",")),
+        }
+    }
+
+    /// The graph is part of the table name, so a GRAPH variable is returned as a constant
+    fn graph_return_param(&self, table: &DatabaseTable) -> Option<(String,SqlVariable)> {
+        let (variable,graph) = match (&self.graph,table.graph()) {
+            (GraphPart::Variable(variable),Some(graph)) => (variable,graph),
+            _ => return None,
+        };
+        let (kind,value) = match graph.strip_prefix("_:") {
+            Some(label) => ("BlankNode",label),
+            None => ("Url",graph),
+        };
+        let param = format!("\"{}\" AS `{variable}`",AppStateStdoutMySQL::sql_escape(value));
+        Some((param,SqlVariable { name: variable.to_owned(), kind: Some(kind.to_string()) }))
+    }
+
     async fn get_sql_return_params(&self, table_name: &str, app: &AppState) -> Result<(Vec<String>,Vec<SqlVariable>),WDQSErr> {
         let mut params = vec![];
         let mut ret_variables = vec![];
@@ -241,48 +354,39 @@ impl QueryTriples {
         let names = table.names().to_owned();
 
         if let Some(variable) = &self.s_meta.variable {
-            let sql_variables = Element::sql_var_from_name(&names.0,"k");
-            match sql_variables.len() {
-                0 => {}
-                1 => {
-                    let sql_variable = sql_variables[0].to_owned();
-                    params.push(format!("{sql_variable} AS `{variable}`"));
-                    ret_variables.push(SqlVariable{
-                        name:variable.to_owned(),
-                        kind: Some(table.names().0.to_owned()),
-                    });
-                }
-                _ => return Err("get_sql_return_params: Too many variables from sql_var_from_name".into()),
+            if let Some(sql_expression) = Element::sql_expression_from_name(&names.0,"k") {
+                params.push(format!("{sql_expression} AS `{variable}`"));
+                ret_variables.push(SqlVariable{
+                    name:variable.to_owned(),
+                    kind: Some(table.names().0.to_owned()),
+                });
             }
         }
 
-        if let Some(_variable) = &self.p_meta.variable {
-            // let fixed_value = table.values().join("_"); // TODO check
-            // let fixed_value = "SOMETHING"; // TODO FIXME
-            // params.push(format!("\"{fixed_value}\" AS `{variable}`"));
-            // ret_variables.push(SqlVariable {
-            //     name:variable.to_owned(),
-            //     kind: Some(table.names().1.to_owned()),
-            // });
-            todo!();
+        if let Some(variable) = &self.p_meta.variable {
+            // The property is part of the table name, so it is returned as a constant
+            params.push(format!("{} AS `{variable}`",Self::predicate_constant(&table)));
+            ret_variables.push(SqlVariable {
+                name:variable.to_owned(),
+                kind: Some(table.names().1.to_owned()),
+            });
         }
 
         if let Some(variable) = &self.o_meta.variable {
-            let sql_variables = Element::sql_var_from_name(&names.2,"k");
-            match sql_variables.len() {
-                0 => {}
-                1 => {
-                    let sql_variable = sql_variables[0].to_owned();
-                    params.push(format!("{sql_variable} AS `{variable}`"));
-                    ret_variables.push(SqlVariable {
-                        name:variable.to_owned(),
-                        kind: Some(table.names().2.to_owned()),
-                    });
-                }
-                _ => return Err("get_sql_return_params: Too many variables from sql_var_from_name".into()),
+            if let Some(sql_expression) = Element::sql_expression_from_name(&names.2,"v") {
+                params.push(format!("{sql_expression} AS `{variable}`"));
+                ret_variables.push(SqlVariable {
+                    name:variable.to_owned(),
+                    kind: Some(table.names().2.to_owned()),
+                });
             }
         }
 
+        if let Some((param,variable)) = self.graph_return_param(&table) {
+            params.push(param);
+            ret_variables.push(variable);
+        }
+
         Ok((params,ret_variables))
     }
 
@@ -299,7 +403,76 @@ impl QueryTriples {
         Ok(ret) // TODO FIXME
     }
 
+    /// Quantity amount, bounds and unit are columns in the quantity table of the value node
+    async fn process_quantity(&self, (column,kind,bounded): (usize,&str,bool), app: &AppState) -> Result<HashMap<String,SqlPart>,WDQSErr> {
+        let mut ret: HashMap<String,SqlPart> = HashMap::new();
+        let mut conditions = self.get_sql_conditions(&self.s,"k");
+        if let QueryPart::Element(element) = &self.o {
+            let numeric = matches!(element.name(),"Integer"|"Decimal");
+            if (kind=="Decimal" && !numeric) || (kind!="Decimal" && element.name()!=kind) {
+                return Ok(ret);
+            }
+            let sql = element.sql_encoded().ok_or_else(||WDQSErr::String(format!("QueryTriples::process_quantity: Cannot encode {element:?}")))?;
+            conditions.push(SqlPart { sql: format!("`v{column}`={sql}"), ..Default::default() });
+        }
+        if bounded {
+            conditions.push(SqlPart { sql: "`v4`=1".to_string(), ..Default::default() });
+        }
+        let property = Element::WikibaseOntology(QUANTITY_PREDICATE.into()).get_table_name();
+        let table_names: Vec<String> = app.tables.iter()
+            .filter(|r|r.value().property()==property && r.value().names().2=="Quantity" && self.table_matches_subject(r.value()) && self.table_matches_graph(r.value()))
+            .map(|r|r.key().to_owned())
+            .collect();
+        for table_name in table_names {
+            let table = match app.tables.get(&table_name) {
+                Some(table) => table.to_owned(),
+                None => continue,
+            };
+            let names = table.names();
+            let mut params = vec![];
+            let mut variables = vec![];
+            if let Some(variable) = &self.s_meta.variable {
+                if let Some(sql_expression) = Element::sql_expression_from_name(&names.0,"k") {
+                    params.push(format!("{sql_expression} AS `{variable}`"));
+                    variables.push(SqlVariable { name: variable.to_owned(), kind: Some(names.0.to_owned()) });
+                }
+            }
+            if let Some(variable) = &self.o_meta.variable {
+                params.push(format!("`v{column}` AS `{variable}`"));
+                variables.push(SqlVariable { name: variable.to_owned(), kind: Some(kind.to_string()) });
+            }
+            if let Some((param,variable)) = self.graph_return_param(&table) {
+                params.push(param);
+                variables.push(variable);
+            }
+            if params.is_empty() {
+                return Err("QueryTriples::process_quantity: Parameter list is empty".into());
+            }
+            let mut conditions = conditions.clone();
+            while conditions.len()>1 {
+                let mut c = conditions.pop().unwrap(); // Safe
+                conditions[0].and(&mut c);
+            }
+            let mut part = conditions.pop().unwrap_or_default();
+            let where_clause = if part.sql.is_empty() { String::new() } else { format!(" WHERE {}",part.sql) };
+            part.sql = format!("SELECT {} FROM `{table_name}`{where_clause}",params.join(","));
+            part.variables = variables;
+            let group_key = self.group_key(&table, kind);
+            match ret.get_mut(&group_key) {
+                Some(existing) => existing.union_all(&mut part),
+                None => { ret.insert(group_key,part); }
+            }
+        }
+        Ok(ret)
+    }
+
     pub async fn process(&mut self, app: &AppState) -> Result<(),WDQSErr> {
+        if let QueryPart::Element(Element::WikibaseOntology(name)) = &self.p {
+            if let Some(component) = Quantity::component(name) {
+                self.result = self.process_quantity(component, app).await?;
+                return Ok(());
+            }
+        }
         let result = self.filter_tables(app).await;
         let result = self.group_tables(result, app).await;
         let result = self.process_grouped_tables(result, app).await?;
@@ -307,35 +480,891 @@ impl QueryTriples {
         Ok(())
     }
 
+    /// Property paths `p+`, `p*` (transitive) and `p?` over a single property, as recursive CTEs.
+    /// Only tables linking a single-column type to itself are followed.
+    pub async fn from_closure(app: &AppState, s: (QueryPart,QueryPartMeta), p: Element, o: (QueryPart,QueryPartMeta), graph: GraphPart, zero_length: bool, transitive: bool) -> Result<Self,WDQSErr> {
+        let ((s,s_meta),(o,o_meta)) = (s,o);
+        let mut ret = Self {
+            s,
+            p: QueryPart::Element(p),
+            o,
+            s_meta,
+            p_meta: QueryPartMeta::default(),
+            o_meta,
+            graph,
+            result: HashMap::new(),
+            offset: 0,
+        };
+        if ret.s_meta.variable.is_none() && ret.o_meta.variable.is_none() {
+            return Err("QueryTriples::from_closure: Property path needs at least one variable".into());
+        }
+        if matches!(ret.graph,GraphPart::Variable(_)) {
+            return Err("QueryTriples::from_closure: Property paths in GRAPH need a graph IRI, not a variable".into());
+        }
+        let mut grouped_tables: HashMap<String,(String,Vec<String>)> = HashMap::new(); // The same property can be in several graphs
+        for table_name in ret.filter_tables(app).await {
+            let table = app.tables.get(&table_name).ok_or_else(|| WDQSErr::String(format!("from_closure: Missing table '{table_name}'")))?;
+            let names = table.names().to_owned();
+            if names.0!=names.2 || Element::sql_var_from_name(&names.0,"k").len()!=1 {
+                continue;
+            }
+            let group_key = format!("{}__{}__{}",names.0,names.1,names.2);
+            grouped_tables.entry(group_key).or_insert_with(||(names.0,vec![])).1.push(table_name);
+        }
+        for (group_key,(kind,table_names)) in grouped_tables {
+            let part = ret.closure_for_tables(&table_names, &kind, zero_length, transitive);
+            ret.result.insert(group_key,part);
+        }
+        Ok(ret)
+    }
+
+    /// Spatial search for wikibase:around and wikibase:box, on the tables with LatLon objects.
+    /// With `distance`, the distance from the center in km is returned as that variable.
+    pub async fn from_geo(app: &AppState, s: (QueryPart,QueryPartMeta), p: (QueryPart,QueryPartMeta), o: (QueryPart,QueryPartMeta), graph: GraphPart, search: &GeoSearch, distance: Option<&str>) -> Result<Self,WDQSErr> {
+        let ((s,s_meta),(p,p_meta),(o,o_meta)) = (s,p,o);
+        let mut ret = Self {
+            s,
+            p,
+            o,
+            s_meta,
+            p_meta,
+            o_meta,
+            graph,
+            result: HashMap::new(),
+            offset: 0,
+        };
+        for table_name in ret.filter_tables(app).await {
+            let table = match app.tables.get(&table_name) {
+                Some(table) => table.to_owned(),
+                None => continue,
+            };
+            let names = table.names();
+            if names.2!="LatLon" {
+                continue;
+            }
+            let (mut params,mut variables) = ret.get_sql_return_params(&table_name, app).await?;
+            if let (Some(distance),Some(sql)) = (distance,search.sql_distance("`v0`")) {
+                params.push(format!("{sql} AS `{distance}`"));
+                variables.push(SqlVariable { name: distance.to_owned(), kind: Some("Decimal".to_string()) });
+            }
+            if params.is_empty() {
+                return Err("QueryTriples::from_geo: Parameter list is empty".into());
+            }
+            let mut part = ret.sql_for_table(&table_name).await?.unwrap_or_default();
+            let mut conditions = vec![search.sql_condition("`v0`","`v1`")];
+            if !part.sql.is_empty() {
+                conditions.insert(0,part.sql);
+            }
+            part.sql = format!("SELECT {} FROM `{table_name}` WHERE {}",params.join(","),conditions.join(" AND "));
+            part.table = None;
+            part.variables = variables;
+            let group_key = ret.group_key(&table, &names.2);
+            match ret.result.get_mut(&group_key) {
+                Some(existing) => existing.union_all(&mut part), // Tables with the same columns
+                None => { ret.result.insert(group_key,part); }
+            }
+        }
+        Ok(ret)
+    }
+
+    fn closure_constant(part: &QueryPart) -> Option<String> {
+        match part {
+            QueryPart::Element(element) => element.values().first().map(|v|v.as_sql_stdout()),
+            QueryPart::Unknown => None,
+        }
+    }
+
+    /// Several tables, from different graphs, are followed as one
+    fn closure_for_tables(&self, table_names: &[String], kind: &str, zero_length: bool, transitive: bool) -> SqlPart {
+        let (edges,table_name) = match table_names {
+            [table_name] => (String::new(),format!("`{table_name}`")),
+            _ => {
+                let tables: Vec<String> = table_names.iter().map(|table_name|format!("SELECT `k0`,`v0` FROM `{table_name}`")).collect();
+                (format!("edges(`k0`,`v0`) AS ({}), ",tables.join(" UNION ALL ")),"edges".to_string())
+            }
+        };
+        // Walk forward from a constant subject, or backward from a constant object
+        let backward = Self::closure_constant(&self.s).is_none() && Self::closure_constant(&self.o).is_some();
+        let (anchor_column,anchor) = if backward { ("v0",Self::closure_constant(&self.o)) } else { ("k0",Self::closure_constant(&self.s)) };
+        let mut steps = vec![];
+        match &anchor {
+            Some(constant) => {
+                steps.push(format!("SELECT `k0`,`v0` FROM {table_name} WHERE `{anchor_column}`={constant}"));
+                if zero_length {
+                    steps.push(format!("SELECT {constant},{constant}"));
+                }
+            }
+            None => {
+                steps.push(format!("SELECT `k0`,`v0` FROM {table_name}"));
+                if zero_length {
+                    steps.push(format!("SELECT `k0`,`k0` FROM {table_name}"));
+                    steps.push(format!("SELECT `v0`,`v0` FROM {table_name}"));
+                }
+            }
+        }
+        if transitive {
+            // UNION (not UNION ALL) stops the recursion on cycles
+            steps.push(match backward {
+                true => format!("SELECT t.`k0`,c.`o` FROM closure c INNER JOIN {table_name} t ON t.`v0`=c.`s`"),
+                false => format!("SELECT c.`s`,t.`v0` FROM closure c INNER JOIN {table_name} t ON t.`k0`=c.`o`"),
+            });
+        }
+        let mut columns = vec![];
+        let mut variables = vec![];
+        for (meta,column) in [(&self.s_meta,"s"),(&self.o_meta,"o")] {
+            if let Some(variable) = &meta.variable {
+                if variables.iter().any(|v: &SqlVariable|v.name==*variable) {
+                    continue;
+                }
+                let sql = Element::sql_expression(kind, &[format!("`{column}`")]).unwrap_or_default();
+                columns.push(format!("{sql} AS `{variable}`"));
+                variables.push(SqlVariable { name: variable.to_owned(), kind: Some(kind.to_string()) });
+            }
+        }
+        let mut conditions = vec![];
+        if !backward {
+            if let Some(constant) = Self::closure_constant(&self.o) {
+                conditions.push(format!("`o`={constant}"));
+            }
+        }
+        if self.s_meta.variable.is_some() && self.s_meta.variable==self.o_meta.variable {
+            conditions.push("`s`=`o`".to_string());
+        }
+        let where_clause = if conditions.is_empty() { String::new() } else { format!(" WHERE {}",conditions.join(" AND ")) };
+        let recursive = if transitive { "RECURSIVE " } else { "" };
+        let sql = format!("WITH {recursive}{edges}closure(`s`,`o`) AS ({}) SELECT DISTINCT {} FROM closure{where_clause}",steps.join(" UNION "),columns.join(","));
+        SqlPart { sql, values: vec![], table: None, variables }
+    }
+
+    /// Adds the result groups of `other` as alternative solutions
+    pub fn union(&mut self, other: &Self) {
+        for (group_key,part) in &other.result {
+            let mut key = group_key.to_owned();
+            while self.result.contains_key(&key) {
+                key = format!("{key}|");
+            }
+            self.result.insert(key,part.to_owned());
+        }
+    }
+
     pub fn and(&mut self, other: &Self) -> Result<(),WDQSErr> {
         self.join("INNER JOIN", other)
     }
+
+    /// Left join for OPTIONAL; rows without a match keep NULL for the variables of `other`
+    pub fn optional(&mut self, other: &Self) -> Result<(),WDQSErr> {
+        self.join("LEFT JOIN", other)
+    }
     
+    /// Variables of both parts that share a name; None if any of them have incompatible types
+    fn common_variables(part: &SqlPart, other_part: &SqlPart) -> Option<Vec<SqlVariable>> {
+        let mut ret = vec![];
+        for v1 in &part.variables {
+            if let Some(v2) = other_part.variables.iter().find(|v2|v2.name==v1.name) {
+                match (&v1.kind,&v2.kind) {
+                    (Some(k1),Some(k2)) if k1!=k2 => return None,
+                    (None,_) => ret.push(v2.to_owned()),
+                    _ => ret.push(v1.to_owned()),
+                }
+            }
+        }
+        Some(ret)
+    }
+
+    fn join_key(variables_common: &[SqlVariable]) -> String {
+        if variables_common.is_empty() {
+            return "TRUE".to_string();
+        }
+        variables_common.iter().map(|v|format!("t1.`{v}`=t2.`{v}`")).collect::<Vec<_>>().join(" AND ")
+    }
+
+    fn join_parts(join: &str, part: &SqlPart, other_part: &SqlPart, variables_common: Vec<SqlVariable>) -> SqlPart {
+        let is_common = |v: &SqlVariable| variables_common.iter().any(|c|c.name==v.name);
+        let variables_t1: Vec<_> = part.variables.iter().filter(|v|!is_common(v)).cloned().collect();
+        let variables_t2: Vec<_> = other_part.variables.iter().filter(|v|!is_common(v)).cloned().collect();
+        let mut variables: Vec<_> = variables_common.iter().map(|v|format!("t1.`{v}`")).collect();
+        variables.append(&mut variables_t1.iter().map(|v|format!("t1.`{v}`")).collect());
+        variables.append(&mut variables_t2.iter().map(|v|format!("t2.`{v}`")).collect());
+        let join_key = Self::join_key(&variables_common);
+        let sql = format!("SELECT {} FROM ({}) AS t1\n{join} ({}) AS t2\nON {join_key}",variables.join(","),part.sql,other_part.sql);
+        let mut values = part.values.clone();
+        values.append(&mut other_part.values.clone());
+        let mut result_variables = variables_common;
+        result_variables.extend(variables_t1);
+        result_variables.extend(variables_t2);
+        SqlPart{ sql, values, table: None, variables: result_variables }
+    }
+
+    /// Rows of `part` that have a match in any of `other_parts`, or with `negated`, no match in any of them
+    fn semi_join_parts(part: &SqlPart, other_parts: &[(&SqlPart,Vec<SqlVariable>)], negated: bool) -> SqlPart {
+        let variables: Vec<_> = part.variables.iter().map(|v|format!("t1.`{v}`")).collect();
+        let mut values = part.values.clone();
+        let mut conditions = vec![];
+        let (exists,operator) = if negated { ("NOT EXISTS","\nAND ") } else { ("EXISTS","\nOR ") };
+        for (other_part,variables_common) in other_parts {
+            conditions.push(format!("{exists} (SELECT 1 FROM ({}) AS t2 WHERE {})",other_part.sql,Self::join_key(variables_common)));
+            values.append(&mut other_part.values.clone());
+        }
+        let sql = format!("SELECT {} FROM ({}) AS t1\nWHERE {}",variables.join(","),part.sql,conditions.join(operator));
+        SqlPart{ sql, values, table: None, variables: part.variables.clone() }
+    }
+
+    /// Keeps rows with (or with `negated`, without) a compatible row in `other`; groups that have no compatible row are
+    /// dropped, or with `negated` kept unchanged. With `shared_only`, groups without common variables are not compatible.
+    fn semi_join(&mut self, other: &Self, negated: bool, shared_only: bool) {
+        let mut result = HashMap::new();
+        let mut other_group_keys: Vec<&String> = other.result.keys().collect();
+        other_group_keys.sort();
+        for (group_key,part) in self.result.drain() {
+            let other_parts: Vec<_> = other_group_keys.iter()
+                .map(|other_group_key|&other.result[*other_group_key])
+                .filter_map(|other_part|Some((other_part,Self::common_variables(&part, other_part)?)))
+                .filter(|(_,variables_common)|!shared_only || !variables_common.is_empty())
+                .collect();
+            match (negated,other_parts.is_empty()) {
+                (true,true) => { result.insert(group_key,part); }
+                (false,true) => {}
+                _ => { result.insert(group_key,Self::semi_join_parts(&part, &other_parts, negated)); }
+            }
+        }
+        self.result = result;
+    }
+
+    /// FILTER EXISTS, or with `negated` FILTER NOT EXISTS
+    pub fn exists(&mut self, other: &Self, negated: bool) {
+        self.semi_join(other, negated, false)
+    }
+
+    /// MINUS; only rows sharing at least one variable with `other` can be removed
+    pub fn minus(&mut self, other: &Self) {
+        self.semi_join(other, true, true)
+    }
+
+    /// Joins every pair of result groups whose shared variables have the same type; without shared variables, on TRUE
     fn join(&mut self, join: &str, other: &Self) -> Result<(),WDQSErr> {
+        let is_left_join = join=="LEFT JOIN";
         let mut result = HashMap::new();
-        for (group_key,part) in &self.result {
-            if let Some(other_part) = other.result.get(group_key) {
-                let variables_common: Vec<_> = part.variables.iter().filter(|v|other_part.variables.contains(v)).cloned().collect();
-                if variables_common.is_empty() {
-                    return Err(format!("QueryTriples::and: No common variables between {self:?} and {other:?}").into());
+        let mut group_keys: Vec<&String> = self.result.keys().collect();
+        group_keys.sort();
+        let mut other_group_keys: Vec<&String> = other.result.keys().collect();
+        other_group_keys.sort();
+        for group_key in group_keys {
+            let part = &self.result[group_key];
+            let mut compatible = vec![];
+            for other_group_key in &other_group_keys {
+                let other_part = &other.result[*other_group_key];
+                let variables_common = match Self::common_variables(part, other_part) {
+                    Some(variables_common) => variables_common,
+                    None => continue,
+                };
+                compatible.push((*other_group_key,other_part,variables_common));
+            }
+            match (is_left_join,compatible.len()) {
+                (true,0) => {
+                    // Nothing to join, but all rows are kept
+                    result.insert(group_key.to_owned(),part.to_owned());
+                }
+                (true,2..) => {
+                    // A LEFT JOIN per pair would repeat unmatched rows; use inner joins plus the unmatched rows instead
+                    let other_parts: Vec<_> = compatible.iter().map(|(_,other_part,variables_common)|(*other_part,variables_common.to_owned())).collect();
+                    result.insert(group_key.to_owned(),Self::semi_join_parts(part, &other_parts, true));
+                    for (other_group_key,other_part,variables_common) in compatible {
+                        result.insert(format!("{group_key}/{other_group_key}"),Self::join_parts("INNER JOIN", part, other_part, variables_common));
+                    }
+                }
+                _ => {
+                    for (other_group_key,other_part,variables_common) in compatible {
+                        result.insert(format!("{group_key}/{other_group_key}"),Self::join_parts(join, part, other_part, variables_common));
+                    }
                 }
-                let variables_t1: Vec<_> = part.variables.iter().filter(|v|!other_part.variables.contains(v)).collect();
-                let variables_t2: Vec<_> = other_part.variables.iter().filter(|v|!part.variables.contains(v)).collect();
-                let mut variables: Vec<_> = variables_common.iter().map(|v|format!("t1.{v}")).collect();
-                variables.append(&mut variables_t1.iter().map(|v|format!("t1.{v}")).collect());
-                variables.append(&mut variables_t2.iter().map(|v|format!("t2.{v}")).collect());
-                let join_key: Vec<_> = variables_common.iter().map(|v|format!("t1.{v}=t2.{v}")).collect();
-                let sql = format!("SELECT {} FROM ({}) AS t1\n{join} ({}) AS t2\nON {}",variables.join(","),part.sql,other_part.sql,join_key.join(","));
-                let mut values = part.values.clone();
-                values.append(&mut other_part.values.clone());
-                result.insert(group_key.to_owned(),SqlPart{ sql, values, table: None, variables: variables_common });
             }
         }
         self.result = result ;
         Ok(())
     }
 
+    /// Adds `label_variable` with the first value of `property` for `variable` in `languages`, as the wikibase:label service does.
+    /// Alternative labels are concatenated; a missing label becomes the entity ID.
+    pub fn label(&mut self, app: &AppState, variable: &str, label_variable: &str, property: &Element, languages: &[String]) {
+        for part in self.result.values_mut() {
+            let kind = part.variables.iter().find(|v|v.name==variable).and_then(|v|v.kind.to_owned());
+            let sql = kind.and_then(|kind|Self::label_sql(app, &kind, &format!("t.`{variable}`"), property, languages));
+            let mut columns: Vec<String> = part.variables.iter().map(|v|format!("t.`{v}`")).collect();
+            columns.push(format!("{} AS `{label_variable}`",sql.as_deref().unwrap_or("NULL")));
+            part.sql = format!("SELECT {} FROM ({}) AS t",columns.join(","),part.sql);
+            part.variables.push(SqlVariable { name: label_variable.to_owned(), kind: sql.map(|_|"TextInLanguage".to_string()) });
+        }
+    }
+
+    fn label_sql(app: &AppState, kind: &str, column: &str, property: &Element, languages: &[String]) -> Option<String> {
+        if !kind.starts_with("Entity") || Element::sql_var_from_name(kind,"k").len()!=1 {
+            return None;
+        }
+        let property_name = property.get_table_name();
+        // Labels can be in several tables, one per graph
+        let mut table_names: Vec<String> = app.tables.iter()
+            .filter(|r|r.value().property()==property_name && r.value().names().0==kind && r.value().names().2=="TextInLanguage")
+            .map(|r|r.key().to_owned())
+            .collect();
+        table_names.sort();
+        let mut candidates = vec![];
+        if !table_names.is_empty() {
+            let table = match table_names.len() {
+                1 => format!("`{}`",table_names[0]),
+                _ => format!("({})",table_names.iter().map(|table_name|format!("SELECT `k0`,`v0`,`v1` FROM `{table_name}`")).collect::<Vec<_>>().join(" UNION ")),
+            };
+            let text = Element::sql_expression("Text", &["l.`v0`".to_string()])?;
+            let (value,limit) = match property {
+                Element::W3SkosCoreAltLabel => (format!("GROUP_CONCAT({text} SEPARATOR ', ')"),""),
+                _ => (text," LIMIT 1"),
+            };
+            for language in languages {
+                let language_id = DbOperationCacheValue::Text(language.to_owned()).as_sql_stdout();
+                let language = AppStateStdoutMySQL::sql_escape(language);
+                candidates.push(format!("(SELECT CONCAT({value},'@',\"{language}\") FROM {table} l WHERE l.`k0`={column} AND l.`v1`={language_id}{limit})"));
+            }
+        }
+        if matches!(property,Element::RdfSchemaLabel) {
+            // A TextInLanguage without language is a plain literal
+            candidates.push(format!("CONCAT(SUBSTRING_INDEX({},'/',-1),'@')",Element::sql_str_expression(kind, column)?));
+        }
+        match candidates.len() {
+            0 => None,
+            1 => candidates.pop(),
+            _ => Some(format!("COALESCE({})",candidates.join(","))),
+        }
+    }
+
+    /// Applies a condition to every result group; groups where the condition is FALSE or NULL are dropped
+    pub fn filter<F>(&mut self, condition: F) -> Result<(),WDQSErr>
+    where F: Fn(&SqlPart) -> Result<String,WDQSErr> {
+        let mut result = HashMap::new();
+        for (group_key,mut part) in self.result.drain() {
+            let condition = condition(&part)?;
+            if condition!="FALSE" && condition!="NULL" {
+                part.filter(&condition);
+                result.insert(group_key,part);
+            }
+        }
+        self.result = result;
+        Ok(())
+    }
+
+    /// Reduces every result group to the given variables, in that order; missing variables become NULL
+    pub fn project(&mut self, variables: &[String]) {
+        for part in self.result.values_mut() {
+            let mut columns = vec![];
+            let mut new_variables = vec![];
+            for name in variables {
+                match part.variables.iter().find(|v|v.name==*name) {
+                    Some(v) => {
+                        columns.push(format!("t.`{name}`"));
+                        new_variables.push(v.to_owned());
+                    }
+                    None => {
+                        columns.push(format!("NULL AS `{name}`"));
+                        new_variables.push(SqlVariable { name: name.to_owned(), kind: None });
+                    }
+                }
+            }
+            part.sql = format!("SELECT {} FROM ({}) AS t",columns.join(","),part.sql);
+            part.variables = new_variables;
+        }
+    }
+
+    /// SQL and element type of the input column of an aggregate, for one result group
+    fn aggregate_input(aggregate: &Aggregate, part: &SqlPart) -> (String,Option<String>) {
+        let variable = match &aggregate.variable {
+            Some(name) => part.variables.iter().find(|v|v.name==*name),
+            None => return ("1".to_string(),None), // COUNT(*)
+        };
+        let (column,kind) = match variable {
+            Some(SqlVariable { name, kind: Some(kind) }) => (format!("t.`{name}`"),kind.to_owned()),
+            _ => return ("NULL".to_string(),None), // Unbound in this group
+        };
+        let sql = match &aggregate.function {
+            AggregateFunction::Count if aggregate.distinct => format!("CONCAT('{kind}','|',{column})"), // Equal values of different types are not the same
+            AggregateFunction::Count => column,
+            AggregateFunction::Sum | AggregateFunction::Avg if matches!(kind.as_str(),"Integer"|"Decimal") => column,
+            AggregateFunction::Sum | AggregateFunction::Avg => "NULL".to_string(),
+            AggregateFunction::Min | AggregateFunction::Max | AggregateFunction::Sample => column,
+            AggregateFunction::GroupConcat(_) => Element::sql_str_expression(&kind, &column).unwrap_or_else(||"NULL".to_string()),
+        };
+        (sql,Some(kind))
+    }
+
+    /// Element type of the aggregate result, given the types of its input in all result groups
+    fn aggregate_kind(aggregate: &Aggregate, input_kinds: &[String]) -> Result<Option<String>,WDQSErr> {
+        Ok(Some(match &aggregate.function {
+            AggregateFunction::Count => "Integer".to_string(),
+            AggregateFunction::Sum if input_kinds.iter().all(|kind|kind=="Integer") => "Integer".to_string(),
+            AggregateFunction::Sum | AggregateFunction::Avg => "Decimal".to_string(),
+            AggregateFunction::GroupConcat(_) => "Text".to_string(),
+            AggregateFunction::Min | AggregateFunction::Max | AggregateFunction::Sample => {
+                let kind = match input_kinds.first() {
+                    Some(kind) => kind,
+                    None => return Ok(None), // Unbound in all result groups
+                };
+                if input_kinds.iter().any(|k|k!=kind) {
+                    return Err(format!("QueryTriples::aggregate: {aggregate:?} over values of different types").into());
+                }
+                if matches!(aggregate.function,AggregateFunction::Min|AggregateFunction::Max) && Element::sql_order_expression(kind, "").is_none() {
+                    return Err(format!("QueryTriples::aggregate: Can not compare {kind} values").into());
+                }
+                kind.to_owned()
+            }
+        }))
+    }
+
+    fn aggregate_sql(aggregate: &Aggregate, column: &str) -> String {
+        let distinct = if aggregate.distinct { "DISTINCT " } else { "" };
+        match &aggregate.function {
+            AggregateFunction::Count => format!("COUNT({distinct}{column})"),
+            AggregateFunction::Sum => format!("SUM({distinct}{column})"),
+            AggregateFunction::Min => format!("MIN({column})"),
+            AggregateFunction::Max => format!("MAX({column})"),
+            AggregateFunction::Avg => format!("AVG({distinct}{column})"),
+            AggregateFunction::Sample => format!("MIN({column})"),
+            AggregateFunction::GroupConcat(separator) => format!("GROUP_CONCAT({distinct}{column} SEPARATOR \"{}\")",AppStateStdoutMySQL::sql_escape(separator)),
+        }
+    }
+
+    /// Groups the solutions by the `group_by` variables, and computes the aggregates as new variables.
+    /// Result groups whose `group_by` variables have the same types are combined, as they can share solution groups.
+    pub fn aggregate(&mut self, group_by: &[String], aggregates: &[(String,Aggregate)]) -> Result<(),WDQSErr> {
+        let mut buckets: HashMap<Vec<Option<String>>,Vec<&SqlPart>> = HashMap::new();
+        for part in self.result.values() {
+            let kinds = group_by.iter().map(|name|part.variables.iter().find(|v|v.name==*name).and_then(|v|v.kind.to_owned())).collect();
+            buckets.entry(kinds).or_default().push(part);
+        }
+        let mut result = HashMap::new();
+        for (kinds,parts) in buckets {
+            let mut union = SqlPart::default();
+            let mut input_kinds = vec![vec![];aggregates.len()];
+            for part in parts {
+                let mut columns: Vec<String> = group_by.iter().zip(kinds.iter()).map(|(name,kind)|match kind {
+                    Some(_) => format!("t.`{name}`"),
+                    None => format!("NULL AS `{name}`"),
+                }).collect();
+                for (num,(_,aggregate)) in aggregates.iter().enumerate() {
+                    let (sql,kind) = Self::aggregate_input(aggregate, part);
+                    columns.push(format!("{sql} AS `__input{num}`"));
+                    input_kinds[num].extend(kind);
+                }
+                let mut sql = SqlPart {
+                    sql: format!("SELECT {} FROM ({}) AS t",columns.join(","),part.sql),
+                    values: part.values.clone(),
+                    ..Default::default()
+                };
+                if union.sql.is_empty() {
+                    union = sql;
+                } else {
+                    union.union_all(&mut sql);
+                }
+            }
+            let mut columns: Vec<String> = group_by.iter().map(|name|format!("t.`{name}`")).collect();
+            let mut variables: Vec<SqlVariable> = group_by.iter().zip(kinds.iter()).map(|(name,kind)|SqlVariable { name: name.to_owned(), kind: kind.to_owned() }).collect();
+            for (num,(name,aggregate)) in aggregates.iter().enumerate() {
+                columns.push(format!("{} AS `{name}`",Self::aggregate_sql(aggregate, &format!("t.`__input{num}`"))));
+                variables.push(SqlVariable { name: name.to_owned(), kind: Self::aggregate_kind(aggregate, &input_kinds[num])? });
+            }
+            let group_by_sql = if group_by.is_empty() { String::new() } else { format!(" GROUP BY {}",group_by.iter().map(|name|format!("t.`{name}`")).collect::<Vec<_>>().join(",")) };
+            union.sql = format!("SELECT {} FROM ({}) AS t{group_by_sql}",columns.join(","),union.sql);
+            union.variables = variables;
+            let group_key = kinds.iter().map(|kind|kind.to_owned().unwrap_or_default()).collect::<Vec<_>>().join("__");
+            result.insert(format!("aggregate__{group_key}"),union);
+        }
+        self.result = result;
+        Ok(())
+    }
+
+    /// Applies DISTINCT, ORDER BY, LIMIT and OFFSET to every result group.
+    /// OFFSET is only applied in SQL for a single group; otherwise each group is limited to LIMIT+OFFSET rows,
+    /// and `self.offset` rows are skipped after the groups are merged.
+    pub fn modify(&mut self, modifiers: &SqlModifiers) {
+        let offset = modifiers.offset.unwrap_or(0);
+        self.offset = offset;
+        if !modifiers.distinct && modifiers.order_by.is_empty() && modifiers.limit.is_none() && offset==0 {
+            return;
+        }
+        let single_group = self.result.len()==1;
+        for part in self.result.values_mut() {
+            let order_by: Option<Vec<(String,&str)>> = modifiers.order_by
+                .iter()
+                .filter_map(|(name,descending)|{
+                    let kind = part.variables.iter().find(|v|v.name==*name)?.kind.as_ref()?; // Unbound everywhere
+                    let direction = if *descending { " DESC" } else { "" };
+                    Some(Element::sql_order_expression(kind, &format!("t.`{name}`")).map(|sql|(sql,direction)))
+                })
+                .collect();
+            let order_by = match order_by {
+                Some(order_by) => order_by,
+                None => { // Not sortable in SQL
+                    part.sql = format!("SELECT {}* FROM ({}) AS t",if modifiers.distinct { "DISTINCT " } else { "" },part.sql);
+                    continue;
+                }
+            };
+            let hidden = part.variables.iter().any(|v|!modifiers.variables.contains(&v.name));
+            let mut sql = if modifiers.distinct && hidden {
+                if !single_group {
+                    // Rows are only distinct for the result variables once the groups are merged
+                    part.sql = format!("SELECT DISTINCT * FROM ({}) AS t",part.sql);
+                    continue;
+                }
+                // Distinct result rows, sorted by the first row of each in the order
+                let columns: Vec<String> = modifiers.variables.iter().map(|name|format!("t.`{name}`")).collect();
+                let order_columns: Vec<String> = order_by.iter().enumerate()
+                    .map(|(num,(sql,direction))|format!("{}({sql}) AS `__order{num}`",if direction.is_empty() { "MIN" } else { "MAX" }))
+                    .collect();
+                let mut sql = format!("SELECT {} FROM (SELECT {},{} FROM ({}) AS t GROUP BY {}) AS t",columns.join(","),columns.join(","),order_columns.join(","),part.sql,columns.join(","));
+                let order_by: Vec<String> = order_by.iter().enumerate().map(|(num,(_,direction))|format!("`__order{num}`{direction}")).collect();
+                if !order_by.is_empty() {
+                    sql += &format!(" ORDER BY {}",order_by.join(","));
+                }
+                part.variables.retain(|v|modifiers.variables.contains(&v.name));
+                sql
+            } else {
+                let mut sql = format!("SELECT {}* FROM ({}) AS t",if modifiers.distinct { "DISTINCT " } else { "" },part.sql);
+                if !order_by.is_empty() {
+                    let order_by: Vec<String> = order_by.iter().map(|(sql,direction)|format!("{sql}{direction}")).collect();
+                    sql += &format!(" ORDER BY {}",order_by.join(","));
+                }
+                sql
+            };
+            if single_group {
+                self.offset = 0;
+                match (modifiers.limit,offset) {
+                    (Some(limit),0) => sql += &format!(" LIMIT {limit}"),
+                    (Some(limit),offset) => sql += &format!(" LIMIT {limit} OFFSET {offset}"),
+                    (None,0) => {}
+                    (None,offset) => sql += &format!(" LIMIT 18446744073709551615 OFFSET {offset}"), // MySQL has no OFFSET without LIMIT
+                }
+            } else if let Some(limit) = modifiers.limit {
+                sql += &format!(" LIMIT {}",limit+offset);
+            }
+            part.sql = sql;
+        }
+    }
+
     pub async fn run(&self, app: &AppState) -> Result<HashMap<String,DatabaseQueryResult>,WDQSErr> {
         app.run_query(&self).await
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_state::tests::app_with_tables, sparql_query::SparqlQuery};
+
+    /// Compares the group key, SQL and variables with their types of every result group, sorted by group key
+    async fn assert_compiles(app: &AppState, query: &str, expected: &[(&str,&str,&str)]) {
+        let qt = SparqlQuery::parse(query).unwrap().compile(app).await.unwrap();
+        let mut result: Vec<_> = qt.result.iter()
+            .map(|(key,part)|{
+                let variables: Vec<_> = part.variables.iter().map(|v|format!("{}:{}",v.name,v.kind().unwrap_or("-"))).collect();
+                (key.to_owned(),part.sql.to_owned(),variables.join(","))
+            })
+            .collect();
+        result.sort();
+        let expected: Vec<_> = expected.iter().map(|(key,sql,variables)|(key.to_string(),sql.to_string(),variables.to_string())).collect();
+        assert_eq!(result,expected,"{query}");
+    }
+
+    #[tokio::test]
+    async fn test_predicate_variable() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q1> <http://www.w3.org/2000/01/rdf-schema#label> \"x\"@en .",
+        ]);
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> SELECT ?p ?o WHERE { wd:Q1 ?p ?o }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`p`,t.`o` FROM (SELECT \"P31\" AS `p`,`v0` AS `o` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `k0`=1) AS t",
+                "p:PropertyDirect,o:EntityItem",
+            ),
+            (
+                "EntityItem__RdfSchemaLabel__TextInLanguage",
+                "SELECT t.`p`,t.`o` FROM (SELECT \"RdfSchemaLabel\" AS `p`,CONCAT((SELECT `value` FROM `texts` WHERE `id`=`v0`),'@',(SELECT `value` FROM `texts` WHERE `id`=`v1`)) AS `o` FROM `data__RdfSchemaLabel__EntityItem__TextInLanguage` WHERE `k0`=1) AS t",
+                "p:RdfSchemaLabel,o:TextInLanguage",
+            ),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn test_optional_first() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+        ]);
+        // Left joined onto a single row, so there is a solution with NULLs even without any match
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT * WHERE { OPTIONAL { ?s wdt:P31 ?o } }", &[
+            (
+                "empty/EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`s`,t.`o` FROM (SELECT t2.`s`,t2.`o` FROM (SELECT 1) AS t1\nLEFT JOIN (SELECT `k0` AS `s`,`v0` AS `o` FROM `data__PropertyDirect_P31__EntityItem__EntityItem`) AS t2\nON TRUE) AS t",
+                "s:EntityItem,o:EntityItem",
+            ),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn test_cross_join() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P2> \"x\" .",
+        ]);
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT * { ?a wdt:P1 ?b . ?c wdt:P2 ?d }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem/EntityItem__PropertyDirect__Text",
+                "SELECT t.`a`,t.`b`,t.`c`,t.`d` FROM (SELECT t1.`a`,t1.`b`,t2.`c`,t2.`d` FROM (SELECT `k0` AS `a`,`v0` AS `b` FROM `data__PropertyDirect_P1__EntityItem__EntityItem`) AS t1\nINNER JOIN (SELECT `k0` AS `c`,(SELECT `value` FROM `texts` WHERE `id`=`v0`) AS `d` FROM `data__PropertyDirect_P2__EntityItem__Text`) AS t2\nON TRUE) AS t",
+                "a:EntityItem,b:EntityItem,c:EntityItem,d:Text",
+            ),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn test_join_groups() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P2> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P2> \"x\" .",
+            "<http://www.wikidata.org/entity/P1> <http://www.wikidata.org/prop/direct/P2> \"y\" .",
+        ]);
+        // Every pair of groups whose shared variables have the same types is joined; the table with a property as subject is not
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?person ?v { ?person wdt:P31 wd:Q5 . ?person wdt:P2 ?v }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem/EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`person`,t.`v` FROM (SELECT t1.`person`,t2.`v` FROM (SELECT `k0` AS `person` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t1\nINNER JOIN (SELECT `k0` AS `person`,`v0` AS `v` FROM `data__PropertyDirect_P2__EntityItem__EntityItem`) AS t2\nON t1.`person`=t2.`person`) AS t",
+                "person:EntityItem,v:EntityItem",
+            ),
+            (
+                "EntityItem__PropertyDirect__EntityItem/EntityItem__PropertyDirect__Text",
+                "SELECT t.`person`,t.`v` FROM (SELECT t1.`person`,t2.`v` FROM (SELECT `k0` AS `person` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t1\nINNER JOIN (SELECT `k0` AS `person`,(SELECT `value` FROM `texts` WHERE `id`=`v0`) AS `v` FROM `data__PropertyDirect_P2__EntityItem__Text`) AS t2\nON t1.`person`=t2.`person`) AS t",
+                "person:EntityItem,v:Text",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?person ?v { ?person wdt:P31 wd:Q5 OPTIONAL { ?person wdt:P2 ?v } }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`person`,NULL AS `v` FROM (SELECT t1.`person` FROM (SELECT `k0` AS `person` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t1\nWHERE NOT EXISTS (SELECT 1 FROM (SELECT `k0` AS `person`,`v0` AS `v` FROM `data__PropertyDirect_P2__EntityItem__EntityItem`) AS t2 WHERE t1.`person`=t2.`person`)\nAND NOT EXISTS (SELECT 1 FROM (SELECT `k0` AS `person`,(SELECT `value` FROM `texts` WHERE `id`=`v0`) AS `v` FROM `data__PropertyDirect_P2__EntityItem__Text`) AS t2 WHERE t1.`person`=t2.`person`)) AS t",
+                "person:EntityItem,v:-",
+            ),
+            (
+                "EntityItem__PropertyDirect__EntityItem/EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`person`,t.`v` FROM (SELECT t1.`person`,t2.`v` FROM (SELECT `k0` AS `person` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t1\nINNER JOIN (SELECT `k0` AS `person`,`v0` AS `v` FROM `data__PropertyDirect_P2__EntityItem__EntityItem`) AS t2\nON t1.`person`=t2.`person`) AS t",
+                "person:EntityItem,v:EntityItem",
+            ),
+            (
+                "EntityItem__PropertyDirect__EntityItem/EntityItem__PropertyDirect__Text",
+                "SELECT t.`person`,t.`v` FROM (SELECT t1.`person`,t2.`v` FROM (SELECT `k0` AS `person` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t1\nINNER JOIN (SELECT `k0` AS `person`,(SELECT `value` FROM `texts` WHERE `id`=`v0`) AS `v` FROM `data__PropertyDirect_P2__EntityItem__Text`) AS t2\nON t1.`person`=t2.`person`) AS t",
+                "person:EntityItem,v:Text",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT * { ?a wdt:P31 ?b . ?a wdt:P2 ?b }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem/EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`a`,t.`b` FROM (SELECT t1.`a`,t1.`b` FROM (SELECT `k0` AS `a`,`v0` AS `b` FROM `data__PropertyDirect_P31__EntityItem__EntityItem`) AS t1\nINNER JOIN (SELECT `k0` AS `a`,`v0` AS `b` FROM `data__PropertyDirect_P2__EntityItem__EntityItem`) AS t2\nON t1.`a`=t2.`a` AND t1.`b`=t2.`b`) AS t",
+                "a:EntityItem,b:EntityItem",
+            ),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn test_limit_offset() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1082> \"+5\"^^<http://www.w3.org/2001/XMLSchema#decimal> .",
+            "<http://www.wikidata.org/entity/Q1> <http://www.w3.org/2000/01/rdf-schema#label> \"x\"@en .",
+        ]);
+        // OFFSET is applied in SQL for a single group, and after merging for several
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?s WHERE { ?s wdt:P1082 ?n } ORDER BY ?n LIMIT 10 OFFSET 20", &[
+            (
+                "EntityItem__PropertyDirect__Decimal",
+                "SELECT * FROM (SELECT t.`s`,t.`n` FROM (SELECT `k0` AS `s`,`v0` AS `n` FROM `data__PropertyDirect_P1082__EntityItem__Decimal`) AS t) AS t ORDER BY t.`n` LIMIT 10 OFFSET 20",
+                "s:EntityItem,n:Decimal",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?s WHERE { ?s wdt:P1082 ?n } OFFSET 5", &[
+            (
+                "EntityItem__PropertyDirect__Decimal",
+                "SELECT * FROM (SELECT t.`s` FROM (SELECT `k0` AS `s`,`v0` AS `n` FROM `data__PropertyDirect_P1082__EntityItem__Decimal`) AS t) AS t LIMIT 18446744073709551615 OFFSET 5",
+                "s:EntityItem",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT DISTINCT ?s WHERE { ?s wdt:P1082 ?n } ORDER BY DESC(?n) LIMIT 10 OFFSET 20", &[
+            (
+                "EntityItem__PropertyDirect__Decimal",
+                "SELECT t.`s` FROM (SELECT t.`s`,MAX(t.`n`) AS `__order0` FROM (SELECT t.`s`,t.`n` FROM (SELECT `k0` AS `s`,`v0` AS `n` FROM `data__PropertyDirect_P1082__EntityItem__Decimal`) AS t) AS t GROUP BY t.`s`) AS t ORDER BY `__order0` DESC LIMIT 10 OFFSET 20",
+                "s:EntityItem",
+            ),
+        ]).await;
+        assert_compiles(&app, "SELECT ?s ?o WHERE { ?s ?p ?o } LIMIT 10 OFFSET 20", &[
+            (
+                "EntityItem__PropertyDirect__Decimal",
+                "SELECT * FROM (SELECT t.`s`,t.`o` FROM (SELECT `k0` AS `s`,\"P1082\" AS `p`,`v0` AS `o` FROM `data__PropertyDirect_P1082__EntityItem__Decimal`) AS t) AS t LIMIT 30",
+                "s:EntityItem,o:Decimal",
+            ),
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT * FROM (SELECT t.`s`,t.`o` FROM (SELECT `k0` AS `s`,\"P31\" AS `p`,`v0` AS `o` FROM `data__PropertyDirect_P31__EntityItem__EntityItem`) AS t) AS t LIMIT 30",
+                "s:EntityItem,o:EntityItem",
+            ),
+            (
+                "EntityItem__RdfSchemaLabel__TextInLanguage",
+                "SELECT * FROM (SELECT t.`s`,t.`o` FROM (SELECT `k0` AS `s`,\"RdfSchemaLabel\" AS `p`,CONCAT((SELECT `value` FROM `texts` WHERE `id`=`v0`),'@',(SELECT `value` FROM `texts` WHERE `id`=`v1`)) AS `o` FROM `data__RdfSchemaLabel__EntityItem__TextInLanguage`) AS t) AS t LIMIT 30",
+                "s:EntityItem,o:TextInLanguage",
+            ),
+        ]).await;
+        // Rows still to skip after merging the groups
+        let qt = SparqlQuery::parse("PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?s WHERE { ?s wdt:P1082 ?n } OFFSET 5").unwrap().compile(&app).await.unwrap();
+        assert_eq!(qt.offset,0);
+        let qt = SparqlQuery::parse("SELECT ?s ?o WHERE { ?s ?p ?o } LIMIT 10 OFFSET 20").unwrap().compile(&app).await.unwrap();
+        assert_eq!(qt.offset,20);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1082> \"+5\"^^<http://www.w3.org/2001/XMLSchema#decimal> .",
+        ]);
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?c (COUNT(*) AS ?n) WHERE { ?s wdt:P31 ?c } GROUP BY ?c", &[
+            (
+                "aggregate__EntityItem",
+                "SELECT t.`c`,t.`n` FROM (SELECT t.`c`,COUNT(t.`__input0`) AS `n` FROM (SELECT t.`c`,1 AS `__input0` FROM (SELECT `k0` AS `s`,`v0` AS `c` FROM `data__PropertyDirect_P31__EntityItem__EntityItem`) AS t) AS t GROUP BY t.`c`) AS t",
+                "c:EntityItem,n:Integer",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT (SUM(?p) AS ?total) (COUNT(DISTINCT ?s) AS ?n) WHERE { ?s wdt:P1082 ?p }", &[
+            (
+                "aggregate__",
+                "SELECT t.`total`,t.`n` FROM (SELECT SUM(t.`__input0`) AS `total`,COUNT(DISTINCT t.`__input1`) AS `n` FROM (SELECT t.`p` AS `__input0`,CONCAT('EntityItem','|',t.`s`) AS `__input1` FROM (SELECT `k0` AS `s`,`v0` AS `p` FROM `data__PropertyDirect_P1082__EntityItem__Decimal`) AS t) AS t) AS t",
+                "total:Decimal,n:Integer",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?c (MAX(?p) AS ?m) WHERE { ?s wdt:P31 ?c ; wdt:P1082 ?p } GROUP BY ?c HAVING (COUNT(?s) > 1)", &[
+            (
+                "aggregate__EntityItem",
+                "SELECT t.`c`,t.`m` FROM (SELECT * FROM (SELECT t.`c`,MAX(t.`__input0`) AS `m`,COUNT(t.`__input1`) AS `__aggregate1` FROM (SELECT t.`c`,t.`p` AS `__input0`,t.`s` AS `__input1` FROM (SELECT t1.`s`,t1.`c`,t2.`p` FROM (SELECT `k0` AS `s`,`v0` AS `c` FROM `data__PropertyDirect_P31__EntityItem__EntityItem`) AS t1\nINNER JOIN (SELECT `k0` AS `s`,`v0` AS `p` FROM `data__PropertyDirect_P1082__EntityItem__Decimal`) AS t2\nON t1.`s`=t2.`s`) AS t) AS t GROUP BY t.`c`) AS t WHERE (t.`__aggregate1`>1)) AS t",
+                "c:EntityItem,m:Decimal",
+            ),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn test_property_paths() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q5> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q6> .",
+        ]);
+        // Hidden sequence variables are numbered per query
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?c WHERE { wd:Q1 wdt:P31/wdt:P279 ?c }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem/EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`c` FROM (SELECT t1.`__path0`,t2.`c` FROM (SELECT `v0` AS `__path0` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `k0`=1) AS t1\nINNER JOIN (SELECT `k0` AS `__path0`,`v0` AS `c` FROM `data__PropertyDirect_P279__EntityItem__EntityItem`) AS t2\nON t1.`__path0`=t2.`__path0`) AS t",
+                "c:EntityItem",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?c WHERE { wd:Q5 wdt:P279* ?c }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`c` FROM (WITH RECURSIVE closure(`s`,`o`) AS (SELECT `k0`,`v0` FROM `data__PropertyDirect_P279__EntityItem__EntityItem` WHERE `k0`=5 UNION SELECT 5,5 UNION SELECT c.`s`,t.`v0` FROM closure c INNER JOIN `data__PropertyDirect_P279__EntityItem__EntityItem` t ON t.`k0`=c.`o`) SELECT DISTINCT `o` AS `c` FROM closure) AS t",
+                "c:EntityItem",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?s WHERE { ?s wdt:P31/wdt:P279+ wd:Q6 }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem/EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`s` FROM (SELECT t1.`__path0`,t1.`s` FROM (SELECT `k0` AS `s`,`v0` AS `__path0` FROM `data__PropertyDirect_P31__EntityItem__EntityItem`) AS t1\nINNER JOIN (WITH RECURSIVE closure(`s`,`o`) AS (SELECT `k0`,`v0` FROM `data__PropertyDirect_P279__EntityItem__EntityItem` WHERE `v0`=6 UNION SELECT t.`k0`,c.`o` FROM closure c INNER JOIN `data__PropertyDirect_P279__EntityItem__EntityItem` t ON t.`v0`=c.`s`) SELECT DISTINCT `s` AS `__path0` FROM closure) AS t2\nON t1.`__path0`=t2.`__path0`) AS t",
+                "s:EntityItem",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?c WHERE { wd:Q5 wdt:P279? ?c }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`c` FROM (WITH closure(`s`,`o`) AS (SELECT `k0`,`v0` FROM `data__PropertyDirect_P279__EntityItem__EntityItem` WHERE `k0`=5 UNION SELECT 5,5) SELECT DISTINCT `o` AS `c` FROM closure) AS t",
+                "c:EntityItem",
+            ),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn test_semi_join() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P40> <http://www.wikidata.org/entity/Q2> .",
+        ]);
+        // MINUS without shared variables removes nothing
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?s WHERE { ?s wdt:P31 wd:Q5 MINUS { ?s wdt:P40 ?d } }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`s` FROM (SELECT t1.`s` FROM (SELECT `k0` AS `s` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t1\nWHERE NOT EXISTS (SELECT 1 FROM (SELECT `k0` AS `s`,`v0` AS `d` FROM `data__PropertyDirect_P40__EntityItem__EntityItem`) AS t2 WHERE t1.`s`=t2.`s`)) AS t",
+                "s:EntityItem",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?s WHERE { ?s wdt:P31 wd:Q5 FILTER NOT EXISTS { ?s wdt:P40 ?d } }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`s` FROM (SELECT t1.`s` FROM (SELECT `k0` AS `s` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t1\nWHERE NOT EXISTS (SELECT 1 FROM (SELECT `k0` AS `s`,`v0` AS `d` FROM `data__PropertyDirect_P40__EntityItem__EntityItem`) AS t2 WHERE t1.`s`=t2.`s`)) AS t",
+                "s:EntityItem",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?s WHERE { ?s wdt:P31 wd:Q5 FILTER EXISTS { ?s wdt:P40 ?d } }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`s` FROM (SELECT t1.`s` FROM (SELECT `k0` AS `s` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t1\nWHERE EXISTS (SELECT 1 FROM (SELECT `k0` AS `s`,`v0` AS `d` FROM `data__PropertyDirect_P40__EntityItem__EntityItem`) AS t2 WHERE t1.`s`=t2.`s`)) AS t",
+                "s:EntityItem",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wd: <http://www.wikidata.org/entity/> PREFIX wdt: <http://www.wikidata.org/prop/direct/> SELECT ?s WHERE { ?s wdt:P31 wd:Q5 MINUS { ?x wdt:P40 ?d } }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`s` FROM (SELECT `k0` AS `s` FROM `data__PropertyDirect_P31__EntityItem__EntityItem` WHERE `v0`=5) AS t",
+                "s:EntityItem",
+            ),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn test_label_service() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> .",
+            "<http://www.wikidata.org/entity/Q5> <http://www.w3.org/2000/01/rdf-schema#label> \"human\"@en .",
+            "<http://www.wikidata.org/entity/Q5> <http://www.w3.org/2000/01/rdf-schema#label> \"Mensch\"@de <http://example.org/graph> .",
+            "<http://www.wikidata.org/entity/Q5> <http://www.w3.org/2004/02/skos/core#altLabel> \"person\"@en .",
+        ]);
+        // Labels are looked up in every graph
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> PREFIX wikibase: <http://wikiba.se/ontology#> PREFIX bd: <http://www.bigdata.com/rdf#> SELECT ?c ?cLabel WHERE { ?s wdt:P31 ?c SERVICE wikibase:label { bd:serviceParam wikibase:language \"de,en\" } }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`c`,t.`cLabel` FROM (SELECT t.`s`,t.`c`,COALESCE((SELECT CONCAT((SELECT `value` FROM `texts` WHERE `id`=l.`v0`),'@',\"de\") FROM (SELECT `k0`,`v0`,`v1` FROM `data__RdfSchemaLabel__EntityItem__TextInLanguage` UNION SELECT `k0`,`v0`,`v1` FROM `data__RdfSchemaLabel__EntityItem__TextInLanguage__g4a7e979a4b`) l WHERE l.`k0`=t.`c` AND l.`v1`=(SELECT `id` FROM `texts` WHERE `value`=\"de\") LIMIT 1),(SELECT CONCAT((SELECT `value` FROM `texts` WHERE `id`=l.`v0`),'@',\"en\") FROM (SELECT `k0`,`v0`,`v1` FROM `data__RdfSchemaLabel__EntityItem__TextInLanguage` UNION SELECT `k0`,`v0`,`v1` FROM `data__RdfSchemaLabel__EntityItem__TextInLanguage__g4a7e979a4b`) l WHERE l.`k0`=t.`c` AND l.`v1`=(SELECT `id` FROM `texts` WHERE `value`=\"en\") LIMIT 1),CONCAT(SUBSTRING_INDEX(CONCAT('http://www.wikidata.org/entity/Q',t.`c`),'/',-1),'@')) AS `cLabel` FROM (SELECT `k0` AS `s`,`v0` AS `c` FROM `data__PropertyDirect_P31__EntityItem__EntityItem`) AS t) AS t",
+                "c:EntityItem,cLabel:TextInLanguage",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> PREFIX wikibase: <http://wikiba.se/ontology#> PREFIX bd: <http://www.bigdata.com/rdf#> SELECT ?c ?cAltLabel WHERE { ?s wdt:P31 ?c SERVICE wikibase:label { bd:serviceParam wikibase:language \"en\" } }", &[
+            (
+                "EntityItem__PropertyDirect__EntityItem",
+                "SELECT t.`c`,t.`cAltLabel` FROM (SELECT t.`s`,t.`c`,(SELECT CONCAT(GROUP_CONCAT((SELECT `value` FROM `texts` WHERE `id`=l.`v0`) SEPARATOR ', '),'@',\"en\") FROM `data__W3SkosCoreAltLabel__EntityItem__TextInLanguage` l WHERE l.`k0`=t.`c` AND l.`v1`=(SELECT `id` FROM `texts` WHERE `value`=\"en\")) AS `cAltLabel` FROM (SELECT `k0` AS `s`,`v0` AS `c` FROM `data__PropertyDirect_P31__EntityItem__EntityItem`) AS t) AS t",
+                "c:EntityItem,cAltLabel:TextInLanguage",
+            ),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn test_geo_service() {
+        let app = app_with_tables(&[
+            "<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P625> \"Point(13.38 52.52)\"^^<http://www.opengis.net/ont/geosparql#wktLiteral> .",
+        ]);
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> PREFIX wikibase: <http://wikiba.se/ontology#> PREFIX bd: <http://www.bigdata.com/rdf#> PREFIX geo: <http://www.opengis.net/ont/geosparql#> SELECT ?place ?dist WHERE { SERVICE wikibase:around { ?place wdt:P625 ?loc . bd:serviceParam wikibase:center \"Point(13.4 52.5)\"^^geo:wktLiteral . bd:serviceParam wikibase:radius \"10\" . bd:serviceParam wikibase:distance ?dist } }", &[
+            (
+                "EntityItem__PropertyDirect__LatLon",
+                "SELECT t.`place`,t.`dist` FROM (SELECT `k0` AS `place`,CONCAT('<http://www.wikidata.org/entity/Q',`v1`,'> ',ST_AsText(`v0`)) AS `loc`,ST_Distance_Sphere(Point(MOD(ST_X(`v0`)+540,360)-180,ST_Y(`v0`)),ST_GeomFromText('Point(13.4 52.5)'),6371000)/1000 AS `dist` FROM `data__PropertyDirect_P625__EntityItem__LatLon` WHERE `v1`=2 AND MBRContains(ST_GeomFromText('Polygon((13.252270271560546 52.41006783940813,13.547729728439455 52.41006783940813,13.547729728439455 52.58993216059187,13.252270271560546 52.58993216059187,13.252270271560546 52.41006783940813))'),`v0`) AND ST_Distance_Sphere(Point(MOD(ST_X(`v0`)+540,360)-180,ST_Y(`v0`)),ST_GeomFromText('Point(13.4 52.5)'),6371000)/1000<=10) AS t",
+                "place:EntityItem,dist:Decimal",
+            ),
+        ]).await;
+        assert_compiles(&app, "PREFIX wdt: <http://www.wikidata.org/prop/direct/> PREFIX wikibase: <http://wikiba.se/ontology#> PREFIX bd: <http://www.bigdata.com/rdf#> PREFIX geo: <http://www.opengis.net/ont/geosparql#> SELECT ?place WHERE { SERVICE wikibase:box { ?place wdt:P625 ?loc . bd:serviceParam wikibase:cornerSouthWest \"Point(13 52)\"^^geo:wktLiteral . bd:serviceParam wikibase:cornerNorthEast \"Point(14 53)\"^^geo:wktLiteral } }", &[
+            (
+                "EntityItem__PropertyDirect__LatLon",
+                "SELECT t.`place` FROM (SELECT `k0` AS `place`,CONCAT('<http://www.wikidata.org/entity/Q',`v1`,'> ',ST_AsText(`v0`)) AS `loc` FROM `data__PropertyDirect_P625__EntityItem__LatLon` WHERE `v1`=2 AND MBRContains(ST_GeomFromText('Polygon((13 52,14 52,14 53,13 53,13 52))'),`v0`)) AS t",
+                "place:EntityItem",
+            ),
+        ]).await;
+    }
+}
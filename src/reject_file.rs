@@ -0,0 +1,85 @@
+use std::{fs::{File, OpenOptions}, io::{BufWriter, Write}, sync::{Mutex, atomic::{AtomicU64, Ordering}}};
+use serde_json::json;
+use crate::{checkpoint::InputLine, error::WDQSErr};
+
+/// Records the input lines that could not be imported, as one JSON object per line with line number, byte offset and reason.
+/// Without a file, they are printed to stderr.
+#[derive(Debug, Default)]
+pub struct RejectFile {
+    filename: Option<String>,
+    writer: Option<Mutex<BufWriter<File>>>,
+    count: AtomicU64,
+}
+
+impl RejectFile {
+    /// Appends to the file, so that a resumed import keeps the lines rejected before
+    pub fn create(filename: &str) -> Result<Self,WDQSErr> {
+        let file = OpenOptions::new().create(true).append(true).open(filename)?;
+        Ok(Self {
+            filename: Some(filename.to_string()),
+            writer: Some(Mutex::new(BufWriter::new(file))),
+            count: AtomicU64::new(0),
+        })
+    }
+
+    pub fn reject(&self, line: &InputLine, reason: &WDQSErr) -> Result<(),WDQSErr> {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        match &self.writer {
+            Some(writer) => {
+                let record = json!({
+                    "line": line.number,
+                    "offset": line.offset,
+                    "reason": reason.kind(),
+                    "message": reason.to_string(),
+                    "text": line.text,
+                });
+                let mut writer = writer.lock().map_err(|e|WDQSErr::String(format!("RejectFile::reject: {e}")))?;
+                writeln!(writer, "{record}")?;
+            }
+            None => eprintln!("REJECTED LINE {} ({}):{reason} line:\n{}\n",line.number,reason.kind(),line.text),
+        }
+        Ok(())
+    }
+
+    /// The number of lines rejected so far
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn flush(&self) -> Result<(),WDQSErr> {
+        if let Some(writer) = &self.writer {
+            writer.lock().map_err(|e|WDQSErr::String(format!("RejectFile::flush: {e}")))?.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes all records, and prints the number of rejected lines to stderr
+    pub fn finish(&self) -> Result<(),WDQSErr> {
+        self.flush()?;
+        match &self.filename {
+            Some(filename) => eprintln!("{} lines rejected, see {filename}",self.count()),
+            None => eprintln!("{} lines rejected",self.count()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_file() {
+        let filename = std::env::temp_dir().join(format!("wdqsbe_test_reject_{}.jsonl",std::process::id()));
+        let filename = filename.to_str().unwrap();
+        let rejects = RejectFile::create(filename).unwrap();
+        let line = InputLine { number: 3, offset: 120, text: "<a> <b> .".to_string() };
+        rejects.reject(&line, &WDQSErr::ParserError("no object".to_string())).unwrap();
+        rejects.finish().unwrap();
+        assert_eq!(rejects.count(),1);
+        let text = std::fs::read_to_string(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        let record: serde_json::Value = serde_json::from_str(text.trim_end()).unwrap();
+        assert_eq!(record,json!({"line":3,"offset":120,"reason":"ParserError","message":"no object","text":"<a> <b> ."}));
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{app_state::AppState, geo_search, app_state_mysql_stdout::AppStateStdoutMySQL, date_time::DateTime, decimal::Decimal, element::Element, element_type::ElementType, error::WDQSErr, query_part::QueryPart, query_triples::SqlPart, sparql_query::{SparqlQuery, SparqlTerm, GraphPattern}};
+
+const XSD_BOOLEAN: &str = "http://www.w3.org/2001/XMLSchema#boolean";
+const XSD_DOUBLE: &str = "http://www.w3.org/2001/XMLSchema#double";
+
+/// Doubles are stored as typed literals, but double constants in expressions compare with numbers
+fn double_constant(term: &SparqlTerm) -> Option<Decimal> {
+    match term {
+        SparqlTerm::Literal { value, datatype: Some(datatype), language: None } if **datatype==SparqlTerm::Iri(XSD_DOUBLE.into()) => Decimal::from_f64(value.parse::<f64>().ok()?),
+        _ => None,
+    }
+}
+const GEOF_DISTANCE: &str = "http://www.opengis.net/def/function/geosparql/distance";
+/// Errors, like comparing unbound values or values of different types, are SQL NULL.
+/// SQL's three-valued logic then gives NOT, AND and OR the SPARQL error semantics, and a FILTER keeps only TRUE rows.
+const SQL_ERROR: &str = "NULL";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComparisonOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl ComparisonOp {
+    pub fn sql(&self) -> &str {
+        match self {
+            ComparisonOp::Eq => "=",
+            ComparisonOp::Ne => "<>",
+            ComparisonOp::Lt => "<",
+            ComparisonOp::Gt => ">",
+            ComparisonOp::Le => "<=",
+            ComparisonOp::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    Sample,
+    GroupConcat(String), // Separator
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    pub distinct: bool,
+    pub variable: Option<String>, // None for COUNT(*)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Term(SparqlTerm),
+    Aggregate(Aggregate),
+    Or(Box<Expression>,Box<Expression>),
+    And(Box<Expression>,Box<Expression>),
+    Not(Box<Expression>),
+    Comparison(ComparisonOp,Box<Expression>,Box<Expression>),
+    Function(String,Vec<Expression>), // Lowercase function name
+    Call(SparqlTerm,Vec<Expression>), // Function by IRI, like geof:distance
+    Exists(Vec<GraphPattern>),
+}
+
+/// The SQL for an expression, with its SPARQL type
+#[derive(Debug, Clone, PartialEq)]
+enum SqlValue {
+    Bool(String),
+    Numeric(String),
+    String(String), // Simple literal
+    Node { sql: String, kind: String }, // Element, as created by Element::sql_expression
+    Unbound,
+}
+
+impl SqlValue {
+    fn from_kind(sql: String, kind: &str) -> Self {
+        match kind {
+            "Integer" | "Decimal" => SqlValue::Numeric(sql),
+            "Text" => SqlValue::String(sql),
+            _ => SqlValue::Node { sql, kind: kind.to_string() },
+        }
+    }
+
+    fn is_bound(&self) -> String {
+        match self {
+            SqlValue::Bool(sql) | SqlValue::Numeric(sql) | SqlValue::String(sql) | SqlValue::Node { sql, .. } => format!("({sql} IS NOT NULL)"),
+            SqlValue::Unbound => "FALSE".to_string(),
+        }
+    }
+
+    /// `sql` if all values are bound, otherwise an error
+    fn when_bound(values: &[&SqlValue], sql: &str) -> String {
+        let mut conditions = vec![];
+        for value in values {
+            match value {
+                SqlValue::Bool(v) | SqlValue::Numeric(v) | SqlValue::String(v) | SqlValue::Node { sql: v, .. } => conditions.push(format!("{v} IS NOT NULL")),
+                SqlValue::Unbound => return SQL_ERROR.to_string(),
+            }
+        }
+        format!("(CASE WHEN {} THEN {sql} END)",conditions.join(" AND "))
+    }
+
+    /// The lexical form, for string functions
+    fn lexical(&self) -> Option<String> {
+        match self {
+            SqlValue::String(sql) => Some(sql.to_owned()),
+            SqlValue::Node { sql, kind } if kind=="TextInLanguage" => Element::sql_str_expression(kind, sql),
+            _ => None,
+        }
+    }
+
+    /// Effective boolean value; an error for other terms
+    fn ebv(self) -> String {
+        match self {
+            SqlValue::Bool(sql) => sql,
+            SqlValue::Numeric(sql) => format!("({sql}<>0)"),
+            SqlValue::String(sql) => format!("({sql}<>'')"),
+            SqlValue::Unbound | SqlValue::Node { .. } => SQL_ERROR.to_string(),
+        }
+    }
+}
+
+impl Expression {
+    /// Replaces every aggregate with a variable, and adds it to `aggregates` under that variable name
+    pub fn extract_aggregates(&self, aggregates: &mut Vec<(String,Aggregate)>) -> Expression {
+        let mut extract = |e: &Expression| Box::new(e.extract_aggregates(aggregates));
+        match self {
+            Expression::Aggregate(aggregate) => {
+                let name = format!("__aggregate{}",aggregates.len());
+                aggregates.push((name.to_owned(),aggregate.to_owned()));
+                Expression::Term(SparqlTerm::Variable(name))
+            }
+            Expression::Term(_) | Expression::Exists(_) => self.to_owned(),
+            Expression::Or(a,b) => Expression::Or(extract(a),extract(b)),
+            Expression::And(a,b) => Expression::And(extract(a),extract(b)),
+            Expression::Not(a) => Expression::Not(extract(a)),
+            Expression::Comparison(op,a,b) => Expression::Comparison(*op,extract(a),extract(b)),
+            Expression::Function(name,args) => Expression::Function(name.to_owned(),args.iter().map(|a|*extract(a)).collect()),
+            Expression::Call(iri,args) => Expression::Call(iri.to_owned(),args.iter().map(|a|*extract(a)).collect()),
+        }
+    }
+
+    /// Variables used in the expression, outside of EXISTS
+    pub fn variables(&self) -> Vec<String> {
+        match self {
+            Expression::Term(SparqlTerm::Variable(name)) => vec![name.to_owned()],
+            Expression::Term(_) | Expression::Exists(_) => vec![],
+            Expression::Aggregate(aggregate) => aggregate.variable.iter().cloned().collect(),
+            Expression::Or(a,b) | Expression::And(a,b) | Expression::Comparison(_,a,b) => [a.variables(),b.variables()].concat(),
+            Expression::Not(a) => a.variables(),
+            Expression::Function(_,args) | Expression::Call(_,args) => args.iter().flat_map(|a|a.variables()).collect(),
+        }
+    }
+
+    /// Compiles the expression into an SQL condition over the variables of `part`
+    pub fn to_sql(&self, query: &SparqlQuery, app: &AppState, part: &SqlPart) -> Result<String,WDQSErr> {
+        Ok(self.value(query, app, part)?.ebv())
+    }
+
+    fn value(&self, query: &SparqlQuery, app: &AppState, part: &SqlPart) -> Result<SqlValue,WDQSErr> {
+        let value = |e: &Expression| e.value(query, app, part);
+        Ok(match self {
+            Expression::Term(SparqlTerm::Variable(name)) => {
+                match part.variables.iter().find(|v|v.name()==name) {
+                    Some(variable) => match variable.kind() {
+                        Some(kind) => SqlValue::from_kind(format!("t.`{name}`"), kind),
+                        None => SqlValue::Unbound,
+                    },
+                    None => SqlValue::Unbound,
+                }
+            }
+            Expression::Term(SparqlTerm::Literal { value, datatype: Some(datatype), language: None }) if **datatype==SparqlTerm::Iri(XSD_BOOLEAN.into()) => {
+                SqlValue::Bool(if value=="true" || value=="1" { "TRUE" } else { "FALSE" }.to_string())
+            }
+            Expression::Term(SparqlTerm::Literal { value, datatype: None, language: None }) => {
+                SqlValue::String(format!("\"{}\"",AppStateStdoutMySQL::sql_escape(value)))
+            }
+            Expression::Term(term) => {
+                if let Some(d) = double_constant(term) {
+                    return Ok(SqlValue::Numeric(d.to_string()));
+                }
+                let element = match query.query_part(term, app)?.0 {
+                    QueryPart::Element(element) => element,
+                    QueryPart::Unknown => return Ok(SqlValue::Unbound),
+                };
+                let sql = element.sql_encoded().ok_or_else(||WDQSErr::ParserError(format!("Expression: Can not use {term:?}")))?;
+                SqlValue::from_kind(sql, element.name())
+            }
+            Expression::Or(a,b) => {
+                match (value(a)?.ebv(),value(b)?.ebv()) {
+                    (x,y) if x=="FALSE" => SqlValue::Bool(y),
+                    (x,y) if y=="FALSE" => SqlValue::Bool(x),
+                    (x,y) => SqlValue::Bool(format!("({x} OR {y})")),
+                }
+            }
+            Expression::And(a,b) => {
+                match (value(a)?.ebv(),value(b)?.ebv()) {
+                    (x,y) if x=="FALSE" || y=="FALSE" => SqlValue::Bool("FALSE".to_string()),
+                    (x,y) => SqlValue::Bool(format!("({x} AND {y})")),
+                }
+            }
+            Expression::Not(a) => match value(a)?.ebv() {
+                x if x==SQL_ERROR => SqlValue::Bool(x),
+                x => SqlValue::Bool(format!("(NOT {x})")),
+            },
+            Expression::Comparison(op,a,b) => Self::comparison(*op, value(a)?, value(b)?),
+            Expression::Aggregate(_) => return Err(WDQSErr::ParserError("Expression: Aggregates are only allowed in SELECT and HAVING".into())),
+            Expression::Exists(_) => return Err(WDQSErr::ParserError("Expression: (NOT) EXISTS is only supported as the entire FILTER condition".into())),
+            Expression::Function(name,args) => {
+                let args = args.iter().map(value).collect::<Result<Vec<_>,_>>()?;
+                Self::function(name, args)?
+            }
+            Expression::Call(iri,args) => {
+                let iri = query.expand(iri, app)?;
+                let args = args.iter().map(value).collect::<Result<Vec<_>,_>>()?;
+                Self::call(&iri, args)?
+            }
+        })
+    }
+
+    fn comparison(op: ComparisonOp, a: SqlValue, b: SqlValue) -> SqlValue {
+        let sql_op = op.sql();
+        SqlValue::Bool(match (a,b) {
+            (SqlValue::Unbound,_) | (_,SqlValue::Unbound) => SQL_ERROR.to_string(),
+            (SqlValue::Numeric(a),SqlValue::Numeric(b)) => format!("({a}{sql_op}{b})"),
+            (SqlValue::Bool(a),SqlValue::Bool(b)) => format!("({a}{sql_op}{b})"),
+            (SqlValue::String(a),SqlValue::String(b)) => format!("(CAST({a} AS BINARY){sql_op}CAST({b} AS BINARY))"),
+            (SqlValue::Node { sql: a, kind: kind_a },SqlValue::Node { sql: b, kind: kind_b }) if kind_a==kind_b => {
+                match op {
+                    ComparisonOp::Eq | ComparisonOp::Ne if matches!(kind_a.as_str(),"TextInLanguage"|"TypedLiteral"|"WikiPage"|"Url") => format!("(CAST({a} AS BINARY){sql_op}CAST({b} AS BINARY))"),
+                    _ if kind_a=="DateTime" => format!("({}{sql_op}{})",DateTime::sql_order_expression(&a),DateTime::sql_order_expression(&b)),
+                    ComparisonOp::Eq | ComparisonOp::Ne => format!("({a}{sql_op}{b})"),
+                    _ => SQL_ERROR.to_string(), // TODO ordering of typed literals
+                }
+            }
+            (a,b) => {
+                // An IRI or blank node is never equal to a value of another type; anything else is a type error
+                let is_node = |v: &SqlValue| matches!(v,SqlValue::Node { kind, .. } if !Element::is_literal_name(kind));
+                match op {
+                    ComparisonOp::Eq if is_node(&a) || is_node(&b) => SqlValue::when_bound(&[&a,&b], "FALSE"),
+                    ComparisonOp::Ne if is_node(&a) || is_node(&b) => SqlValue::when_bound(&[&a,&b], "TRUE"),
+                    _ => SQL_ERROR.to_string(),
+                }
+            }
+        })
+    }
+
+    fn call(iri: &str, args: Vec<SqlValue>) -> Result<SqlValue,WDQSErr> {
+        match (iri,args.as_slice()) {
+            (GEOF_DISTANCE,[a,b]) => match (a,b) {
+                (SqlValue::Node { sql: a, kind: kind_a },SqlValue::Node { sql: b, kind: kind_b }) if kind_a=="LatLon" && kind_b=="LatLon" => {
+                    Ok(SqlValue::Numeric(geo_search::sql_distance_between(a, b)))
+                }
+                _ => Ok(SqlValue::Unbound),
+            },
+            (GEOF_DISTANCE,_) => Err(WDQSErr::ParserError("Expression: geof:distance() takes 2 arguments".into())),
+            _ => Err(WDQSErr::ParserError(format!("Expression: Unknown function <{iri}>"))),
+        }
+    }
+
+    fn function(name: &str, mut args: Vec<SqlValue>) -> Result<SqlValue,WDQSErr> {
+        let expected_args = match name {
+            "contains" | "strstarts" => 2,
+            _ => 1,
+        };
+        if args.len()!=expected_args {
+            return Err(WDQSErr::ParserError(format!("Expression: {name}() takes {expected_args} argument(s)")));
+        }
+        let a = args.remove(0);
+        Ok(match name {
+            "bound" => SqlValue::Bool(a.is_bound()),
+            "isiri" | "isuri" => match &a {
+                SqlValue::Node { kind, .. } if !Element::is_literal_name(kind) && kind!="BlankNode" => SqlValue::Bool(SqlValue::when_bound(&[&a], "TRUE")),
+                _ => SqlValue::Bool(SqlValue::when_bound(&[&a], "FALSE")),
+            },
+            "str" => match a {
+                SqlValue::String(sql) => SqlValue::String(sql),
+                SqlValue::Numeric(sql) => SqlValue::String(Element::sql_str_expression("Decimal", &sql).unwrap_or_default()), // Also right for integers
+                SqlValue::Node { sql, kind } => match Element::sql_str_expression(&kind, &sql) {
+                    Some(sql) => SqlValue::String(sql),
+                    None => SqlValue::Unbound,
+                },
+                _ => SqlValue::Unbound,
+            },
+            "lang" => match a {
+                SqlValue::Node { sql, kind } if kind=="TextInLanguage" => SqlValue::String(format!("SUBSTRING_INDEX({sql},'@',-1)")),
+                SqlValue::Node { kind, .. } if !Element::is_literal_name(&kind) => SqlValue::Unbound,
+                SqlValue::Unbound => SqlValue::Unbound,
+                _ => SqlValue::String("''".to_string()),
+            },
+            "contains" | "strstarts" => {
+                let b = args.remove(0);
+                let (a,b) = match (a.lexical(),b.lexical()) {
+                    (Some(a),Some(b)) => (a,b),
+                    _ => return Ok(SqlValue::Bool(SQL_ERROR.to_string())),
+                };
+                let position = format!("LOCATE(CAST({b} AS BINARY),CAST({a} AS BINARY))");
+                match name {
+                    "contains" => SqlValue::Bool(format!("({position}>0)")),
+                    _ => SqlValue::Bool(format!("({position}=1)")),
+                }
+            }
+            _ => return Err(WDQSErr::ParserError(format!("Expression: Unknown function {name}()"))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_state::tests::app_with_tables, query_triples::SqlVariable};
+
+    /// The SQL for the FILTER condition of `query`, with ?x an integer, ?s an item, and ?u unbound
+    fn filter_sql(app: &AppState, query: &str) -> String {
+        let query = SparqlQuery::parse(query).unwrap();
+        let expression = query.patterns.iter().find_map(|pattern|match pattern {
+            GraphPattern::Filter(expression) => Some(expression),
+            _ => None,
+        }).unwrap();
+        let part = SqlPart {
+            variables: vec![SqlVariable::new("x",Some("Integer")), SqlVariable::new("s",Some("EntityItem")), SqlVariable::new("u",None)],
+            ..Default::default()
+        };
+        expression.to_sql(&query, app, &part).unwrap()
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        let app = app_with_tables(&[]);
+        let sql = |filter: &str| filter_sql(&app, &format!("SELECT * WHERE {{ ?s ?p ?x FILTER({filter}) }}"));
+        assert_eq!(sql("!(?x = 1)"),"(NOT (t.`x`=1))");
+        assert_eq!(sql("?x > 1.5e3"),"(t.`x`>1500)");
+        // Unbound and type errors stay errors under NOT, so the row is dropped
+        assert_eq!(sql("!(?u = 1)"),"NULL");
+        assert_eq!(sql("!(?x = \"a\")"),"NULL");
+        assert_eq!(sql("?x < ?s"),"NULL");
+        // An IRI is never equal to a literal, but only when both are bound
+        assert_eq!(sql("!(?s = 1)"),"(NOT (CASE WHEN t.`s` IS NOT NULL AND 1 IS NOT NULL THEN FALSE END))");
+        assert_eq!(sql("?s != ?u"),"NULL");
+        // Errors propagate through || and && by three-valued logic
+        assert_eq!(sql("?u = 1 || ?x > 2"),"(NULL OR (t.`x`>2))");
+        assert_eq!(sql("?u = 1 && ?x > 2"),"(NULL AND (t.`x`>2))");
+        assert_eq!(sql("!(?u = 1 || ?x > 2)"),"(NOT (NULL OR (t.`x`>2)))");
+        assert_eq!(sql("isIRI(?u)"),"NULL");
+        assert_eq!(sql("!isIRI(?x)"),"(NOT (CASE WHEN t.`x` IS NOT NULL THEN FALSE END))");
+    }
+}
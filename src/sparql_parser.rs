@@ -0,0 +1,763 @@
+use std::collections::HashMap;
+use nom::{IResult, bytes::complete::{tag, tag_no_case, take_while, take_while1, take_until}, branch::alt, character::complete::{char, multispace1, not_line_ending}, combinator::{opt, map, recognize, not, peek}, multi::{many0, many1, separated_list0}, sequence::{preceded, delimited, terminated, tuple}, error::{VerboseError, ErrorKind, ParseError}};
+use crate::{error::WDQSErr, sparql_query::{SparqlQuery, SparqlTerm, TriplePattern, GraphPattern, PathPattern, PropertyPath, Projection, SolutionModifiers, OrderCondition}, sparql_expression::{Expression, ComparisonOp, Aggregate, AggregateFunction}};
+
+type Res<T, U> = IResult<T, U, VerboseError<T>>;
+type SelectExpressions = Vec<(String,Expression)>;
+
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const XSD_INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+const XSD_DECIMAL: &str = "http://www.w3.org/2001/XMLSchema#decimal";
+const XSD_DOUBLE: &str = "http://www.w3.org/2001/XMLSchema#double";
+const XSD_BOOLEAN: &str = "http://www.w3.org/2001/XMLSchema#boolean";
+
+pub struct SparqlParser;
+
+impl SparqlParser {
+    pub fn parse(query: &str) -> Result<SparqlQuery,WDQSErr> {
+        match Self::query(query) {
+            Ok((rest,ret)) => {
+                match ws(rest) {
+                    Ok(("",_)) => Ok(ret),
+                    _ => Err(WDQSErr::ParserError(format!("SparqlParser: Unexpected input at '{}'",Self::excerpt(rest)))),
+                }
+            }
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                let at = e.errors.first().map(|(input,_)|Self::excerpt(input)).unwrap_or_default();
+                Err(WDQSErr::ParserError(format!("SparqlParser: Syntax error at '{at}'")))
+            }
+            Err(e) => Err(WDQSErr::ParserError(e.to_string())),
+        }
+    }
+
+    fn excerpt(input: &str) -> String {
+        input.trim_start().chars().take(40).collect()
+    }
+
+    fn query(input: &str) -> Res<&str, SparqlQuery> {
+        let (input,prefixes) = prologue(input)?;
+        let (input,_) = keyword("SELECT")(input)?;
+        let (input,distinct) = opt(alt((keyword("DISTINCT"),keyword("REDUCED"))))(input)?;
+        let (input,(projection,select_expressions)) = projection(input)?;
+        let (input,_) = opt(keyword("WHERE"))(input)?;
+        let (input,patterns) = group_graph_pattern(input)?;
+        let (input,group_by) = opt(group_clause)(input)?;
+        let (input,having) = opt(having_clause)(input)?;
+        let (input,order_by) = opt(order_clause)(input)?;
+        let (input,(limit,offset)) = limit_offset_clauses(input)?;
+        let modifiers = SolutionModifiers {
+            distinct: distinct.is_some(),
+            order_by: order_by.unwrap_or_default(),
+            limit,
+            offset,
+        };
+        let group_by = group_by.unwrap_or_default();
+        let having = having.unwrap_or_default();
+        Ok((input,SparqlQuery { prefixes, projection, select_expressions, patterns, group_by, having, modifiers }))
+    }
+}
+
+fn error<T>(input: &str) -> Res<&str, T> {
+    Err(nom::Err::Error(VerboseError::from_error_kind(input, ErrorKind::Tag)))
+}
+
+/// Skips whitespace and comments
+fn ws(input: &str) -> Res<&str, ()> {
+    let comment = recognize(preceded(char('#'), not_line_ending));
+    let (input,_) = many0(alt((multispace1,comment)))(input)?;
+    Ok((input,()))
+}
+
+fn keyword<'a>(kw: &'static str) -> impl FnMut(&'a str) -> Res<&'a str, &'a str> {
+    move |input| {
+        let (input,_) = ws(input)?;
+        let (input,ret) = tag_no_case(kw)(input)?;
+        let (input,_) = not(peek(take_while1(|c:char|c.is_alphanumeric() || c=='_')))(input)?;
+        Ok((input,ret))
+    }
+}
+
+fn symbol<'a>(c: char) -> impl FnMut(&'a str) -> Res<&'a str, char> {
+    move |input| preceded(ws, char(c))(input)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c=='_' || c=='-'
+}
+
+fn prologue(input: &str) -> Res<&str, HashMap<String,String>> {
+    let (input,declarations) = many0(alt((prefix_declaration,base_declaration)))(input)?;
+    Ok((input,declarations.into_iter().flatten().collect()))
+}
+
+fn prefix_declaration(input: &str) -> Res<&str, Option<(String,String)>> {
+    let (input,_) = keyword("PREFIX")(input)?;
+    let (input,_) = ws(input)?;
+    let (input,prefix) = take_while(is_name_char)(input)?;
+    let (input,_) = char(':')(input)?;
+    let (input,_) = ws(input)?;
+    let (input,iri) = iri_ref(input)?;
+    Ok((input,Some((prefix.to_string(),iri))))
+}
+
+fn base_declaration(input: &str) -> Res<&str, Option<(String,String)>> {
+    let (input,_) = keyword("BASE")(input)?;
+    let (input,_) = ws(input)?;
+    let (input,_) = iri_ref(input)?;
+    Ok((input,None)) // BASE is accepted, but relative IRIs are not resolved
+}
+
+/// `(expression AS ?variable)` in SELECT
+fn select_expression(input: &str) -> Res<&str, (String,Expression)> {
+    let (input,_) = symbol('(')(input)?;
+    let (input,expression) = expression(input)?;
+    let (input,_) = keyword("AS")(input)?;
+    let (input,variable) = variable(input)?;
+    let (input,_) = symbol(')')(input)?;
+    Ok((input,(variable,expression)))
+}
+
+fn projection(input: &str) -> Res<&str, (Projection,SelectExpressions)> {
+    if let Ok((input,_)) = symbol('*')(input) {
+        return Ok((input,(Projection::All,vec![])));
+    }
+    let (input,items) = many1(alt((
+        map(variable, |v|(v,None)),
+        map(select_expression, |(v,e)|(v,Some(e))),
+    )))(input)?;
+    let variables = items.iter().map(|(v,_)|v.to_owned()).collect();
+    let expressions = items.into_iter().filter_map(|(v,e)|Some((v,e?))).collect();
+    Ok((input,(Projection::Variables(variables),expressions)))
+}
+
+fn group_clause(input: &str) -> Res<&str, Vec<String>> {
+    let (input,_) = keyword("GROUP")(input)?;
+    let (input,_) = keyword("BY")(input)?;
+    many1(variable)(input)
+}
+
+fn having_clause(input: &str) -> Res<&str, Vec<Expression>> {
+    let (input,_) = keyword("HAVING")(input)?;
+    many1(alt((bracketted_expression,builtin_call)))(input)
+}
+
+fn order_condition(input: &str) -> Res<&str, OrderCondition> {
+    if let Ok((input,direction)) = alt((keyword("ASC"),keyword("DESC")))(input) {
+        let (input,variable) = delimited(symbol('('), variable, symbol(')'))(input)?;
+        let descending = direction.eq_ignore_ascii_case("DESC");
+        return Ok((input,OrderCondition { variable, descending }));
+    }
+    let (input,variable) = variable(input)?;
+    Ok((input,OrderCondition { variable, descending: false }))
+}
+
+fn order_clause(input: &str) -> Res<&str, Vec<OrderCondition>> {
+    let (input,_) = keyword("ORDER")(input)?;
+    let (input,_) = keyword("BY")(input)?;
+    many1(order_condition)(input)
+}
+
+fn integer(input: &str) -> Res<&str, usize> {
+    let (input,_) = ws(input)?;
+    let (rest,digits) = take_while1(|c:char|c.is_ascii_digit())(input)?;
+    match digits.parse::<usize>() {
+        Ok(i) => Ok((rest,i)),
+        Err(_) => error(input),
+    }
+}
+
+/// LIMIT and OFFSET, in any order
+fn limit_offset_clauses(input: &str) -> Res<&str, (Option<usize>,Option<usize>)> {
+    let limit = |input| preceded(keyword("LIMIT"), integer)(input);
+    let offset = |input| preceded(keyword("OFFSET"), integer)(input);
+    if let Ok((input,l)) = limit(input) {
+        let (input,o) = opt(offset)(input)?;
+        return Ok((input,(Some(l),o)));
+    }
+    let (input,o) = opt(offset)(input)?;
+    let (input,l) = opt(limit)(input)?;
+    Ok((input,(l,o)))
+}
+
+fn variable(input: &str) -> Res<&str, String> {
+    let (input,_) = ws(input)?;
+    let (input,_) = alt((char('?'),char('$')))(input)?;
+    let (input,name) = take_while1(|c:char|c.is_alphanumeric() || c=='_')(input)?;
+    Ok((input,name.to_string()))
+}
+
+fn iri_ref(input: &str) -> Res<&str, String> {
+    let (input,iri) = delimited(char('<'), take_until(">"), char('>'))(input)?;
+    if iri.contains(|c:char|c.is_whitespace() || "<\"{}|^`".contains(c)) {
+        return error(input);
+    }
+    Ok((input,iri.to_string()))
+}
+
+/// Prefixed name like wd:Q42; a trailing '.' belongs to the triple, not the name
+fn prefixed_name(input: &str) -> Res<&str, (String,String)> {
+    let (input,prefix) = take_while(is_name_char)(input)?;
+    let (after_colon,_) = char(':')(input)?;
+    let (_,local) = take_while(|c:char|is_name_char(c) || c=='.' || c==':' || c=='%')(after_colon)?;
+    let local = local.trim_end_matches('.');
+    Ok((&after_colon[local.len()..],(prefix.to_string(),local.to_string())))
+}
+
+fn iri(input: &str) -> Res<&str, SparqlTerm> {
+    let (input,_) = ws(input)?;
+    alt((
+        map(iri_ref, SparqlTerm::Iri),
+        map(prefixed_name, SparqlTerm::PrefixedName),
+    ))(input)
+}
+
+fn string_literal(input: &str) -> Res<&str, String> {
+    let quote = ["\"\"\"","'''","\"","'"].into_iter().find(|quote|input.starts_with(quote));
+    let quote = match quote {
+        Some(quote) => quote,
+        None => return error(input),
+    };
+    let body = &input[quote.len()..];
+    let mut ret = String::new();
+    let mut chars = body.char_indices();
+    while let Some((pos,c)) = chars.next() {
+        if body[pos..].starts_with(quote) {
+            return Ok((&body[pos+quote.len()..],ret));
+        }
+        match c {
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some((_,'t')) => '\t',
+                    Some((_,'n')) => '\n',
+                    Some((_,'r')) => '\r',
+                    Some((_,'b')) => '\u{8}',
+                    Some((_,'f')) => '\u{c}',
+                    Some((_,'u')) => unicode_escape(&mut chars, 4).ok_or_else(||nom::Err::Failure(VerboseError::from_error_kind(&body[pos..], ErrorKind::Escaped)))?,
+                    Some((_,'U')) => unicode_escape(&mut chars, 8).ok_or_else(||nom::Err::Failure(VerboseError::from_error_kind(&body[pos..], ErrorKind::Escaped)))?,
+                    Some((_,c)) if "\"'\\".contains(c) => c,
+                    _ => return Err(nom::Err::Failure(VerboseError::from_error_kind(&body[pos..], ErrorKind::Escaped))),
+                };
+                ret.push(escaped);
+            }
+            '\n' | '\r' if quote.len()==1 => return Err(nom::Err::Failure(VerboseError::from_error_kind(&body[pos..], ErrorKind::Char))),
+            c => ret.push(c),
+        }
+    }
+    Err(nom::Err::Failure(VerboseError::from_error_kind(input, ErrorKind::Eof)))
+}
+
+fn unicode_escape(chars: &mut std::str::CharIndices, digits: usize) -> Option<char> {
+    let hex: String = chars.take(digits).map(|(_,c)|c).collect();
+    if hex.len()!=digits {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)
+}
+
+fn rdf_literal(input: &str) -> Res<&str, SparqlTerm> {
+    let (input,value) = string_literal(input)?;
+    let language: Res<&str, &str> = preceded(char('@'), take_while1(|c:char|c.is_alphanumeric() || c=='-'))(input);
+    if let Ok((input,language)) = language {
+        return Ok((input,SparqlTerm::Literal { value, datatype: None, language: Some(language.to_string()) }));
+    }
+    if let Ok((input,datatype)) = preceded(tag("^^"), iri)(input) {
+        return Ok((input,SparqlTerm::Literal { value, datatype: Some(Box::new(datatype)), language: None }));
+    }
+    Ok((input,SparqlTerm::Literal { value, datatype: None, language: None }))
+}
+
+fn numeric_literal(input: &str) -> Res<&str, SparqlTerm> {
+    let digits = |input| take_while1(|c:char|c.is_ascii_digit())(input);
+    let sign = |input| opt(alt((char('+'),char('-'))))(input);
+    let exponent = |input| recognize(tuple((alt((char('e'),char('E'))),sign,digits)))(input);
+    let (rest,mantissa) = recognize(tuple((sign,alt((
+        recognize(tuple((digits,opt(tuple((char('.'),opt(digits))))))),
+        recognize(tuple((char('.'),digits))),
+    )))))(input)?;
+    let (rest,exponent) = opt(exponent)(rest)?;
+    let mantissa = mantissa.trim_end_matches('.'); // "1." ends a triple
+    let rest = if exponent.is_none() { &input[mantissa.len()..] } else { rest };
+    let datatype = if exponent.is_some() {
+        XSD_DOUBLE
+    } else if mantissa.contains('.') {
+        XSD_DECIMAL
+    } else {
+        XSD_INTEGER
+    };
+    let value = format!("{mantissa}{}",exponent.unwrap_or_default());
+    Ok((rest,SparqlTerm::Literal { value, datatype: Some(Box::new(SparqlTerm::Iri(datatype.to_string()))), language: None }))
+}
+
+fn boolean_literal(input: &str) -> Res<&str, SparqlTerm> {
+    let (input,value) = alt((keyword("true"),keyword("false")))(input)?;
+    Ok((input,SparqlTerm::Literal { value: value.to_lowercase(), datatype: Some(Box::new(SparqlTerm::Iri(XSD_BOOLEAN.to_string()))), language: None }))
+}
+
+fn term(input: &str) -> Res<&str, SparqlTerm> {
+    let (input,_) = ws(input)?;
+    alt((
+        map(variable, SparqlTerm::Variable),
+        boolean_literal,
+        iri,
+        rdf_literal,
+        numeric_literal,
+    ))(input)
+}
+
+fn verb(input: &str) -> Res<&str, PropertyPath> {
+    let (input,_) = ws(input)?;
+    alt((map(variable, |v|PropertyPath::Predicate(SparqlTerm::Variable(v))),path_alternative))(input)
+}
+
+fn path_alternative(input: &str) -> Res<&str, PropertyPath> {
+    let (input,first) = path_sequence(input)?;
+    let (input,mut rest) = many0(preceded(symbol('|'), path_sequence))(input)?;
+    if rest.is_empty() {
+        return Ok((input,first));
+    }
+    rest.insert(0,first);
+    Ok((input,PropertyPath::Alternative(rest)))
+}
+
+fn path_sequence(input: &str) -> Res<&str, PropertyPath> {
+    let (input,first) = path_elt_or_inverse(input)?;
+    let (input,mut rest) = many0(preceded(symbol('/'), path_elt_or_inverse))(input)?;
+    if rest.is_empty() {
+        return Ok((input,first));
+    }
+    rest.insert(0,first);
+    Ok((input,PropertyPath::Sequence(rest)))
+}
+
+fn path_elt_or_inverse(input: &str) -> Res<&str, PropertyPath> {
+    let (input,inverse) = opt(symbol('^'))(input)?;
+    let (input,path) = path_elt(input)?;
+    match inverse {
+        Some(_) => Ok((input,PropertyPath::Inverse(Box::new(path)))),
+        None => Ok((input,path)),
+    }
+}
+
+/// '?' directly followed by a name is a variable, not a modifier
+fn path_mod(input: &str) -> Res<&str, char> {
+    alt((char('*'),char('+'),terminated(char('?'),not(peek(take_while1(is_name_char))))))(input)
+}
+
+fn path_elt(input: &str) -> Res<&str, PropertyPath> {
+    let (input,path) = path_primary(input)?;
+    let (input,modifier) = opt(path_mod)(input)?;
+    let path = Box::new(path);
+    Ok((input,match modifier {
+        Some('*') => PropertyPath::ZeroOrMore(path),
+        Some('+') => PropertyPath::OneOrMore(path),
+        Some(_) => PropertyPath::ZeroOrOne(path),
+        None => *path,
+    }))
+}
+
+fn path_primary(input: &str) -> Res<&str, PropertyPath> {
+    let (input,_) = ws(input)?;
+    if let Ok((input,_)) = terminated_keyword_a(input) {
+        return Ok((input,PropertyPath::Predicate(SparqlTerm::Iri(RDF_TYPE.to_string()))));
+    }
+    alt((
+        map(iri, PropertyPath::Predicate),
+        delimited(symbol('('), path_alternative, symbol(')')),
+    ))(input)
+}
+
+fn terminated_keyword_a(input: &str) -> Res<&str, &str> {
+    let (input,ret) = tag("a")(input)?;
+    let (input,_) = not(peek(take_while1(|c:char|is_name_char(c) || c==':')))(input)?;
+    Ok((input,ret))
+}
+
+fn object_list(input: &str) -> Res<&str, Vec<SparqlTerm>> {
+    let (input,first) = term(input)?;
+    let (input,mut rest) = many0(preceded(symbol(','), term))(input)?;
+    rest.insert(0,first);
+    Ok((input,rest))
+}
+
+fn property_list(input: &str) -> Res<&str, Vec<(PropertyPath,Vec<SparqlTerm>)>> {
+    let (input,first) = tuple((verb,object_list))(input)?;
+    let (input,rest) = many0(preceded(many1(symbol(';')), tuple((verb,object_list))))(input)?;
+    let (input,_) = many0(symbol(';'))(input)?;
+    let mut ret = vec![first];
+    ret.extend(rest);
+    Ok((input,ret))
+}
+
+/// Triples, or property paths where the verb is more than a single predicate
+fn triples_same_subject(input: &str) -> Res<&str, Vec<GraphPattern>> {
+    let (input,s) = term(input)?;
+    let (input,properties) = property_list(input)?;
+    let ret = properties
+        .into_iter()
+        .flat_map(|(path,objects)|{
+            let s = s.clone();
+            objects.into_iter().map(move |o|match &path {
+                PropertyPath::Predicate(p) => GraphPattern::Triple(TriplePattern { s: s.clone(), p: p.clone(), o }),
+                path => GraphPattern::Path(PathPattern { s: s.clone(), path: path.clone(), o }),
+            })
+        })
+        .collect();
+    Ok((input,ret))
+}
+
+fn optional_graph_pattern(input: &str) -> Res<&str, GraphPattern> {
+    let (input,_) = keyword("OPTIONAL")(input)?;
+    let (input,patterns) = group_graph_pattern(input)?;
+    Ok((input,GraphPattern::Optional(patterns)))
+}
+
+fn service_graph_pattern(input: &str) -> Res<&str, GraphPattern> {
+    let (input,_) = keyword("SERVICE")(input)?;
+    let (input,_) = opt(keyword("SILENT"))(input)?;
+    let (input,service) = iri(input)?;
+    let (input,patterns) = group_graph_pattern(input)?;
+    Ok((input,GraphPattern::Service(service,patterns)))
+}
+
+fn graph_graph_pattern(input: &str) -> Res<&str, GraphPattern> {
+    let (input,_) = keyword("GRAPH")(input)?;
+    let (input,_) = ws(input)?;
+    let (input,graph) = alt((map(variable, SparqlTerm::Variable),iri))(input)?;
+    let (input,patterns) = group_graph_pattern(input)?;
+    Ok((input,GraphPattern::Graph(graph,patterns)))
+}
+
+fn minus_graph_pattern(input: &str) -> Res<&str, GraphPattern> {
+    let (input,_) = keyword("MINUS")(input)?;
+    let (input,patterns) = group_graph_pattern(input)?;
+    Ok((input,GraphPattern::Minus(patterns)))
+}
+
+/// `{ .. } UNION { .. }`, or a single nested group
+fn group_or_union_graph_pattern(input: &str) -> Res<&str, GraphPattern> {
+    let (input,first) = group_graph_pattern(input)?;
+    let (input,mut rest) = many0(preceded(keyword("UNION"), group_graph_pattern))(input)?;
+    rest.insert(0,first);
+    Ok((input,GraphPattern::Union(rest)))
+}
+
+fn filter(input: &str) -> Res<&str, GraphPattern> {
+    let (input,_) = keyword("FILTER")(input)?;
+    let (input,expression) = alt((bracketted_expression,exists,builtin_call,iri_call))(input)?;
+    Ok((input,GraphPattern::Filter(expression)))
+}
+
+fn graph_pattern_not_triples(input: &str) -> Res<&str, GraphPattern> {
+    alt((optional_graph_pattern,minus_graph_pattern,graph_graph_pattern,service_graph_pattern,group_or_union_graph_pattern,filter))(input)
+}
+
+fn expression(input: &str) -> Res<&str, Expression> {
+    let (input,first) = conditional_and_expression(input)?;
+    let (input,rest) = many0(preceded(preceded(ws,tag("||")), conditional_and_expression))(input)?;
+    let ret = rest.into_iter().fold(first, |a,b|Expression::Or(Box::new(a),Box::new(b)));
+    Ok((input,ret))
+}
+
+fn conditional_and_expression(input: &str) -> Res<&str, Expression> {
+    let (input,first) = relational_expression(input)?;
+    let (input,rest) = many0(preceded(preceded(ws,tag("&&")), relational_expression))(input)?;
+    let ret = rest.into_iter().fold(first, |a,b|Expression::And(Box::new(a),Box::new(b)));
+    Ok((input,ret))
+}
+
+fn comparison_op(input: &str) -> Res<&str, ComparisonOp> {
+    let (input,_) = ws(input)?;
+    alt((
+        map(tag("!="), |_|ComparisonOp::Ne),
+        map(tag("<="), |_|ComparisonOp::Le),
+        map(tag(">="), |_|ComparisonOp::Ge),
+        map(tag("="), |_|ComparisonOp::Eq),
+        map(tag("<"), |_|ComparisonOp::Lt),
+        map(tag(">"), |_|ComparisonOp::Gt),
+    ))(input)
+}
+
+fn relational_expression(input: &str) -> Res<&str, Expression> {
+    let (input,a) = unary_expression(input)?;
+    match tuple((comparison_op,unary_expression))(input) {
+        Ok((input,(op,b))) => Ok((input,Expression::Comparison(op,Box::new(a),Box::new(b)))),
+        Err(_) => Ok((input,a)),
+    }
+}
+
+fn unary_expression(input: &str) -> Res<&str, Expression> {
+    if let Ok((input,_)) = symbol('!')(input) {
+        let (input,expression) = unary_expression(input)?;
+        return Ok((input,Expression::Not(Box::new(expression))));
+    }
+    primary_expression(input)
+}
+
+fn aggregate(input: &str) -> Res<&str, Expression> {
+    let (input,_) = ws(input)?;
+    let (input,name) = alt((
+        keyword("COUNT"),
+        keyword("SUM"),
+        keyword("MIN"),
+        keyword("MAX"),
+        keyword("AVG"),
+        keyword("SAMPLE"),
+        keyword("GROUP_CONCAT"),
+    ))(input)?;
+    let (input,_) = symbol('(')(input)?;
+    let (input,distinct) = opt(keyword("DISTINCT"))(input)?;
+    let name = name.to_uppercase();
+    let (input,variable) = if name=="COUNT" {
+        alt((map(symbol('*'),|_|None),map(variable,Some)))(input)?
+    } else {
+        map(variable,Some)(input)?
+    };
+    let (input,separator) = if name=="GROUP_CONCAT" {
+        let separator = preceded(tuple((symbol(';'),keyword("SEPARATOR"),symbol('='),ws)), string_literal);
+        opt(separator)(input)?
+    } else {
+        (input,None)
+    };
+    let (input,_) = symbol(')')(input)?;
+    let function = match name.as_str() {
+        "COUNT" => AggregateFunction::Count,
+        "SUM" => AggregateFunction::Sum,
+        "MIN" => AggregateFunction::Min,
+        "MAX" => AggregateFunction::Max,
+        "AVG" => AggregateFunction::Avg,
+        "SAMPLE" => AggregateFunction::Sample,
+        _ => AggregateFunction::GroupConcat(separator.unwrap_or_else(||" ".to_string())),
+    };
+    Ok((input,Expression::Aggregate(Aggregate { function, distinct: distinct.is_some(), variable })))
+}
+
+fn primary_expression(input: &str) -> Res<&str, Expression> {
+    alt((
+        bracketted_expression,
+        aggregate,
+        exists,
+        builtin_call,
+        iri_call,
+        map(term, Expression::Term),
+    ))(input)
+}
+
+fn bracketted_expression(input: &str) -> Res<&str, Expression> {
+    delimited(symbol('('), expression, symbol(')'))(input)
+}
+
+fn iri_call(input: &str) -> Res<&str, Expression> {
+    let (input,function) = iri(input)?;
+    let (input,_) = symbol('(')(input)?;
+    let (input,args) = separated_list0(symbol(','), expression)(input)?;
+    let (input,_) = symbol(')')(input)?;
+    Ok((input,Expression::Call(function,args)))
+}
+
+fn exists(input: &str) -> Res<&str, Expression> {
+    let (input,not) = opt(keyword("NOT"))(input)?;
+    let (input,_) = keyword("EXISTS")(input)?;
+    let (input,patterns) = group_graph_pattern(input)?;
+    let ret = Expression::Exists(patterns);
+    match not {
+        Some(_) => Ok((input,Expression::Not(Box::new(ret)))),
+        None => Ok((input,ret)),
+    }
+}
+
+fn builtin_call(input: &str) -> Res<&str, Expression> {
+    let (input,_) = ws(input)?;
+    let (input,name) = take_while1(|c:char|c.is_ascii_alphabetic())(input)?;
+    let name = name.to_lowercase();
+    if !["lang","str","contains","strstarts","isiri","isuri","bound"].contains(&name.as_str()) {
+        return error(input);
+    }
+    let (input,_) = symbol('(')(input)?;
+    let (input,args) = separated_list0(symbol(','), expression)(input)?;
+    let (input,_) = symbol(')')(input)?;
+    Ok((input,Expression::Function(name,args)))
+}
+
+fn group_graph_pattern(input: &str) -> Res<&str, Vec<GraphPattern>> {
+    let (mut input,_) = symbol('{')(input)?;
+    let mut ret = vec![];
+    let mut needs_dot = false; // Two triple blocks must be separated by '.'
+    loop {
+        if let Ok((rest,_)) = symbol('}')(input) {
+            return Ok((rest,ret));
+        }
+        if let Ok((rest,pattern)) = graph_pattern_not_triples(input) {
+            ret.push(pattern);
+            (input,_) = opt(symbol('.'))(rest)?;
+            needs_dot = false;
+            continue;
+        }
+        if needs_dot {
+            return error(input);
+        }
+        let (rest,triples) = triples_same_subject(input)?;
+        ret.extend(triples);
+        input = match symbol('.')(rest) {
+            Ok((rest,_)) => {
+                needs_dot = false;
+                rest
+            }
+            Err(_) => {
+                needs_dot = true;
+                rest
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triple(query: &SparqlQuery, num: usize) -> &TriplePattern {
+        match &query.patterns[num] {
+            GraphPattern::Triple(triple) => triple,
+            other => panic!("Not a triple: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let query = r#"PREFIX wd: <http://www.wikidata.org/entity/>
+            # Female humans
+            SELECT ?person ?name WHERE {
+                ?person wdt:P31 wd:Q5 ; wdt:P21 wd:Q6581072 .
+                ?person rdfs:label ?name, "Douglas Adams"@en .
+            }"#;
+        let query = SparqlParser::parse(query).unwrap();
+        assert_eq!(query.prefixes.get("wd").unwrap(),"http://www.wikidata.org/entity/");
+        assert_eq!(query.projection,Projection::Variables(vec!["person".into(),"name".into()]));
+        assert_eq!(query.patterns.len(),4);
+        assert_eq!(triple(&query,1).p,SparqlTerm::PrefixedName(("wdt".into(),"P21".into())));
+        assert_eq!(triple(&query,1).o,SparqlTerm::PrefixedName(("wd".into(),"Q6581072".into())));
+        assert_eq!(triple(&query,3).o,SparqlTerm::Literal { value: "Douglas Adams".into(), datatype: None, language: Some("en".into()) });
+    }
+
+    #[test]
+    fn test_parse_terms() {
+        let query = SparqlParser::parse(r#"select * { ?x a <http://example.org/C> ; wdt:P1082 1.5e3 ; wdt:P1 "a \"b\" é"^^xsd:string ; wdt:P2 -42. }"#).unwrap();
+        assert_eq!(query.projection,Projection::All);
+        assert_eq!(triple(&query,0).p,SparqlTerm::Iri(RDF_TYPE.into()));
+        assert_eq!(triple(&query,1).o,SparqlTerm::Literal { value: "1.5e3".into(), datatype: Some(Box::new(SparqlTerm::Iri(XSD_DOUBLE.into()))), language: None });
+        assert_eq!(triple(&query,2).o,SparqlTerm::Literal { value: "a \"b\" é".into(), datatype: Some(Box::new(SparqlTerm::PrefixedName(("xsd".into(),"string".into())))), language: None });
+        assert_eq!(triple(&query,3).o,SparqlTerm::Literal { value: "-42".into(), datatype: Some(Box::new(SparqlTerm::Iri(XSD_INTEGER.into()))), language: None });
+        assert_eq!(query.variables(),vec!["x".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_optional() {
+        let query = SparqlParser::parse("SELECT * { ?p wdt:P31 wd:Q5 OPTIONAL { ?p wdt:P569 ?dob } . OPTIONAL { ?p wdt:P18 ?image . } ?p wdt:P21 ?g }").unwrap();
+        assert_eq!(query.patterns.len(),4);
+        assert_eq!(query.patterns[1],GraphPattern::Optional(vec![GraphPattern::Triple(TriplePattern {
+            s: SparqlTerm::Variable("p".into()),
+            p: SparqlTerm::PrefixedName(("wdt".into(),"P569".into())),
+            o: SparqlTerm::Variable("dob".into()),
+        })]));
+        assert_eq!(query.variables(),vec!["p".to_string(),"dob".into(),"image".into(),"g".into()]);
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        let query = SparqlParser::parse(r#"SELECT ?p { ?p rdfs:label ?l FILTER(lang(?l)="en" && !CONTAINS(?l,"Adams") || ?p!=wd:Q42) FILTER bound(?l) }"#).unwrap();
+        let var = |name: &str| Expression::Term(SparqlTerm::Variable(name.into()));
+        let string = |s: &str| Expression::Term(SparqlTerm::Literal { value: s.into(), datatype: None, language: None });
+        assert_eq!(query.patterns[1],GraphPattern::Filter(Expression::Or(
+            Box::new(Expression::And(
+                Box::new(Expression::Comparison(ComparisonOp::Eq,Box::new(Expression::Function("lang".into(),vec![var("l")])),Box::new(string("en")))),
+                Box::new(Expression::Not(Box::new(Expression::Function("contains".into(),vec![var("l"),string("Adams")])))),
+            )),
+            Box::new(Expression::Comparison(ComparisonOp::Ne,Box::new(var("p")),Box::new(Expression::Term(SparqlTerm::PrefixedName(("wd".into(),"Q42".into())))))),
+        )));
+        assert_eq!(query.patterns[2],GraphPattern::Filter(Expression::Function("bound".into(),vec![var("l")])));
+        assert!(SparqlParser::parse("SELECT ?p { ?p rdfs:label ?l FILTER ?l }").is_err());
+    }
+
+    #[test]
+    fn test_parse_modifiers() {
+        let query = SparqlParser::parse("SELECT DISTINCT ?p { ?p wdt:P31 wd:Q5 } ORDER BY DESC(?p) ?x OFFSET 20 LIMIT 10").unwrap();
+        assert_eq!(query.modifiers,SolutionModifiers {
+            distinct: true,
+            order_by: vec![OrderCondition { variable: "p".into(), descending: true },OrderCondition { variable: "x".into(), descending: false }],
+            limit: Some(10),
+            offset: Some(20),
+        });
+        let query = SparqlParser::parse("SELECT ?p { ?p wdt:P31 wd:Q5 } LIMIT 5").unwrap();
+        assert_eq!((query.modifiers.distinct,query.modifiers.limit,query.modifiers.offset),(false,Some(5),None));
+        assert!(SparqlParser::parse("SELECT ?p { ?p wdt:P31 wd:Q5 } LIMIT -5").is_err());
+    }
+
+    #[test]
+    fn test_parse_aggregates() {
+        let query = SparqlParser::parse(r#"SELECT ?class (COUNT(DISTINCT ?item) AS ?count) (GROUP_CONCAT(?l;separator=", ") AS ?labels) {
+            ?item wdt:P31 ?class
+        } GROUP BY ?class HAVING (COUNT(*)>10) ORDER BY DESC(?count)"#).unwrap();
+        assert_eq!(query.projection,Projection::Variables(vec!["class".into(),"count".into(),"labels".into()]));
+        assert_eq!(query.select_expressions[0],("count".to_string(),Expression::Aggregate(Aggregate { function: AggregateFunction::Count, distinct: true, variable: Some("item".into()) })));
+        assert_eq!(query.select_expressions[1].1,Expression::Aggregate(Aggregate { function: AggregateFunction::GroupConcat(", ".into()), distinct: false, variable: Some("l".into()) }));
+        assert_eq!(query.group_by,vec!["class".to_string()]);
+        assert_eq!(query.having,vec![Expression::Comparison(ComparisonOp::Gt,
+            Box::new(Expression::Aggregate(Aggregate { function: AggregateFunction::Count, distinct: false, variable: None })),
+            Box::new(Expression::Term(SparqlTerm::Literal { value: "10".into(), datatype: Some(Box::new(SparqlTerm::Iri(XSD_INTEGER.into()))), language: None })),
+        )]);
+        assert!(SparqlParser::parse("SELECT (SUM(*) AS ?x) { ?item wdt:P31 ?class }").is_err());
+    }
+
+    #[test]
+    fn test_parse_property_paths() {
+        let query = SparqlParser::parse("SELECT ?x { ?x wdt:P31/wdt:P279* wd:Q5 ; ^wdt:P40|wdt:P22? ?y ; a+ ?z . ?x wdt:P31? ?c }").unwrap();
+        let p = |local: &str| Box::new(PropertyPath::Predicate(SparqlTerm::PrefixedName(("wdt".into(),local.into()))));
+        let path = |num: usize| match &query.patterns[num] {
+            GraphPattern::Path(path) => path.path.to_owned(),
+            other => panic!("Not a path: {other:?}"),
+        };
+        assert_eq!(path(0),PropertyPath::Sequence(vec![*p("P31"),PropertyPath::ZeroOrMore(p("P279"))]));
+        assert_eq!(path(1),PropertyPath::Alternative(vec![PropertyPath::Inverse(p("P40")),PropertyPath::ZeroOrOne(p("P22"))]));
+        assert_eq!(path(2),PropertyPath::OneOrMore(Box::new(PropertyPath::Predicate(SparqlTerm::Iri(RDF_TYPE.into())))));
+        assert_eq!(path(3),PropertyPath::ZeroOrOne(p("P31")));
+        assert_eq!(query.variables(),vec!["x".to_string(),"y".into(),"z".into(),"c".into()]);
+    }
+
+    #[test]
+    fn test_parse_union_minus_exists() {
+        let query = SparqlParser::parse("SELECT ?x { ?x wdt:P31 wd:Q5 { ?x wdt:P569 ?d } UNION { ?x wdt:P570 ?d } MINUS { ?x wdt:P21 wd:Q6581097 } FILTER NOT EXISTS { ?x wdt:P19 ?place } }").unwrap();
+        assert!(matches!(&query.patterns[1],GraphPattern::Union(branches) if branches.len()==2));
+        assert!(matches!(&query.patterns[2],GraphPattern::Minus(patterns) if patterns.len()==1));
+        assert!(matches!(&query.patterns[3],GraphPattern::Filter(Expression::Not(e)) if matches!(e.as_ref(),Expression::Exists(_))));
+        assert_eq!(query.variables(),vec!["x".to_string(),"d".into()]);
+        assert!(SparqlParser::parse("SELECT ?x { ?x wdt:P31 wd:Q5 FILTER(EXISTS { ?x wdt:P19 ?place }) }").is_ok());
+    }
+
+    #[test]
+    fn test_parse_service() {
+        let query = SparqlParser::parse(r#"SELECT ?item ?itemLabel { ?item wdt:P31 wd:Q5 SERVICE wikibase:label { bd:serviceParam wikibase:language "[AUTO_LANGUAGE],en". } }"#).unwrap();
+        match &query.patterns[1] {
+            GraphPattern::Service(service,patterns) => {
+                assert_eq!(*service,SparqlTerm::PrefixedName(("wikibase".into(),"label".into())));
+                assert!(matches!(&patterns[0],GraphPattern::Triple(t) if t.p==SparqlTerm::PrefixedName(("wikibase".into(),"language".into()))));
+            }
+            other => panic!("Not a service: {other:?}"),
+        }
+        assert_eq!(query.variables(),vec!["item".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_graph() {
+        let query = SparqlParser::parse("SELECT * { GRAPH ?g { ?x wdt:P31 wd:Q5 } GRAPH <http://example.org/g> { ?x wdt:P21 ?sex } }").unwrap();
+        assert!(matches!(&query.patterns[0],GraphPattern::Graph(SparqlTerm::Variable(g),patterns) if g=="g" && patterns.len()==1));
+        assert!(matches!(&query.patterns[1],GraphPattern::Graph(SparqlTerm::Iri(_),_)));
+        assert_eq!(query.variables(),vec!["g".to_string(),"x".into(),"sex".into()]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(SparqlParser::parse("SELECT ?x WHERE { ?x wdt:P31 }").is_err());
+        assert!(SparqlParser::parse("SELECT ?x WHERE { ?x wdt:P31 wd:Q5 } garbage").is_err());
+        assert!(SparqlParser::parse("SELECT WHERE { ?x wdt:P31 wd:Q5 }").is_err());
+        assert!(SparqlParser::parse("SELECT ?x WHERE { ?x wdt:P31 wd:Q5 ?x wdt:P21 ?g }").is_err());
+    }
+}
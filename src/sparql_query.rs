@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use crate::{app_state::AppState, element::Element, entity::Entity, geo_search::GeoSearch, lat_lon::LatLon, error::WDQSErr, query_part::QueryPart, query_triples::{QueryTriples, QueryPartMeta, DatabaseQueryResult, SqlModifiers, GraphPart}, sparql_expression::Expression, sparql_parser::SparqlParser, sparql_results::SparqlResults};
+
+const WIKIBASE: &str = "http://wikiba.se/ontology#";
+const WIKIBASE_LABEL: &str = "http://wikiba.se/ontology#label";
+const WIKIBASE_LANGUAGE: &str = "http://wikiba.se/ontology#language";
+const WIKIBASE_AROUND: &str = "http://wikiba.se/ontology#around";
+const WIKIBASE_BOX: &str = "http://wikiba.se/ontology#box";
+const BD_SERVICE_PARAM: &str = "http://www.bigdata.com/rdf#serviceParam";
+const DEFAULT_LANGUAGE: &str = "en"; // Used for [AUTO_LANGUAGE]
+
+/// State of a single compilation of a query
+#[derive(Debug, Default)]
+struct CompileState {
+    path_variables: usize, // Counter for the hidden variables that link the steps of property path sequences
+}
+
+impl CompileState {
+    fn path_variable(&mut self) -> SparqlTerm {
+        self.path_variables += 1;
+        SparqlTerm::Variable(format!("__path{}",self.path_variables-1))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SparqlTerm {
+    Variable(String),
+    Iri(String),
+    PrefixedName((String,String)), // (prefix,local name)
+    Literal { value: String, datatype: Option<Box<SparqlTerm>>, language: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriplePattern {
+    pub s: SparqlTerm,
+    pub p: SparqlTerm,
+    pub o: SparqlTerm,
+}
+
+impl TriplePattern {
+    pub fn variables(&self) -> Vec<String> {
+        [&self.s,&self.p,&self.o]
+            .iter()
+            .filter_map(|term|match term {
+                SparqlTerm::Variable(name) => Some(name.to_owned()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyPath {
+    Predicate(SparqlTerm),
+    Sequence(Vec<PropertyPath>),
+    Alternative(Vec<PropertyPath>),
+    Inverse(Box<PropertyPath>),
+    ZeroOrMore(Box<PropertyPath>),
+    OneOrMore(Box<PropertyPath>),
+    ZeroOrOne(Box<PropertyPath>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPattern {
+    pub s: SparqlTerm,
+    pub path: PropertyPath,
+    pub o: SparqlTerm,
+}
+
+impl PathPattern {
+    pub fn variables(&self) -> Vec<String> {
+        [&self.s,&self.o]
+            .iter()
+            .filter_map(|term|match term {
+                SparqlTerm::Variable(name) => Some(name.to_owned()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphPattern {
+    Triple(TriplePattern),
+    Path(PathPattern),
+    Optional(Vec<GraphPattern>),
+    Union(Vec<Vec<GraphPattern>>), // A single branch is a nested group
+    Minus(Vec<GraphPattern>),
+    Service(SparqlTerm,Vec<GraphPattern>),
+    Graph(SparqlTerm,Vec<GraphPattern>),
+    Filter(Expression),
+}
+
+impl GraphPattern {
+    pub fn variables(&self) -> Vec<String> {
+        match self {
+            GraphPattern::Triple(triple) => triple.variables(),
+            GraphPattern::Path(path) => path.variables(),
+            GraphPattern::Optional(patterns) => patterns.iter().flat_map(|pattern|pattern.variables()).collect(),
+            GraphPattern::Union(branches) => branches.iter().flatten().flat_map(|pattern|pattern.variables()).collect(),
+            GraphPattern::Minus(_) | GraphPattern::Filter(_) => vec![], // Variables in MINUS are never bound
+            GraphPattern::Service(_,_) => vec![], // Label variables are only bound when they are used elsewhere
+            GraphPattern::Graph(graph,patterns) => {
+                let graph = match graph {
+                    SparqlTerm::Variable(name) => Some(name.to_owned()),
+                    _ => None,
+                };
+                graph.into_iter().chain(patterns.iter().flat_map(|pattern|pattern.variables())).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Projection {
+    All,
+    Variables(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderCondition {
+    pub variable: String,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolutionModifiers {
+    pub distinct: bool, // REDUCED may remove duplicates, so it is treated like DISTINCT
+    pub order_by: Vec<OrderCondition>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparqlQuery {
+    pub prefixes: HashMap<String,String>,
+    pub projection: Projection,
+    pub select_expressions: Vec<(String,Expression)>, // (variable,expression)
+    pub patterns: Vec<GraphPattern>,
+    pub group_by: Vec<String>,
+    pub having: Vec<Expression>,
+    pub modifiers: SolutionModifiers,
+}
+
+impl SparqlQuery {
+    pub fn parse(query: &str) -> Result<Self,WDQSErr> {
+        SparqlParser::parse(query)
+    }
+
+    /// Expands prefixed names, using the query PREFIX declarations first, then the configured prefixes
+    pub fn expand(&self, term: &SparqlTerm, app: &AppState) -> Result<String,WDQSErr> {
+        match term {
+            SparqlTerm::Iri(iri) => Ok(iri.to_owned()),
+            SparqlTerm::PrefixedName((prefix,local)) => {
+                match self.prefixes.get(prefix) {
+                    Some(path) => Ok(format!("{path}{local}")),
+                    None => {
+                        let pname = format!("{prefix}:{local}");
+                        let iri = app.replace_prefix(&pname);
+                        if iri==pname {
+                            return Err(WDQSErr::ParserError(format!("SparqlQuery: Unknown prefix '{prefix}:'")));
+                        }
+                        Ok(iri)
+                    }
+                }
+            }
+            _ => Err(WDQSErr::ParserError(format!("SparqlQuery::expand: {term:?} is not an IRI"))),
+        }
+    }
+
+    pub fn query_part(&self, term: &SparqlTerm, app: &AppState) -> Result<(QueryPart,QueryPartMeta),WDQSErr> {
+        let element = match term {
+            SparqlTerm::Variable(name) => return Ok((QueryPart::Unknown,QueryPartMeta::new(Some(name.to_owned())))),
+            SparqlTerm::Literal { value, datatype, language } => {
+                let datatype = match datatype {
+                    Some(datatype) => Some(self.expand(datatype, app)?),
+                    None => None,
+                };
+                Element::from_literal(value, datatype.as_deref(), language.as_deref())
+            }
+            _ => Element::from_str(&self.expand(term, app)?),
+        };
+        let element = element.ok_or_else(||WDQSErr::ParserError(format!("SparqlQuery: Can not use {term:?}")))?;
+        Ok((QueryPart::Element(element),QueryPartMeta::default()))
+    }
+
+    /// All variables in the order they first appear in the WHERE clause
+    pub fn variables(&self) -> Vec<String> {
+        let mut ret: Vec<String> = vec![];
+        for variable in self.patterns.iter().flat_map(|pattern|pattern.variables()) {
+            if !ret.contains(&variable) {
+                ret.push(variable);
+            }
+        }
+        ret
+    }
+
+    pub fn result_variables(&self) -> Vec<String> {
+        match &self.projection {
+            Projection::All => self.variables(),
+            Projection::Variables(variables) => variables.to_owned(),
+        }
+    }
+
+    /// The graph of the patterns in `GRAPH graph { .. }`
+    fn graph_part(&self, graph: &SparqlTerm, app: &AppState) -> Result<GraphPart,WDQSErr> {
+        match graph {
+            SparqlTerm::Variable(name) => Ok(GraphPart::Variable(name.to_owned())),
+            _ => Ok(GraphPart::Named(self.expand(graph, app)?)),
+        }
+    }
+
+    /// Compiles a group graph pattern into a single, joined QueryTriples
+    async fn compile_group(&self, patterns: &[GraphPattern], graph: &GraphPart, app: &AppState, state: &mut CompileState) -> Result<Option<QueryTriples>,WDQSErr> {
+        let mut ret: Option<QueryTriples> = None;
+        for pattern in patterns {
+            match pattern {
+                GraphPattern::Triple(triple) => {
+                    let s = self.query_part(&triple.s, app)?;
+                    let p = self.query_part(&triple.p, app)?;
+                    let o = self.query_part(&triple.o, app)?;
+                    let qt = QueryTriples::from_parts_in_graph(app, s, p, o, graph.to_owned()).await?;
+                    match ret.as_mut() {
+                        Some(joined) => joined.and(&qt)?,
+                        None => ret = Some(qt),
+                    }
+                }
+                GraphPattern::Path(path) => {
+                    let qt = self.compile_path(&path.s, &path.path, &path.o, graph, app, state).await?;
+                    match ret.as_mut() {
+                        Some(joined) => joined.and(&qt)?,
+                        None => ret = Some(qt),
+                    }
+                }
+                GraphPattern::Optional(optional_patterns) => {
+                    let qt = match Box::pin(self.compile_group(optional_patterns, graph, app, state)).await? {
+                        Some(qt) => qt,
+                        None => continue, // Empty OPTIONAL
+                    };
+                    let joined = ret.get_or_insert_with(QueryTriples::empty_solution);
+                    joined.optional(&qt)?;
+                }
+                GraphPattern::Union(branches) => {
+                    let mut united: Option<QueryTriples> = None;
+                    for branch in branches {
+                        if let Some(qt) = Box::pin(self.compile_group(branch, graph, app, state)).await? {
+                            match united.as_mut() {
+                                Some(united) => united.union(&qt),
+                                None => united = Some(qt),
+                            }
+                        }
+                    }
+                    let qt = match united {
+                        Some(qt) => qt,
+                        None => continue, // Empty group
+                    };
+                    match ret.as_mut() {
+                        Some(joined) => joined.and(&qt)?,
+                        None => ret = Some(qt),
+                    }
+                }
+                GraphPattern::Minus(minus_patterns) => {
+                    if let (Some(joined),Some(qt)) = (ret.as_mut(),Box::pin(self.compile_group(minus_patterns, graph, app, state)).await?) {
+                        joined.minus(&qt);
+                    }
+                }
+                GraphPattern::Graph(graph_term,graph_patterns) => {
+                    let qt = match Box::pin(self.compile_group(graph_patterns, &self.graph_part(graph_term, app)?, app, state)).await? {
+                        Some(qt) => qt,
+                        None => continue, // Empty group
+                    };
+                    match ret.as_mut() {
+                        Some(joined) => joined.and(&qt)?,
+                        None => ret = Some(qt),
+                    }
+                }
+                GraphPattern::Service(service,service_patterns) => {
+                    let qt = match self.compile_geo_service(service, service_patterns, graph, app).await? {
+                        Some(qt) => qt,
+                        None => continue, // The label service is applied to the whole group, below
+                    };
+                    match ret.as_mut() {
+                        Some(joined) => joined.and(&qt)?,
+                        None => ret = Some(qt),
+                    }
+                }
+                GraphPattern::Filter(_) => {} // Applied to the whole group, below
+            }
+        }
+        // The label service and filters apply to the entire group, regardless of their position
+        if let Some(qt) = ret.as_mut() {
+            for pattern in patterns {
+                if let GraphPattern::Service(service,service_patterns) = pattern {
+                    self.apply_label_service(qt, service, service_patterns, app)?;
+                }
+            }
+            for pattern in patterns {
+                if let GraphPattern::Filter(expression) = pattern {
+                    self.apply_filter(qt, expression, graph, app, state).await?;
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// wikibase:around and wikibase:box, with one triple for the coordinates and a constant center or corners.
+    /// Returns None for the label service.
+    async fn compile_geo_service(&self, service: &SparqlTerm, patterns: &[GraphPattern], graph: &GraphPart, app: &AppState) -> Result<Option<QueryTriples>,WDQSErr> {
+        let service = self.expand(service, app)?;
+        if service==WIKIBASE_LABEL {
+            return Ok(None);
+        }
+        if service!=WIKIBASE_AROUND && service!=WIKIBASE_BOX {
+            return Err(WDQSErr::ParserError(format!("SparqlQuery: Unsupported SERVICE <{service}>")));
+        }
+        let mut triple = None;
+        let mut params = HashMap::new();
+        for pattern in patterns {
+            let t = match pattern {
+                GraphPattern::Triple(t) => t,
+                _ => return Err(WDQSErr::ParserError("SparqlQuery: Only triples are allowed in geospatial services".into())),
+            };
+            let is_param = !matches!(t.s,SparqlTerm::Variable(_)) && self.expand(&t.s, app)?==BD_SERVICE_PARAM;
+            match (is_param,triple.is_none()) {
+                (true,_) => {
+                    let param = self.expand(&t.p, app)?;
+                    params.insert(param.trim_start_matches(WIKIBASE).to_string(),&t.o);
+                }
+                (false,true) => triple = Some(t),
+                (false,false) => return Err(WDQSErr::ParserError("SparqlQuery: Geospatial services take a single coordinate triple".into())),
+            }
+        }
+        let triple = triple.ok_or_else(||WDQSErr::ParserError("SparqlQuery: Geospatial service without coordinate triple".into()))?;
+        let point = |name: &str| -> Result<LatLon,WDQSErr> {
+            match params.get(name).map(|term|self.query_part(term, app)).transpose()? {
+                Some((QueryPart::Element(Element::LatLon(point)),_)) => Ok(point),
+                _ => Err(WDQSErr::ParserError(format!("SparqlQuery: wikibase:{name} must be a constant WKT point"))),
+            }
+        };
+        let globe_q = match params.get("globe").map(|term|self.query_part(term, app)).transpose()? {
+            Some((QueryPart::Element(Element::Entity(Entity::Item(q))),_)) => Some(q),
+            None => None,
+            _ => return Err(WDQSErr::ParserError("SparqlQuery: wikibase:globe must be an item".into())),
+        };
+        let mut distance = None;
+        let search = if service==WIKIBASE_AROUND {
+            let center = point("center")?;
+            let radius = match params.get("radius") {
+                Some(SparqlTerm::Literal { value, .. }) => value.parse::<f64>().ok(),
+                _ => None,
+            }.ok_or_else(||WDQSErr::ParserError("SparqlQuery: wikibase:radius must be a number".into()))?;
+            distance = match params.get("distance") {
+                Some(SparqlTerm::Variable(name)) => Some(name.as_str()),
+                None => None,
+                _ => return Err(WDQSErr::ParserError("SparqlQuery: wikibase:distance must be a variable".into())),
+            };
+            let globe_q = globe_q.unwrap_or(center.globe_q());
+            GeoSearch::Around { center, radius, globe_q }
+        } else {
+            let (south_west,north_east) = match (point("cornerSouthWest"),point("cornerNorthEast")) {
+                (Ok(south_west),Ok(north_east)) => (south_west,north_east),
+                _ => {
+                    // cornerWest/cornerEast are the north-west and south-east corners
+                    let (west,east) = (point("cornerWest")?,point("cornerEast")?);
+                    let south_west = LatLon::new(east.latitude(),west.longitude(),west.globe_q());
+                    let north_east = LatLon::new(west.latitude(),east.longitude(),east.globe_q());
+                    (south_west,north_east)
+                }
+            };
+            let globe_q = globe_q.unwrap_or(south_west.globe_q());
+            GeoSearch::Box { south_west, north_east, globe_q }
+        };
+        let s = self.query_part(&triple.s, app)?;
+        let p = self.query_part(&triple.p, app)?;
+        let o = self.query_part(&triple.o, app)?;
+        Ok(Some(QueryTriples::from_geo(app, s, p, o, graph.to_owned(), &search, distance).await?))
+    }
+
+    /// Variables used outside of the WHERE clause
+    fn used_variables(&self) -> Vec<String> {
+        let mut ret = self.result_variables();
+        ret.extend(self.select_expressions.iter().flat_map(|(_,e)|e.variables()));
+        ret.extend(self.having.iter().flat_map(|e|e.variables()));
+        ret.extend(self.group_by.iter().cloned());
+        ret.extend(self.modifiers.order_by.iter().map(|c|c.variable.to_owned()));
+        ret
+    }
+
+    /// Emulates the wikibase:label service. Without explicit rdfs:label, schema:description or skos:altLabel triples,
+    /// every used ?xLabel, ?xDescription and ?xAltLabel is resolved for a bound ?x.
+    fn apply_label_service(&self, qt: &mut QueryTriples, service: &SparqlTerm, patterns: &[GraphPattern], app: &AppState) -> Result<(),WDQSErr> {
+        if self.expand(service, app)?!=WIKIBASE_LABEL {
+            return Ok(());
+        }
+        let mut languages = vec![];
+        let mut labels = vec![]; // (variable,label variable,property)
+        for pattern in patterns {
+            let triple = match pattern {
+                GraphPattern::Triple(triple) => triple,
+                _ => return Err(WDQSErr::ParserError("SparqlQuery: Only triples are allowed in the label service".into())),
+            };
+            match (&triple.s,self.query_part(&triple.p, app)?.0,&triple.o) {
+                (_,QueryPart::Element(p),SparqlTerm::Literal { value, .. }) if p.to_iri().as_deref()==Some(WIKIBASE_LANGUAGE) => {
+                    for language in value.split(',').map(|l|l.trim()).filter(|l|!l.is_empty()) {
+                        languages.push(if language=="[AUTO_LANGUAGE]" { DEFAULT_LANGUAGE } else { language }.to_string());
+                    }
+                }
+                (SparqlTerm::Variable(variable),QueryPart::Element(p),SparqlTerm::Variable(label)) if matches!(p,Element::RdfSchemaLabel|Element::SchemaOrg(_)|Element::W3SkosCoreAltLabel) => {
+                    labels.push((variable.to_owned(),label.to_owned(),p));
+                }
+                _ => return Err(WDQSErr::ParserError(format!("SparqlQuery: Unsupported triple in the label service: {triple:?}"))),
+            }
+        }
+        if languages.is_empty() {
+            languages.push(DEFAULT_LANGUAGE.to_string());
+        }
+        if labels.is_empty() {
+            let bound: Vec<&str> = qt.result.values().flat_map(|part|part.variables.iter().map(|v|v.name())).collect();
+            for label in self.used_variables() {
+                let suffixes = [("AltLabel",Element::W3SkosCoreAltLabel),("Label",Element::RdfSchemaLabel),("Description",Element::SchemaOrg("description".into()))];
+                let found = suffixes.into_iter().find_map(|(suffix,p)|Some((label.strip_suffix(suffix)?.to_string(),p)));
+                if let Some((variable,p)) = found {
+                    if bound.contains(&variable.as_str()) && !bound.contains(&label.as_str()) && !labels.iter().any(|(_,l,_)|*l==label) {
+                        labels.push((variable,label.to_owned(),p));
+                    }
+                }
+            }
+        }
+        for (variable,label,p) in labels {
+            qt.label(app, &variable, &label, &p, &languages);
+        }
+        Ok(())
+    }
+
+    /// FILTER (NOT) EXISTS as the whole condition becomes a subquery; everything else is an SQL condition
+    async fn apply_filter(&self, qt: &mut QueryTriples, expression: &Expression, graph: &GraphPart, app: &AppState, state: &mut CompileState) -> Result<(),WDQSErr> {
+        let (patterns,negated) = match expression {
+            Expression::Exists(patterns) => (patterns,false),
+            Expression::Not(e) => match e.as_ref() {
+                Expression::Exists(patterns) => (patterns,true),
+                _ => return qt.filter(|part|expression.to_sql(self, app, part)),
+            },
+            _ => return qt.filter(|part|expression.to_sql(self, app, part)),
+        };
+        match Box::pin(self.compile_group(patterns, graph, app, state)).await? {
+            Some(other) => qt.exists(&other, negated),
+            None if negated => qt.result.clear(), // NOT EXISTS {} is always false
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Compiles a property path between two terms; sequences are joined via hidden `__path{n}` variables
+    async fn compile_path(&self, s: &SparqlTerm, path: &PropertyPath, o: &SparqlTerm, graph: &GraphPart, app: &AppState, state: &mut CompileState) -> Result<QueryTriples,WDQSErr> {
+        match path {
+            PropertyPath::Predicate(p) => {
+                QueryTriples::from_parts_in_graph(app, self.query_part(s, app)?, self.query_part(p, app)?, self.query_part(o, app)?, graph.to_owned()).await
+            }
+            PropertyPath::Inverse(path) => Box::pin(self.compile_path(o, path, s, graph, app, state)).await,
+            PropertyPath::Sequence(paths) => {
+                let mut ret: Option<QueryTriples> = None;
+                let mut from = s.to_owned();
+                for (num,path) in paths.iter().enumerate() {
+                    let to = match num+1==paths.len() {
+                        true => o.to_owned(),
+                        false => state.path_variable(),
+                    };
+                    let qt = Box::pin(self.compile_path(&from, path, &to, graph, app, state)).await?;
+                    match ret.as_mut() {
+                        Some(joined) => joined.and(&qt)?,
+                        None => ret = Some(qt),
+                    }
+                    from = to;
+                }
+                ret.ok_or_else(||WDQSErr::ParserError("SparqlQuery: Empty property path sequence".into()))
+            }
+            PropertyPath::Alternative(paths) => {
+                let mut ret: Option<QueryTriples> = None;
+                for path in paths {
+                    let qt = Box::pin(self.compile_path(s, path, o, graph, app, state)).await?;
+                    match ret.as_mut() {
+                        Some(united) => united.union(&qt),
+                        None => ret = Some(qt),
+                    }
+                }
+                ret.ok_or_else(||WDQSErr::ParserError("SparqlQuery: Empty property path alternative".into()))
+            }
+            PropertyPath::ZeroOrMore(inner) | PropertyPath::OneOrMore(inner) | PropertyPath::ZeroOrOne(inner) => {
+                let zero_length = !matches!(path,PropertyPath::OneOrMore(_));
+                let transitive = !matches!(path,PropertyPath::ZeroOrOne(_));
+                let (s,p,o) = match inner.as_ref() {
+                    PropertyPath::Predicate(p) => (s,p,o),
+                    PropertyPath::Inverse(inverse) => match inverse.as_ref() {
+                        PropertyPath::Predicate(p) => (o,p,s),
+                        _ => return Err(WDQSErr::ParserError(format!("SparqlQuery: Unsupported property path {path:?}"))),
+                    },
+                    _ => return Err(WDQSErr::ParserError(format!("SparqlQuery: Unsupported property path {path:?}"))),
+                };
+                let p = match self.query_part(p, app)?.0 {
+                    QueryPart::Element(element) => element,
+                    QueryPart::Unknown => return Err(WDQSErr::ParserError("SparqlQuery: Variables are not allowed in property paths".into())),
+                };
+                QueryTriples::from_closure(app, self.query_part(s, app)?, p, self.query_part(o, app)?, graph.to_owned(), zero_length, transitive).await
+            }
+        }
+    }
+
+    pub fn is_aggregated(&self) -> bool {
+        !self.group_by.is_empty() || !self.having.is_empty() || self.select_expressions.iter().any(|(_,e)|matches!(e,Expression::Aggregate(_)))
+    }
+
+    /// Computes GROUP BY, aggregates and HAVING
+    fn compile_aggregation(&self, qt: &mut QueryTriples, app: &AppState) -> Result<(),WDQSErr> {
+        let mut aggregates = vec![];
+        for (variable,expression) in &self.select_expressions {
+            match expression {
+                Expression::Aggregate(aggregate) => aggregates.push((variable.to_owned(),aggregate.to_owned())),
+                _ => return Err(WDQSErr::ParserError(format!("SparqlQuery: Only aggregates are supported in SELECT, not {expression:?}"))),
+            }
+        }
+        let having: Vec<Expression> = self.having.iter().map(|e|e.extract_aggregates(&mut aggregates)).collect();
+        qt.aggregate(&self.group_by, &aggregates)?;
+        for expression in &having {
+            qt.filter(|part|expression.to_sql(self, app, part))?;
+        }
+        Ok(())
+    }
+
+    pub async fn compile(&self, app: &AppState) -> Result<QueryTriples,WDQSErr> {
+        let mut ret = self.compile_group(&self.patterns, &GraphPart::Default, app, &mut CompileState::default()).await?
+            .ok_or_else(||WDQSErr::ParserError("SparqlQuery::compile: Empty WHERE clause".into()))?;
+        if self.is_aggregated() {
+            self.compile_aggregation(&mut ret, app)?;
+        } else if !self.select_expressions.is_empty() {
+            return Err(WDQSErr::ParserError("SparqlQuery: Expressions in SELECT require aggregation".into()));
+        }
+        // Variables only used for sorting are kept until the results are sorted
+        let result_variables = self.result_variables();
+        let mut variables = result_variables.clone();
+        for condition in &self.modifiers.order_by {
+            if !variables.contains(&condition.variable) {
+                variables.push(condition.variable.to_owned());
+            }
+        }
+        ret.project(&variables);
+        ret.modify(&SqlModifiers {
+            distinct: self.modifiers.distinct,
+            order_by: self.modifiers.order_by.iter().map(|c|(c.variable.to_owned(),c.descending)).collect(),
+            limit: self.modifiers.limit,
+            offset: self.modifiers.offset,
+            variables: result_variables,
+        });
+        Ok(ret)
+    }
+
+    pub async fn run(&self, app: &AppState) -> Result<HashMap<String,DatabaseQueryResult>,WDQSErr> {
+        self.compile(app).await?.run(app).await
+    }
+
+    /// Runs the query and decodes the results into RDF terms
+    pub async fn results(&self, app: &AppState) -> Result<SparqlResults,WDQSErr> {
+        let qt = self.compile(app).await?;
+        let results = qt.run(app).await?;
+        let mut ret = SparqlResults::new(self.result_variables(), &results);
+        // Groups were limited and sorted individually, so merge them here
+        let order_by: Vec<_> = self.modifiers.order_by.iter().map(|c|(c.variable.to_owned(),c.descending)).collect();
+        ret.sort(&order_by);
+        if self.modifiers.distinct {
+            ret.distinct();
+        }
+        ret.slice(qt.offset, self.modifiers.limit);
+        Ok(ret)
+    }
+}
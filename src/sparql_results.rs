@@ -0,0 +1,351 @@
+use std::{collections::{HashMap, HashSet}, cmp::Ordering};
+use crate::{query_triples::DatabaseQueryResult, date_time::DateTime, element_type::ElementType};
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// A single RDF term in a query result
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SparqlValue {
+    Uri(String),
+    BlankNode(String), // Label
+    Literal { value: String, language: Option<String>, datatype: Option<String> },
+}
+
+impl SparqlValue {
+    pub fn value(&self) -> &str {
+        match self {
+            SparqlValue::Uri(uri) => uri,
+            SparqlValue::BlankNode(label) => label,
+            SparqlValue::Literal { value, .. } => value,
+        }
+    }
+
+    /// CSV encoding, which only marks blank nodes
+    fn to_csv(&self) -> String {
+        match self {
+            SparqlValue::BlankNode(label) => format!("_:{label}"),
+            _ => csv_escape(self.value()),
+        }
+    }
+
+    fn date_time_value(&self) -> Option<DateTime> {
+        match self {
+            SparqlValue::Literal { value, datatype: Some(datatype), .. } if datatype.strip_prefix(XSD)==Some("dateTime") => Some(*DateTime::from_str(value)?),
+            _ => None,
+        }
+    }
+
+    fn numeric_value(&self) -> Option<f64> {
+        match self {
+            SparqlValue::Literal { value, datatype: Some(datatype), .. } => {
+                match datatype.strip_prefix(XSD)? {
+                    "integer" | "decimal" | "double" | "float" | "int" | "long" => value.parse::<f64>().ok(),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Ordering as used by ORDER BY: unbound values first, then blank nodes, then IRIs, then literals
+    pub fn order(a: Option<&Self>, b: Option<&Self>) -> Ordering {
+        match (a,b) {
+            (None,None) => Ordering::Equal,
+            (None,Some(_)) => Ordering::Less,
+            (Some(_),None) => Ordering::Greater,
+            (Some(SparqlValue::BlankNode(a)),Some(SparqlValue::BlankNode(b))) => a.cmp(b),
+            (Some(SparqlValue::BlankNode(_)),Some(_)) => Ordering::Less,
+            (Some(_),Some(SparqlValue::BlankNode(_))) => Ordering::Greater,
+            (Some(SparqlValue::Uri(a)),Some(SparqlValue::Uri(b))) => a.cmp(b),
+            (Some(SparqlValue::Uri(_)),Some(_)) => Ordering::Less,
+            (Some(_),Some(SparqlValue::Uri(_))) => Ordering::Greater,
+            (Some(a),Some(b)) => {
+                if let (Some(x),Some(y)) = (a.numeric_value(),b.numeric_value()) {
+                    return x.partial_cmp(&y).unwrap_or(Ordering::Equal);
+                }
+                if let (Some(x),Some(y)) = (a.date_time_value(),b.date_time_value()) {
+                    return x.sort_key().cmp(&y.sort_key());
+                }
+                a.value().cmp(b.value())
+            }
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            SparqlValue::Uri(uri) => serde_json::json!({"type":"uri","value":uri}),
+            SparqlValue::BlankNode(label) => serde_json::json!({"type":"bnode","value":label}),
+            SparqlValue::Literal { value, language, datatype } => {
+                let mut ret = serde_json::json!({"type":"literal","value":value});
+                if let Some(language) = language {
+                    ret["xml:lang"] = serde_json::json!(language);
+                }
+                if let Some(datatype) = datatype {
+                    ret["datatype"] = serde_json::json!(datatype);
+                }
+                ret
+            }
+        }
+    }
+
+    fn to_xml(&self) -> String {
+        match self {
+            SparqlValue::Uri(uri) => format!("<uri>{}</uri>",xml_escape(uri)),
+            SparqlValue::BlankNode(label) => format!("<bnode>{}</bnode>",xml_escape(label)),
+            SparqlValue::Literal { value, language: Some(language), .. } => format!("<literal xml:lang=\"{}\">{}</literal>",xml_escape(language),xml_escape(value)),
+            SparqlValue::Literal { value, datatype: Some(datatype), .. } => format!("<literal datatype=\"{}\">{}</literal>",xml_escape(datatype),xml_escape(value)),
+            SparqlValue::Literal { value, .. } => format!("<literal>{}</literal>",xml_escape(value)),
+        }
+    }
+
+    /// Turtle/N-Triples encoding, as used by TSV results
+    fn to_turtle(&self) -> String {
+        match self {
+            SparqlValue::Uri(uri) => format!("<{uri}>"),
+            SparqlValue::BlankNode(label) => format!("_:{label}"),
+            SparqlValue::Literal { value, language, datatype } => {
+                let value = value
+                    .replace('\\',"\\\\")
+                    .replace('"',"\\\"")
+                    .replace('\n',"\\n")
+                    .replace('\r',"\\r")
+                    .replace('\t',"\\t");
+                match (language,datatype) {
+                    (Some(language),_) => format!("\"{value}\"@{language}"),
+                    (None,Some(datatype)) => format!("\"{value}\"^^<{datatype}>"),
+                    (None,None) => format!("\"{value}\""),
+                }
+            }
+        }
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&',"&amp;")
+        .replace('<',"&lt;")
+        .replace('>',"&gt;")
+        .replace('"',"&quot;")
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',','"','\n','\r']) {
+        format!("\"{}\"",s.replace('"',"\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SparqlResultFormat {
+    Json,
+    Xml,
+    Csv,
+    Tsv,
+}
+
+impl SparqlResultFormat {
+    /// Accepts short names ("json") as well as MIME types
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.split(';').next()?.trim().to_lowercase().as_str() {
+            "json" | "application/sparql-results+json" | "application/json" => Some(Self::Json),
+            "xml" | "application/sparql-results+xml" | "application/xml" => Some(Self::Xml),
+            "csv" | "text/csv" => Some(Self::Csv),
+            "tsv" | "text/tab-separated-values" => Some(Self::Tsv),
+            _ => None,
+        }
+    }
+
+    /// Picks the first supported format from an HTTP Accept header
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        accept.split(',').find_map(Self::from_name)
+    }
+
+    pub fn content_type(&self) -> &str {
+        match self {
+            Self::Json => "application/sparql-results+json",
+            Self::Xml => "application/sparql-results+xml",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Tsv => "text/tab-separated-values; charset=utf-8",
+        }
+    }
+}
+
+/// Query results as RDF terms, ready for serialization
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SparqlResults {
+    pub variables: Vec<String>,
+    pub rows: Vec<HashMap<String,SparqlValue>>,
+}
+
+impl SparqlResults {
+    /// Merges the results of all groups, decoding values according to their element type
+    pub fn new(variables: Vec<String>, results: &HashMap<String,DatabaseQueryResult>) -> Self {
+        let mut group_keys: Vec<&String> = results.keys().collect();
+        group_keys.sort();
+        let mut rows = vec![];
+        for group_key in group_keys {
+            let result = &results[group_key];
+            for row in &result.rows {
+                let bindings = result.variables
+                    .iter()
+                    .zip(row.iter())
+                    .filter_map(|(variable,value)|{
+                        let element = variable.to_element(value.as_ref()?)?;
+                        Some((variable.name().to_string(),element.to_sparql_value()))
+                    })
+                    .collect();
+                rows.push(bindings);
+            }
+        }
+        Self { variables, rows }
+    }
+
+    /// Stable sort by (variable,descending) conditions
+    pub fn sort(&mut self, order_by: &[(String,bool)]) {
+        if order_by.is_empty() {
+            return;
+        }
+        self.rows.sort_by(|a,b|{
+            order_by
+                .iter()
+                .map(|(variable,descending)|{
+                    let ordering = SparqlValue::order(a.get(variable),b.get(variable));
+                    if *descending { ordering.reverse() } else { ordering }
+                })
+                .find(|ordering|*ordering!=Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    /// Removes duplicate rows, keeping the first one
+    pub fn distinct(&mut self) {
+        let mut seen = HashSet::new();
+        let variables = &self.variables;
+        self.rows.retain(|row|{
+            let key: Vec<Option<SparqlValue>> = variables.iter().map(|variable|row.get(variable).cloned()).collect();
+            seen.insert(key)
+        });
+    }
+
+    pub fn slice(&mut self, offset: usize, limit: Option<usize>) {
+        self.rows.drain(..offset.min(self.rows.len()));
+        if let Some(limit) = limit {
+            self.rows.truncate(limit);
+        }
+    }
+
+    pub fn serialize(&self, format: SparqlResultFormat) -> String {
+        match format {
+            SparqlResultFormat::Json => self.to_json(),
+            SparqlResultFormat::Xml => self.to_xml(),
+            SparqlResultFormat::Csv => self.to_csv(),
+            SparqlResultFormat::Tsv => self.to_tsv(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let bindings: Vec<serde_json::Value> = self.rows
+            .iter()
+            .map(|row|{
+                let row: serde_json::Map<String,serde_json::Value> = self.variables
+                    .iter()
+                    .filter_map(|name|Some((name.to_owned(),row.get(name)?.to_json())))
+                    .collect();
+                serde_json::Value::Object(row)
+            })
+            .collect();
+        serde_json::json!({
+            "head": {"vars": self.variables},
+            "results": {"bindings": bindings},
+        }).to_string()
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut ret = String::from("<?xml version=\"1.0\"?>\n<sparql xmlns=\"http://www.w3.org/2005/sparql-results#\">\n  <head>\n");
+        for variable in &self.variables {
+            ret += &format!("    <variable name=\"{}\"/>\n",xml_escape(variable));
+        }
+        ret += "  </head>\n  <results>\n";
+        for row in &self.rows {
+            ret += "    <result>\n";
+            for variable in &self.variables {
+                if let Some(value) = row.get(variable) {
+                    ret += &format!("      <binding name=\"{}\">{}</binding>\n",xml_escape(variable),value.to_xml());
+                }
+            }
+            ret += "    </result>\n";
+        }
+        ret += "  </results>\n</sparql>\n";
+        ret
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut ret = self.variables.iter().map(|v|csv_escape(v)).collect::<Vec<_>>().join(",");
+        ret += "\r\n";
+        for row in &self.rows {
+            ret += &self.variables
+                .iter()
+                .map(|variable|row.get(variable).map(|value|value.to_csv()).unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join(",");
+            ret += "\r\n";
+        }
+        ret
+    }
+
+    pub fn to_tsv(&self) -> String {
+        let mut ret = self.variables.iter().map(|v|format!("?{v}")).collect::<Vec<_>>().join("\t");
+        ret += "\n";
+        for row in &self.rows {
+            ret += &self.variables
+                .iter()
+                .map(|variable|row.get(variable).map(|value|value.to_turtle()).unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join("\t");
+            ret += "\n";
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results() -> SparqlResults {
+        let mut row = HashMap::new();
+        row.insert("item".to_string(),SparqlValue::Uri("http://www.wikidata.org/entity/Q42".into()));
+        row.insert("label".to_string(),SparqlValue::Literal { value: "Douglas \"DNA\" Adams, <author>".into(), language: Some("en".into()), datatype: None });
+        SparqlResults { variables: vec!["item".into(),"label".into(),"date".into()], rows: vec![row] }
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let number = |i: i32| SparqlValue::Literal { value: format!("{i}"), language: None, datatype: Some(format!("{XSD}integer")) };
+        let rows = [9,10,9,-1].iter().map(|i|HashMap::from([("n".to_string(),number(*i))])).collect();
+        let mut r = SparqlResults { variables: vec!["n".into()], rows };
+        r.rows.push(HashMap::new());
+        r.sort(&[("n".into(),true)]);
+        r.distinct();
+        r.slice(1, Some(2));
+        assert_eq!(r.rows.iter().map(|row|row.get("n").map(|v|v.value().to_string())).collect::<Vec<_>>(),vec![Some("9".into()),Some("-1".into())]);
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(SparqlResultFormat::from_name("json"),Some(SparqlResultFormat::Json));
+        assert_eq!(SparqlResultFormat::from_accept("text/html, text/csv;q=0.9"),Some(SparqlResultFormat::Csv));
+        assert_eq!(SparqlResultFormat::from_accept("text/html"),None);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let r = results();
+        let json: serde_json::Value = serde_json::from_str(&r.to_json()).unwrap();
+        assert_eq!(json["head"]["vars"][2],"date");
+        assert_eq!(json["results"]["bindings"][0]["label"]["xml:lang"],"en");
+        assert!(r.to_xml().contains("<binding name=\"label\"><literal xml:lang=\"en\">Douglas &quot;DNA&quot; Adams, &lt;author&gt;</literal></binding>"));
+        assert_eq!(r.to_csv(),"item,label,date\r\nhttp://www.wikidata.org/entity/Q42,\"Douglas \"\"DNA\"\" Adams, <author>\",\r\n");
+        assert_eq!(r.to_tsv(),"?item\t?label\t?date\n<http://www.wikidata.org/entity/Q42>\t\"Douglas \\\"DNA\\\" Adams, <author>\"@en\t\n");
+    }
+}
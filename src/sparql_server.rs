@@ -0,0 +1,91 @@
+use std::{collections::HashMap, sync::Arc};
+use axum::{Router, routing::get, extract::{State, Query}, http::{HeaderMap, StatusCode, header}, response::{IntoResponse, Response}};
+use crate::{app_state::AppState, error::WDQSErr, sparql_query::SparqlQuery, sparql_results::SparqlResultFormat};
+
+/// HTTP endpoint following the SPARQL 1.1 Protocol
+#[derive(Debug, Clone)]
+pub struct SparqlServer {
+    app: Arc<AppState>,
+}
+
+impl SparqlServer {
+    pub fn new(app: Arc<AppState>) -> Self {
+        Self { app }
+    }
+
+    pub async fn run(&self, port: u16) -> Result<(),WDQSErr> {
+        let router = Router::new()
+            .route("/sparql", get(Self::get_sparql).post(Self::post_sparql))
+            .with_state(self.app.clone());
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0",port)).await?;
+        eprintln!("SparqlServer: listening on port {port}");
+        axum::serve(listener, router).await?;
+        Ok(())
+    }
+
+    async fn get_sparql(State(app): State<Arc<AppState>>, headers: HeaderMap, Query(params): Query<HashMap<String,String>>) -> Response {
+        let format = Self::result_format(&headers, &params);
+        Self::answer(&app, params.get("query"), format).await
+    }
+
+    async fn post_sparql(State(app): State<Arc<AppState>>, headers: HeaderMap, Query(params): Query<HashMap<String,String>>, body: String) -> Response {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v|v.to_str().ok())
+            .unwrap_or_default()
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        match content_type.as_str() {
+            "application/sparql-query" => {
+                let format = Self::result_format(&headers, &params);
+                Self::answer(&app, Some(&body), format).await
+            }
+            "application/x-www-form-urlencoded" => {
+                let form = Self::parse_form(&body);
+                let format = Self::result_format(&headers, &form);
+                Self::answer(&app, form.get("query"), format).await
+            }
+            other => (StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("Unsupported content type '{other}'")).into_response(),
+        }
+    }
+
+    fn parse_form(body: &str) -> HashMap<String,String> {
+        let decode = |s: &str| percent_encoding::percent_decode(s.replace('+'," ").as_bytes()).decode_utf8_lossy().to_string();
+        body.split('&')
+            .filter_map(|pair|pair.split_once('='))
+            .map(|(key,value)|(decode(key),decode(value)))
+            .collect()
+    }
+
+    /// An explicit `format` parameter wins over the Accept header; defaults to XML, like WDQS
+    fn result_format(headers: &HeaderMap, params: &HashMap<String,String>) -> SparqlResultFormat {
+        params
+            .get("format")
+            .and_then(|format|SparqlResultFormat::from_name(format))
+            .or_else(||{
+                headers
+                    .get(header::ACCEPT)
+                    .and_then(|v|v.to_str().ok())
+                    .and_then(SparqlResultFormat::from_accept)
+            })
+            .unwrap_or(SparqlResultFormat::Xml)
+    }
+
+    async fn answer(app: &AppState, query: Option<&String>, format: SparqlResultFormat) -> Response {
+        let query = match query {
+            Some(query) => query,
+            None => return (StatusCode::BAD_REQUEST, "Missing 'query' parameter").into_response(),
+        };
+        let query = match SparqlQuery::parse(query) {
+            Ok(query) => query,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+        match query.results(app).await {
+            Ok(results) => ([(header::CONTENT_TYPE,format.content_type().to_string())], results.serialize(format)).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+}
@@ -6,6 +6,11 @@ pub struct TextId {
 }
 
 impl TextId {
+    /// The text as it is, eg a decoded literal
+    pub fn new(s: &str) -> Self {
+        Self { s: s.to_string() }
+    }
+
     fn from_str_lossy(s: &str) -> Self {
         // Percent-decode if required
         let s = if s.contains('%') {
@@ -13,17 +18,6 @@ impl TextId {
         } else {
             s.to_string()
         };
-
-        // Unicode backslash decode, eg "\u6BD4\u5229\u65F6"@zh
-        // DOES NOT WORK TOTO FIXME
-        // let s = if s.contains("\\u") {
-        //     match serde_json::from_str(&s) {
-        //         Ok(decoded) => decoded,
-        //         Err(_) => s,
-        //     }
-        // } else {
-        //     s
-        // };
         Self{s}
     }
 }
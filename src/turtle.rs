@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use nom::{IResult, bytes::complete::{tag, tag_no_case, take_while}, branch::alt, character::complete::{char, multispace1, satisfy}, combinator::{opt, recognize}, multi::many0, sequence::{preceded, pair}, error::VerboseError};
+use regex::Regex;
+use crate::{error::WDQSErr, ntriples::{self, Term, TermTriple}};
+
+type Res<T, U> = IResult<T, U, VerboseError<T>>;
+
+lazy_static! {
+    static ref RE_DOUBLE: Regex = Regex::new(r#"^[+-]?(?:[0-9]+\.[0-9]*|\.[0-9]+|[0-9]+)[eE][+-]?[0-9]+"#).expect("RE_DOUBLE does not parse");
+    static ref RE_DECIMAL: Regex = Regex::new(r#"^[+-]?[0-9]*\.[0-9]+"#).expect("RE_DECIMAL does not parse");
+    static ref RE_INTEGER: Regex = Regex::new(r#"^[+-]?[0-9]+"#).expect("RE_INTEGER does not parse");
+}
+
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// A statement that has not ended after this many bytes is assumed to be broken
+const MAX_STATEMENT_SIZE: usize = 100*1024*1024;
+
+/// Reads a Turtle document line by line, statement by statement; prefixes and the base IRI carry over between statements.
+/// `[]` and collections create blank nodes labelled `#genid1`, `#genid2`, ...; '#' can not occur in the labels of the document.
+#[derive(Debug, Default)]
+pub struct TurtleReader {
+    prefixes: HashMap<String,String>,
+    base: Option<String>,
+    buffer: String,
+    long_string: Option<char>, // Quote character, while in a multi-line literal
+    blank_nodes: usize,
+}
+
+impl TurtleReader {
+    /// Adds a line; returns the triples of the statements that it completes
+    pub fn add_line(&mut self, line: &str) -> Result<Vec<TermTriple>,WDQSErr> {
+        let last = self.scan_line(line);
+        self.buffer += line;
+        self.buffer.push('\n');
+        let complete = match last {
+            _ if self.long_string.is_some() => false,
+            Some('.') => true,
+            Some('>') => self.is_sparql_directive(),
+            _ => false,
+        };
+        if !complete {
+            if self.buffer.len()>MAX_STATEMENT_SIZE {
+                self.buffer.clear();
+                self.long_string = None;
+                return Err(WDQSErr::ParserError("Turtle: Statement does not end".into()));
+            }
+            return Ok(vec![]);
+        }
+        self.parse_buffer()
+    }
+
+    /// Returns the triples of a last statement without line break; fails if the document ends within a statement
+    pub fn finish(&mut self) -> Result<Vec<TermTriple>,WDQSErr> {
+        self.long_string = None;
+        self.parse_buffer()
+    }
+
+    fn parse_buffer(&mut self) -> Result<Vec<TermTriple>,WDQSErr> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let mut triples = vec![];
+        match self.statements(&buffer, &mut triples) {
+            Ok(_) => Ok(triples),
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                let at: String = e.errors.first().map(|(input,_)|input.chars().take(40).collect()).unwrap_or_default();
+                Err(WDQSErr::ParserError(format!("Turtle: Syntax error at '{at}'")))
+            }
+            Err(e) => Err(WDQSErr::ParserError(e.to_string())),
+        }
+    }
+
+    /// Follows multi-line literals through the line; returns the last character outside of literals and comments
+    fn scan_line(&mut self, line: &str) -> Option<char> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut last = None;
+        let mut pos = 0;
+        while pos<chars.len() {
+            let c = chars[pos];
+            if let Some(quote) = self.long_string {
+                if c=='\\' {
+                    pos += 2;
+                } else if chars[pos..].starts_with(&[quote,quote,quote]) {
+                    self.long_string = None;
+                    last = Some(quote);
+                    pos += 3;
+                } else {
+                    pos += 1;
+                }
+                continue;
+            }
+            match c {
+                '#' => break,
+                '\\' => pos += 1, // Escape in a local name
+                '<' => while pos+1<chars.len() && chars[pos]!='>' { pos += 1 },
+                '"' | '\'' if chars[pos..].starts_with(&[c,c,c]) => {
+                    self.long_string = Some(c);
+                    pos += 2;
+                }
+                '"' | '\'' => {
+                    pos += 1;
+                    while pos<chars.len() && chars[pos]!=c {
+                        if chars[pos]=='\\' {
+                            pos += 1;
+                        }
+                        pos += 1;
+                    }
+                }
+                c if c.is_whitespace() => {
+                    pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            last = Some(if c=='\\' { c } else { chars[pos.min(chars.len()-1)] });
+            pos += 1;
+        }
+        last
+    }
+
+    /// Whether the buffer starts with a SPARQL-style `PREFIX` or `BASE`, which has no final '.'
+    fn is_sparql_directive(&self) -> bool {
+        match ws(&self.buffer) {
+            Ok((input,_)) => sparql_keyword(input).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn statements<'a>(&mut self, mut input: &'a str, triples: &mut Vec<TermTriple>) -> Res<&'a str, ()> {
+        loop {
+            (input,_) = ws(input)?;
+            if input.is_empty() {
+                return Ok((input,()));
+            }
+            input = match self.directive(input) {
+                Ok((input,_)) => input,
+                Err(e) if input.starts_with('@') => return Err(e),
+                Err(_) => self.triples(input, triples)?.0,
+            };
+        }
+    }
+
+    /// `@prefix`, `@base`, `PREFIX` or `BASE`
+    fn directive<'a>(&mut self, input: &'a str) -> Res<&'a str, ()> {
+        let (input,keyword) = alt((tag("@prefix"),tag("@base"),sparql_keyword))(input)?;
+        let (input,_) = ws(input)?;
+        let (input,name) = match keyword.trim_start_matches('@').eq_ignore_ascii_case("prefix") {
+            true => {
+                let (input,name) = pname_ns(input)?;
+                let (input,_) = ws(input)?;
+                (input,Some(name))
+            }
+            false => (input,None),
+        };
+        let (input,iri) = self.iri_ref(input)?;
+        let input = match keyword.starts_with('@') {
+            true => {
+                let (input,_) = ws(input)?;
+                char('.')(input)?.0
+            }
+            false => input,
+        };
+        match name {
+            Some(name) => { self.prefixes.insert(name.to_string(), iri); }
+            None => self.base = Some(iri),
+        }
+        Ok((input,()))
+    }
+
+    /// subject predicateObjectList '.', or blankNodePropertyList predicateObjectList? '.'
+    fn triples<'a>(&mut self, input: &'a str, triples: &mut Vec<TermTriple>) -> Res<&'a str, ()> {
+        let input = match self.subject(input, triples) {
+            Ok((input,subject)) => {
+                let (input,_) = ws(input)?;
+                self.predicate_object_list(input, &subject, triples)?.0
+            }
+            Err(_) => {
+                let (input,subject) = self.blank_node_property_list(input, triples)?;
+                let (input,_) = ws(input)?;
+                match input.starts_with('.') {
+                    true => input,
+                    false => self.predicate_object_list(input, &subject, triples)?.0,
+                }
+            }
+        };
+        let (input,_) = ws(input)?;
+        let (input,_) = char('.')(input)?;
+        Ok((input,()))
+    }
+
+    fn subject<'a>(&mut self, input: &'a str, triples: &mut Vec<TermTriple>) -> Res<&'a str, Term> {
+        if let Ok((input,iri)) = self.iri(input) {
+            return Ok((input,Term::Iri(iri)));
+        }
+        if let Ok(ret) = self.blank_node(input) {
+            return Ok(ret);
+        }
+        self.collection(input, triples)
+    }
+
+    /// verb objectList (';' (verb objectList)?)*
+    fn predicate_object_list<'a>(&mut self, input: &'a str, subject: &Term, triples: &mut Vec<TermTriple>) -> Res<&'a str, ()> {
+        let (mut input,_) = self.verb_object_list(input, subject, triples)?;
+        loop {
+            let (rest,_) = ws(input)?;
+            let rest = match char::<_,VerboseError<&str>>(';')(rest) {
+                Ok((rest,_)) => ws(rest)?.0,
+                Err(_) => return Ok((input,())),
+            };
+            input = match rest.is_empty() || rest.starts_with(['.',']',';']) {
+                true => rest,
+                false => self.verb_object_list(rest, subject, triples)?.0,
+            };
+        }
+    }
+
+    /// verb object (',' object)*
+    fn verb_object_list<'a>(&mut self, input: &'a str, subject: &Term, triples: &mut Vec<TermTriple>) -> Res<&'a str, ()> {
+        let (input,predicate) = self.verb(input)?;
+        let (mut input,_) = ws(input)?;
+        loop {
+            let (rest,object) = self.object(input, triples)?;
+            triples.push((subject.to_owned(),predicate.to_owned(),object));
+            let (rest,_) = ws(rest)?;
+            input = match char::<_,VerboseError<&str>>(',')(rest) {
+                Ok((rest,_)) => ws(rest)?.0,
+                Err(_) => return Ok((rest,())),
+            };
+        }
+    }
+
+    fn verb<'a>(&self, input: &'a str) -> Res<&'a str, Term> {
+        if let Some(rest) = input.strip_prefix('a') {
+            if !rest.starts_with(|c:char|ntriples::is_pn_chars(c) || ".%\\".contains(c)) {
+                return Ok((rest,Term::Iri(format!("{RDF}type"))));
+            }
+        }
+        let (input,iri) = self.iri(input)?;
+        Ok((input,Term::Iri(iri)))
+    }
+
+    fn object<'a>(&mut self, input: &'a str, triples: &mut Vec<TermTriple>) -> Res<&'a str, Term> {
+        if let Ok((input,iri)) = self.iri(input) {
+            return Ok((input,Term::Iri(iri)));
+        }
+        if let Ok(ret) = self.blank_node(input) {
+            return Ok(ret);
+        }
+        match input.chars().next() {
+            Some('(') => self.collection(input, triples),
+            Some('[') => self.blank_node_property_list(input, triples),
+            _ => self.literal(input),
+        }
+    }
+
+    /// An IRI reference, resolved against the base IRI, or a prefixed name
+    fn iri<'a>(&self, input: &'a str) -> Res<&'a str, String> {
+        if input.starts_with('<') {
+            return self.iri_ref(input);
+        }
+        let (rest,prefix) = pname_ns(input)?;
+        let namespace = match self.prefixes.get(prefix) {
+            Some(namespace) => namespace,
+            None => return ntriples::error(input),
+        };
+        let (rest,local) = opt(pn_local)(rest)?;
+        Ok((rest,format!("{namespace}{}",local.unwrap_or_default())))
+    }
+
+    fn iri_ref<'a>(&self, input: &'a str) -> Res<&'a str, String> {
+        let (rest,iri) = ntriples::iri_ref_relative(input)?;
+        if ntriples::is_absolute_iri(&iri) {
+            return Ok((rest,iri));
+        }
+        let base = self.base.as_ref().and_then(|base|url::Url::parse(base).ok());
+        match base.and_then(|base|base.join(&iri).ok()) {
+            Some(iri) => Ok((rest,iri.to_string())),
+            None => ntriples::error(input),
+        }
+    }
+
+    /// `_:label` or `[]`
+    fn blank_node<'a>(&mut self, input: &'a str) -> Res<&'a str, Term> {
+        if let Ok(ret) = ntriples::blank_node_label(input) {
+            return Ok(ret);
+        }
+        let (input,_) = char('[')(input)?;
+        let (input,_) = ws(input)?;
+        let (input,_) = char(']')(input)?;
+        Ok((input,self.new_blank_node()))
+    }
+
+    fn new_blank_node(&mut self) -> Term {
+        self.blank_nodes += 1;
+        Term::BlankNode(format!("#genid{}",self.blank_nodes))
+    }
+
+    /// `[ predicateObjectList ]`
+    fn blank_node_property_list<'a>(&mut self, input: &'a str, triples: &mut Vec<TermTriple>) -> Res<&'a str, Term> {
+        let (input,_) = char('[')(input)?;
+        let (input,_) = ws(input)?;
+        let node = self.new_blank_node();
+        let (input,_) = self.predicate_object_list(input, &node, triples)?;
+        let (input,_) = ws(input)?;
+        let (input,_) = char(']')(input)?;
+        Ok((input,node))
+    }
+
+    /// `( object* )`, as an rdf:first/rdf:rest list
+    fn collection<'a>(&mut self, input: &'a str, triples: &mut Vec<TermTriple>) -> Res<&'a str, Term> {
+        let (mut input,_) = char('(')(input)?;
+        let mut items = vec![];
+        loop {
+            (input,_) = ws(input)?;
+            if let Some(rest) = input.strip_prefix(')') {
+                input = rest;
+                break;
+            }
+            let (rest,item) = self.object(input, triples)?;
+            items.push(item);
+            input = rest;
+        }
+        let mut list = Term::Iri(format!("{RDF}nil"));
+        for item in items.into_iter().rev() {
+            let node = self.new_blank_node();
+            triples.push((node.to_owned(),Term::Iri(format!("{RDF}first")),item));
+            triples.push((node.to_owned(),Term::Iri(format!("{RDF}rest")),list));
+            list = node;
+        }
+        Ok((input,list))
+    }
+
+    /// A string with language or datatype, a number, or a boolean
+    fn literal<'a>(&self, input: &'a str) -> Res<&'a str, Term> {
+        let typed = |value: &str, datatype: &str| Term::Literal { value: value.to_string(), datatype: Some(format!("{XSD}{datatype}")), language: None };
+        for (re,datatype) in [(&*RE_DOUBLE,"double"),(&*RE_DECIMAL,"decimal"),(&*RE_INTEGER,"integer")] {
+            if let Some(m) = re.find(input) {
+                return Ok((&input[m.end()..],typed(m.as_str(),datatype)));
+            }
+        }
+        for value in ["true","false"] {
+            if let Some(rest) = input.strip_prefix(value) {
+                if !rest.starts_with(|c:char|ntriples::is_pn_chars(c) || ".%\\".contains(c)) {
+                    return Ok((rest,typed(value,"boolean")));
+                }
+            }
+        }
+        let (input,value) = string(input)?;
+        if let Ok((input,language)) = ntriples::lang_tag(input) {
+            return Ok((input,Term::Literal { value, datatype: None, language: Some(language.to_string()) }));
+        }
+        if let Ok((input,_)) = tag::<_,_,VerboseError<&str>>("^^")(input) {
+            let (input,datatype) = self.iri(input)?;
+            return Ok((input,Term::Literal { value, datatype: Some(datatype), language: None }));
+        }
+        Ok((input,Term::Literal { value, datatype: None, language: None }))
+    }
+}
+
+/// Whitespace, including line breaks, and comments
+fn ws(input: &str) -> Res<&str, ()> {
+    let (input,_) = many0(alt((multispace1, recognize(preceded(char('#'), take_while(|c|c!='\n'))))))(input)?;
+    Ok((input,()))
+}
+
+fn sparql_keyword(input: &str) -> Res<&str, &str> {
+    let (input,keyword) = alt((tag_no_case("PREFIX"),tag_no_case("BASE")))(input)?;
+    let (_,_) = multispace1(input)?;
+    Ok((input,keyword))
+}
+
+fn is_pn_chars(c: char) -> bool {
+    c!=':' && ntriples::is_pn_chars(c)
+}
+
+/// The prefix of a prefixed name, without ':'
+fn pname_ns(input: &str) -> Res<&str, &str> {
+    let (input,prefix) = opt(recognize(pair(satisfy(ntriples::is_pn_chars_base), take_while(|c|is_pn_chars(c) || c=='.'))))(input)?;
+    let prefix = prefix.unwrap_or_default();
+    if prefix.ends_with('.') {
+        return ntriples::error(input);
+    }
+    let (input,_) = char(':')(input)?;
+    Ok((input,prefix))
+}
+
+/// The local part of a prefixed name, with `\` escapes removed; percent-encodings are kept, and a trailing '.' ends the statement
+fn pn_local(input: &str) -> Res<&str, String> {
+    let mut local = String::new();
+    let mut rest = input;
+    let mut ret = None; // Without trailing '.'
+    while let Some(c) = rest.chars().next() {
+        let first = rest.len()==input.len();
+        let (piece,len) = match c {
+            '\\' => match rest[1..].chars().next() {
+                Some(e) if "_~.-!$&'()*+,;=/?#@%".contains(e) => (e.to_string(),1+e.len_utf8()),
+                _ => break,
+            },
+            '%' if rest.len()>=3 && rest.as_bytes()[1..3].iter().all(|b|b.is_ascii_hexdigit()) => (rest[..3].to_string(),3),
+            '.' if !first => (c.to_string(),1),
+            c if ntriples::is_pn_chars(c) && !(first && (c=='-' || c=='\u{B7}' || ('\u{300}'..='\u{36F}').contains(&c) || ('\u{203F}'..='\u{2040}').contains(&c))) => (c.to_string(),c.len_utf8()),
+            _ => break,
+        };
+        local += &piece;
+        rest = &rest[len..];
+        if c!='.' {
+            ret = Some((rest,local.len()));
+        }
+    }
+    match ret {
+        Some((rest,len)) => Ok((rest,local[..len].to_string())),
+        None => ntriples::error(input),
+    }
+}
+
+/// Any of the four string forms, with escapes decoded
+fn string(input: &str) -> Res<&str, String> {
+    let quote = match input.chars().next() {
+        Some(c) if c=='"' || c=='\'' => c,
+        _ => return ntriples::error(input),
+    };
+    let long = quote.to_string().repeat(3);
+    let multi_line = input.starts_with(&long);
+    let delimiter = if multi_line { long.as_str() } else { &input[..1] };
+    let mut rest = &input[delimiter.len()..];
+    let mut value = String::new();
+    loop {
+        if let Some(after) = rest.strip_prefix(delimiter) {
+            // In long strings, quotes right before the end belong to the value
+            if !(multi_line && after.starts_with(quote)) {
+                return Ok((after,value));
+            }
+        }
+        match rest.chars().next() {
+            None => return ntriples::error(rest),
+            Some('\\') => {
+                let (after,c) = alt((ntriples::uchar,ntriples::echar))(rest)?;
+                value.push(c);
+                rest = after;
+            }
+            Some('\n') | Some('\r') if !multi_line => return ntriples::error(rest),
+            Some(c) => {
+                value.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(document: &str) -> Result<Vec<TermTriple>,WDQSErr> {
+        let mut reader = TurtleReader::default();
+        let mut ret = vec![];
+        for line in document.lines() {
+            ret.append(&mut reader.add_line(line)?);
+        }
+        ret.append(&mut reader.finish()?);
+        Ok(ret)
+    }
+
+    fn iri(s: &str) -> Term {
+        Term::Iri(s.to_string())
+    }
+
+    fn literal(value: &str, datatype: Option<&str>, language: Option<&str>) -> Term {
+        Term::Literal { value: value.to_string(), datatype: datatype.map(|s|s.to_string()), language: language.map(|s|s.to_string()) }
+    }
+
+    #[test]
+    fn test_wikidata_dump() {
+        let document = r#"@prefix rdf: <http://www.w3.org/1999/02/22-rdf-syntax-ns#> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+@prefix wd: <http://www.wikidata.org/entity/> .
+@prefix wdt: <http://www.wikidata.org/prop/direct/> .
+@prefix schema: <http://schema.org/> .
+PREFIX rdfs: <http://www.w3.org/2000/01/rdf-schema#>
+
+# Douglas Adams
+wd:Q42 a wikibase:Item ;
+	rdfs:label "Douglas Adams"@en, 'Douglas Adams'@de ;
+	schema:description """English writer
+and "humorist\""""@en ;
+	wdt:P1082 1234, -5.0, 1e3 ;
+	wdt:P31 wd:Q5 .
+"#.replace("wikibase:Item","<http://wikiba.se/ontology#Item>");
+        let triples = read(&document).unwrap();
+        let q42 = iri("http://www.wikidata.org/entity/Q42");
+        assert_eq!(triples.len(),8);
+        assert_eq!(triples[0],(q42.to_owned(),iri("http://www.w3.org/1999/02/22-rdf-syntax-ns#type"),iri("http://wikiba.se/ontology#Item")));
+        assert_eq!(triples[2].2,literal("Douglas Adams",None,Some("de")));
+        assert_eq!(triples[3].2,literal("English writer\nand \"humorist\"",None,Some("en")));
+        assert_eq!(triples[5].2,literal("-5.0",Some("http://www.w3.org/2001/XMLSchema#decimal"),None));
+        assert_eq!(triples[6].2,literal("1e3",Some("http://www.w3.org/2001/XMLSchema#double"),None));
+        assert_eq!(triples[7],(q42,iri("http://www.wikidata.org/prop/direct/P31"),iri("http://www.wikidata.org/entity/Q5")));
+    }
+
+    #[test]
+    fn test_syntax() {
+        let triples = read("@base <http://example/base/> . @prefix : <http://example/> .\n<s> :p\\.q :o.\n:s :p [ :q :r ], (1 _:b) .").unwrap();
+        assert_eq!(triples[0],(iri("http://example/base/s"),iri("http://example/p.q"),iri("http://example/o")));
+        assert_eq!(triples[1],(Term::BlankNode("#genid1".into()),iri("http://example/q"),iri("http://example/r")));
+        assert_eq!(triples[2].2,Term::BlankNode("#genid1".into()));
+        assert_eq!(triples.len(),8); // Including two list nodes
+        let triples = read("@prefix : <http://example/> .\n_:genid1 :p [] .").unwrap();
+        assert_eq!(triples[0],(Term::BlankNode("genid1".into()),iri("http://example/p"),Term::BlankNode("#genid1".into())));
+        assert!(read(":s :p :o .").is_err()); // Undefined prefix
+        assert!(read("@prefix : <http://example/> .\n:s :p \"unterminated .").is_err());
+        assert!(read("@prefix : <http://example/> .\n:s :p :o").is_err());
+    }
+}
@@ -6,6 +6,7 @@ pub enum TypePart {
     ShortText,
     Int,
     Float,
+    Decimal,
     Point,
     UUID40,
     UUID32,
@@ -13,6 +14,7 @@ pub enum TypePart {
     U16,
     I32,
     U32,
+    I64,
     U8,
     Blank,
 }
@@ -24,6 +26,7 @@ impl TypePart {
             TypePart::ShortText => Some("VARCHAR(64) CHARACTER SET ascii COLLATE ascii_general_ci NOT NULL"),
             TypePart::Int => Some("INT(11) UNSIGNED NOT NULL"),
             TypePart::Float => Some("DOUBLE(11,4) UNSIGNED NOT NULL"),
+            TypePart::Decimal => Some("DECIMAL(65,30) NOT NULL"),
             TypePart::Point => Some("POINT NOT NULL"),
             TypePart::UUID40 => Some("BINARY(20)"),
             TypePart::UUID32 => Some("BINARY(16)"),
@@ -31,6 +34,7 @@ impl TypePart {
             TypePart::U16 => Some("SMALLINT(6) UNSIGNED NOT NULL"),
             TypePart::I32 => Some("INT(6) SIGNED NOT NULL"),
             TypePart::U32 => Some("INT(6) UNSIGNED NOT NULL"),
+            TypePart::I64 => Some("BIGINT(20) SIGNED NOT NULL"),
             TypePart::U8 => Some("TINYINT(3) UNSIGNED NOT NULL"),
             TypePart::Blank => None,
         }
@@ -60,7 +60,7 @@ impl ElementType for UUID40 {
     }
 
     fn to_url(&self) -> String  {
-        self.to_string() // TODO CHECKME FIXME
+        format!("http://www.wikidata.org/reference/{}",self.uuid)
     }
 
     fn sql_var_from_name(_name: &str, prefix: &str) -> Option<Vec<String>>  {
@@ -85,6 +85,14 @@ impl From<String> for UUID32 {
     }
 }
 
+impl UUID32 {
+    /// Upper case 8-4-4-4-12 form, as used in statement IDs
+    pub fn to_dashed(&self) -> String {
+        let u = self.uuid.to_ascii_uppercase();
+        format!("{}-{}-{}-{}-{}",&u[0..8],&u[8..12],&u[12..16],&u[16..20],&u[20..32])
+    }
+}
+
 impl ElementType for UUID32 {
     fn from_str(s: &str) -> Option<Box<Self>> {
         if RE_UUID32_NO_DASHES.is_match(&s.replace('-',"")) {
@@ -124,7 +132,7 @@ impl ElementType for UUID32 {
     }
 
     fn to_url(&self) -> String  {
-        self.to_string() // TODO CHECKME FIXME
+        format!("http://www.wikidata.org/value/{}",self.uuid)
     }
 
     fn sql_var_from_name(_name: &str, prefix: &str) -> Option<Vec<String>>  {
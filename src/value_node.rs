@@ -0,0 +1,93 @@
+use crate::{element::Element, element_type::ElementType, decimal::Decimal, entity::Entity, quantity::{Quantity, QUANTITY_PREDICATE, UNIT_ONE_Q}};
+
+pub type Triple = (Element,Element,Element);
+
+/// Collects the triples of one `wdv:` value node, which are consecutive in dumps,
+/// so that details like the time precision can be stored with the value itself
+#[derive(Debug, Default)]
+pub struct ValueNode {
+    subject: Option<String>,
+    triples: Vec<Triple>,
+}
+
+impl ValueNode {
+    /// Whether an N-Triples line has a value node as subject
+    pub fn is_value_node_line(line: &str) -> bool {
+        line.starts_with("<http://www.wikidata.org/value/")
+    }
+
+    /// Adds a triple; returns the triples of the previous value node, if this triple starts a new one
+    pub fn add(&mut self, triple: Triple) -> Option<Vec<Triple>> {
+        let subject = triple.0.to_iri();
+        let ret = match self.subject==subject {
+            true => None,
+            false => self.flush(),
+        };
+        self.subject = subject;
+        self.triples.push(triple);
+        ret
+    }
+
+    /// The collected triples, with precision, timezone and calendar model set on the wikibase:timeValue,
+    /// and the quantity amount, bounds and unit combined into a single quantity
+    pub fn flush(&mut self) -> Option<Vec<Triple>> {
+        self.subject = None;
+        let mut triples = std::mem::take(&mut self.triples);
+        if triples.is_empty() {
+            return None;
+        }
+        let object = |name: &str| triples.iter()
+            .find(|(_,p,_)|matches!(p,Element::WikibaseOntology(s) if s==name))
+            .map(|(_,_,o)|o.to_owned());
+        let precision = match object("timePrecision") {
+            Some(Element::Int(i)) => u8::try_from(i).ok(),
+            _ => None,
+        };
+        let timezone = match object("timeTimezone") {
+            Some(Element::Int(i)) => i16::try_from(i).ok(),
+            _ => None,
+        };
+        let calendar_q = match object("timeCalendarModel") {
+            Some(Element::Entity(Entity::Item(q))) => Some(q),
+            _ => None,
+        };
+        let number = |name: &str| match object(name) {
+            Some(Element::Decimal(d)) => Some(d),
+            Some(Element::Int(i)) => Decimal::from_str(&i.to_string()).map(|d|*d),
+            _ => None,
+        };
+        let quantity = number("quantityAmount").map(|amount| {
+            let bounds = match (number("quantityLowerBound"),number("quantityUpperBound")) {
+                (Some(lower),Some(upper)) => Some((lower,upper)),
+                _ => None,
+            };
+            let unit_q = match object("quantityUnit") {
+                Some(Element::Entity(Entity::Item(q))) => q,
+                _ => UNIT_ONE_Q,
+            };
+            Quantity::new(amount, bounds, unit_q)
+        });
+        if let Some(quantity) = quantity {
+            let subject = triples[0].0.to_owned();
+            triples.retain(|(_,p,_)|!matches!(p,Element::WikibaseOntology(s) if Quantity::component(s).is_some()));
+            triples.push((subject,Element::WikibaseOntology(QUANTITY_PREDICATE.into()),Element::Quantity(quantity)));
+        }
+        for (_,p,o) in triples.iter_mut() {
+            match (&*p,o) {
+                (Element::WikibaseOntology(name),Element::DateTime(dt)) if name=="timeValue" => {
+                    if let Some(precision) = precision {
+                        dt.set_precision(precision);
+                    }
+                    if let Some(timezone) = timezone {
+                        dt.set_timezone(timezone);
+                    }
+                    if let Some(calendar_q) = calendar_q {
+                        dt.set_calendar_q(calendar_q);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(triples)
+    }
+}